@@ -0,0 +1,58 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use twitch_irc::message::{IRCMessage, IRCMessageRef, ServerMessage};
+
+const PRIVMSG: &str = "@badge-info=subscriber/22;badges=moderator/1,subscriber/12,glhf-pledge/1;client-nonce=e8bd7b3e5ed36e7e2ac3e6b8e0d6a8b1;color=#19E6E6;display-name=randers;emotes=25:0-4,12-16/1902:6-10;first-msg=0;flags=;id=6e2ccb1f-01ed-44d0-85b6-edf762524475;mod=1;returning-chatter=0;room-id=11148817;subscriber=1;tmi-sent-ts=1577040814959;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Kappa Keepo Kappa";
+const USERNOTICE: &str = "@badge-info=subscriber/0;badges=subscriber/0,premium/1;color=;display-name=fallenseraphhh;emotes=;flags=;id=2a9bea11-a80a-49a0-a498-1642d457f775;login=fallenseraphhh;mod=0;msg-id=sub;msg-param-cumulative-months=1;msg-param-months=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=Channel\\sSubscription\\s(xqcow);msg-param-sub-plan=Prime;room-id=71092938;subscriber=1;system-msg=fallenseraphhh\\ssubscribed\\swith\\sTwitch\\sPrime.;tmi-sent-ts=1582685713242;user-id=224005980;user-type= :tmi.twitch.tv USERNOTICE #xqcow";
+const PING: &str = "PING :tmi.twitch.tv";
+
+fn parse(c: &mut Criterion) {
+    for (name, source) in [
+        ("privmsg", PRIVMSG),
+        ("usernotice", USERNOTICE),
+        ("ping", PING),
+    ] {
+        let mut group = c.benchmark_group(name);
+        group.bench_function("IRCMessage::parse", |b| {
+            b.iter(|| IRCMessage::parse(black_box(source)).unwrap());
+        });
+        group.bench_function("IRCMessageRef::parse", |b| {
+            b.iter(|| IRCMessageRef::parse(black_box(source)).unwrap());
+        });
+        group.bench_function("IRCMessageRef::parse + tag lookup", |b| {
+            b.iter(|| {
+                let message = IRCMessageRef::parse(black_box(source)).unwrap();
+                message.tags.get("display-name").map(|name| name.len())
+            });
+        });
+        group.bench_function("IRCMessageRef::parse + into_owned", |b| {
+            b.iter(|| {
+                IRCMessageRef::parse(black_box(source))
+                    .unwrap()
+                    .into_owned()
+            });
+        });
+        group.bench_function("ServerMessage::try_from", |b| {
+            b.iter(|| {
+                ServerMessage::try_from(IRCMessage::parse(black_box(source)).unwrap()).unwrap()
+            });
+        });
+        let server_message = ServerMessage::try_from(IRCMessage::parse(source).unwrap()).unwrap();
+        group.bench_function("ServerMessage::clone", |b| {
+            b.iter(|| black_box(&server_message).clone());
+        });
+        // incoming messages are moved a number of times on their way to the receiver, e.g.
+        // through the channels between the background tasks
+        group.bench_function("ServerMessage move", |b| {
+            b.iter_batched(
+                || server_message.clone(),
+                |message| vec![black_box(message)],
+                BatchSize::SmallInput,
+            );
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
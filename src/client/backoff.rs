@@ -0,0 +1,165 @@
+use crate::config::ReconnectBackoff;
+use tokio::time::{Duration, Instant};
+
+/// Tracks consecutive connection failures, and decides how long new connections have to wait
+/// before connecting.
+pub(crate) struct Backoff {
+    config: ReconnectBackoff,
+    consecutive_failures: u32,
+    /// when a connection first successfully logged in after the last failure.
+    healthy_since: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(config: ReconnectBackoff) -> Backoff {
+        Backoff {
+            config,
+            consecutive_failures: 0,
+            healthy_since: None,
+        }
+    }
+
+    /// Backoff applied after Twitch rejected the login credentials, regardless of the
+    /// configured reconnect backoff. It is reset as soon as a login succeeds.
+    pub fn for_login_failures() -> Backoff {
+        Backoff::new(ReconnectBackoff {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(60),
+            jitter: 0.0,
+            reset_after: Duration::ZERO,
+        })
+    }
+
+    /// Record that a connection successfully logged in.
+    pub fn register_healthy(&mut self, now: Instant) {
+        self.healthy_since.get_or_insert(now);
+    }
+
+    /// Record that a connection failed. `failed_connection_healthy_since` is when the failed
+    /// connection had successfully logged in, if it did.
+    pub fn register_failure(
+        &mut self,
+        now: Instant,
+        failed_connection_healthy_since: Option<Instant>,
+    ) {
+        self.reset_if_healthy(now);
+        if failed_connection_healthy_since
+            .is_some_and(|t| now.saturating_duration_since(t) >= self.config.reset_after)
+        {
+            self.consecutive_failures = 0;
+        }
+
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        self.healthy_since = None;
+    }
+
+    fn reset_if_healthy(&mut self, now: Instant) {
+        if self
+            .healthy_since
+            .is_some_and(|t| now.saturating_duration_since(t) >= self.config.reset_after)
+        {
+            self.consecutive_failures = 0;
+        }
+    }
+
+    /// The delay new connections currently have to wait for, before jitter is applied.
+    pub fn current_delay(&mut self, now: Instant) -> Duration {
+        self.reset_if_healthy(now);
+        if self.consecutive_failures == 0 {
+            return Duration::ZERO;
+        }
+
+        let exponent = i32::try_from(self.consecutive_failures - 1).unwrap_or(i32::MAX);
+        let delay = self.config.initial_delay.as_secs_f64() * self.config.multiplier.powi(exponent);
+        // fails for overflowing (or otherwise nonsensical) values
+        Duration::try_from_secs_f64(delay)
+            .unwrap_or(self.config.max_delay)
+            .min(self.config.max_delay)
+    }
+
+    /// The delay a connection made now has to wait for, with jitter applied.
+    pub fn next_delay(&mut self, now: Instant) -> Duration {
+        let delay = self.current_delay(now);
+        let jitter = self.config.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (fastrand::f64() * 2.0 - 1.0);
+        Duration::try_from_secs_f64(delay.as_secs_f64() * factor).unwrap_or(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::backoff::Backoff;
+    use crate::config::ReconnectBackoff;
+    use tokio::time::{Duration, Instant};
+
+    fn backoff() -> Backoff {
+        Backoff::new(ReconnectBackoff {
+            jitter: 0.0,
+            ..ReconnectBackoff::default()
+        })
+    }
+
+    #[test]
+    fn test_exponential_growth() {
+        let mut backoff = backoff();
+        let now = Instant::now();
+
+        assert_eq!(backoff.next_delay(now), Duration::ZERO);
+        let mut delays = vec![];
+        for _ in 0..10 {
+            backoff.register_failure(now, None);
+            delays.push(backoff.next_delay(now).as_secs());
+        }
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60, 60, 60]);
+
+        for _ in 0..1000 {
+            backoff.register_failure(now, None);
+        }
+        assert_eq!(backoff.next_delay(now), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_reset_after_healthy() {
+        let mut backoff = backoff();
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            backoff.register_failure(start, None);
+        }
+        backoff.register_healthy(start + Duration::from_secs(1));
+        assert_eq!(
+            backoff.current_delay(start + Duration::from_secs(60)),
+            Duration::from_secs(4)
+        );
+        assert_eq!(
+            backoff.current_delay(start + Duration::from_secs(61)),
+            Duration::ZERO
+        );
+
+        // a connection that failed after being healthy for long enough is the first failure
+        for _ in 0..3 {
+            backoff.register_failure(start, None);
+        }
+        backoff.register_failure(start + Duration::from_secs(60), Some(start));
+        assert_eq!(
+            backoff.current_delay(start + Duration::from_secs(60)),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut backoff = Backoff::new(ReconnectBackoff::default());
+        let now = Instant::now();
+        for _ in 0..4 {
+            backoff.register_failure(now, None);
+        }
+
+        for _ in 0..100 {
+            let delay = backoff.next_delay(now);
+            assert!(delay >= Duration::from_millis(6400));
+            assert!(delay <= Duration::from_millis(9600));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::message::{FollowersOnlyMode, NoticeId, RoomStateMessage, UserStateMessage};
+use std::time::{Duration, Instant};
+
+/// The status of a channel, as returned by
+/// [`TwitchIRCClient::get_channel_status`](crate::TwitchIRCClient::get_channel_status) and
+/// [`TwitchIRCClient::list_channels`](crate::TwitchIRCClient::list_channels).
+///
+/// Note that any combination of `wanted` and `joined` is possible.
+///
+/// For example, `wanted` without `joined` could indicate that the `JOIN` message to join this
+/// channel is currently being sent or already sent, but no response confirming the `JOIN` has been
+/// received yet. **Note this status can also mean that the server did not answer the `JOIN` request
+/// because the channel did not exist/was suspended or similar conditions.**
+///
+/// `joined` without `wanted` might on the other hand (similarly) mean that a `PART` message is sent
+/// but not answered yet by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ChannelStatus {
+    /// Login name of the channel.
+    pub channel_login: String,
+    /// Whether the channel is wanted. This is true if the last operation for this channel was a
+    /// `join()`, or alternatively whether it was included in the set of channels in a
+    /// `set_wanted_channels` call.
+    pub wanted: bool,
+    /// Whether the channel is currently joined server-side. (This is purely based on `JOIN` and
+    /// `PART` messages being received from the server).
+    pub joined: bool,
+    /// ID of the pool connection the channel is joined (or being joined) on, the same ID
+    /// [`ClientEvent`](crate::ClientEvent)s refer to.
+    pub connection_id: Option<usize>,
+    /// When the channel was first requested to be joined. This is kept when the channel is
+    /// rejoined after a connection failed.
+    pub join_requested_at: Option<Instant>,
+    /// When the server last confirmed the `JOIN`. `None` while the channel is not joined.
+    pub joined_at: Option<Instant>,
+    /// How many `JOIN`s were sent for this channel, including retries and rejoins after a
+    /// connection failed.
+    pub join_attempts: u32,
+    /// Why the last attempt to join the channel failed, if any attempt failed.
+    pub last_failure: Option<JoinFailure>,
+    /// The latest `ROOMSTATE` received for the channel. Note that `ROOMSTATE`s sent because a
+    /// setting changed only contain that setting.
+    pub room_state: Option<RoomStateMessage>,
+}
+
+/// Why joining a channel failed, see [`ChannelStatus::last_failure`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JoinFailure {
+    /// The server did not confirm the `JOIN` within the
+    /// [configured timeout](crate::ClientConfig::join_timeout).
+    TimedOut,
+    /// Twitch refused the `JOIN` with a `NOTICE`.
+    Rejected(NoticeId),
+    /// The connection the channel was joined on was closed. Contains the description of the
+    /// error that caused it.
+    ConnectionClosed(String),
+}
+
+/// The settings of a channel, merged from all `ROOMSTATE`s received for it, as returned by
+/// [`TwitchIRCClient::room_state`](crate::TwitchIRCClient::room_state).
+///
+/// A `ROOMSTATE` sent because a setting changed only contains that setting, so a setting is
+/// `None` only if no `ROOMSTATE` specifying it was received yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RoomState {
+    /// Login name of the channel.
+    pub channel_login: String,
+    /// ID of the channel.
+    pub channel_id: String,
+    /// Whether emote-only mode is enabled.
+    pub emote_only: Option<bool>,
+    /// The followers-only mode the channel is in.
+    pub followers_only: Option<FollowersOnlyMode>,
+    /// Whether r9k (unique-chat) mode is enabled.
+    pub r9k: Option<bool>,
+    /// The slow-mode setting, a duration of 0 seconds means slow mode is disabled.
+    pub slow_mode: Option<Duration>,
+    /// Whether subscribers-only mode is enabled.
+    pub subscribers_only: Option<bool>,
+}
+
+impl RoomState {
+    /// Update the settings that are specified in the given `ROOMSTATE`.
+    pub(crate) fn update(&mut self, room_state: &RoomStateMessage) {
+        self.channel_id.clone_from(&room_state.channel_id);
+        if room_state.emote_only.is_some() {
+            self.emote_only = room_state.emote_only;
+        }
+        if room_state.followers_only.is_some() {
+            self.followers_only.clone_from(&room_state.followers_only);
+        }
+        if room_state.r9k.is_some() {
+            self.r9k = room_state.r9k;
+        }
+        if room_state.slow_mode.is_some() {
+            self.slow_mode = room_state.slow_mode;
+        }
+        if room_state.subscribers_only.is_some() {
+            self.subscribers_only = room_state.subscribers_only;
+        }
+    }
+}
+
+impl From<&RoomStateMessage> for RoomState {
+    fn from(room_state: &RoomStateMessage) -> RoomState {
+        RoomState {
+            channel_login: room_state.channel_login.clone(),
+            channel_id: room_state.channel_id.clone(),
+            emote_only: room_state.emote_only,
+            followers_only: room_state.followers_only.clone(),
+            r9k: room_state.r9k,
+            slow_mode: room_state.slow_mode,
+            subscribers_only: room_state.subscribers_only,
+        }
+    }
+}
+
+/// What the client tracks about a wanted channel, in addition to the channel sets of the
+/// pool connections. This is kept when the channel moves to another connection.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelInfo {
+    pub join_requested_at: Option<tokio::time::Instant>,
+    pub joined_at: Option<tokio::time::Instant>,
+    pub join_attempts: u32,
+    pub last_failure: Option<JoinFailure>,
+    pub room_state: Option<RoomStateMessage>,
+    /// all `ROOMSTATE`s received for the channel, merged.
+    pub merged_room_state: Option<RoomState>,
+    pub user_state: Option<UserStateMessage>,
+}
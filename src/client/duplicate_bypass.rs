@@ -0,0 +1,186 @@
+use crate::message::IRCMessage;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+/// Twitch refuses messages identical to the previous message sent to the same channel,
+/// if the previous message was sent less than this long ago.
+const DUPLICATE_WINDOW: Duration = Duration::from_secs(30);
+
+/// Appended to a message to make it differ from the previous message. `U+E0000` is an
+/// unassigned tag character that is not rendered by Twitch chat.
+pub(crate) const BYPASS_SUFFIX: &str = " \u{E0000}";
+
+/// Modifies outgoing `PRIVMSG`s that would be refused by Twitch for being identical to the
+/// previous message in the same channel.
+#[derive(Default)]
+pub(crate) struct DuplicateMessageBypass {
+    /// the last message text (as it was sent out) and when it was sent, by channel.
+    /// Entries older than `DUPLICATE_WINDOW` are removed.
+    last_messages: HashMap<String, (String, Instant)>,
+}
+
+/// Information to take back the recording of a sent message, in case Twitch does not
+/// accept it. See [`DuplicateMessageBypass::undo`].
+#[derive(Debug)]
+pub(crate) struct SentMessageRecord {
+    channel_login: String,
+    text: String,
+    previous: Option<(String, Instant)>,
+}
+
+impl DuplicateMessageBypass {
+    /// Appends the bypass suffix to the text of the given `PRIVMSG` if the same text was the
+    /// last message sent to that channel. Must be called for every `PRIVMSG` right before it
+    /// is sent out.
+    ///
+    /// Since the text is compared with the text as it was actually sent, repeating the same
+    /// message alternates between the text with and without the suffix.
+    ///
+    /// The returned record can be used to [`undo`](DuplicateMessageBypass::undo) this if the
+    /// message turns out not to be accepted by Twitch.
+    pub fn process(&mut self, message: &mut IRCMessage, now: Instant) -> Option<SentMessageRecord> {
+        self.last_messages
+            .retain(|_, (_, sent_at)| now < *sent_at + DUPLICATE_WINDOW);
+
+        let [channel, text] = message.params.as_mut_slice() else {
+            return None;
+        };
+        let channel_login = channel.trim_start_matches('#');
+
+        if let Some((last_text, _)) = self.last_messages.get(channel_login)
+            && last_text.as_str() == text.as_str()
+        {
+            text.push_str(BYPASS_SUFFIX);
+        }
+
+        let previous = self
+            .last_messages
+            .insert(channel_login.to_owned(), (text.clone(), now));
+        Some(SentMessageRecord {
+            channel_login: channel_login.to_owned(),
+            text: text.clone(),
+            previous,
+        })
+    }
+
+    /// Called when a processed message was not sent or was rejected by Twitch. Unless another
+    /// message was sent to the channel since, the previous message is the last one again.
+    pub fn undo(&mut self, record: SentMessageRecord) {
+        let is_last_message = self
+            .last_messages
+            .get(&record.channel_login)
+            .is_some_and(|(last_text, _)| *last_text == record.text);
+        if !is_last_message {
+            return;
+        }
+        match record.previous {
+            Some(previous) => {
+                self.last_messages.insert(record.channel_login, previous);
+            }
+            None => {
+                self.last_messages.remove(&record.channel_login);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::duplicate_bypass::DuplicateMessageBypass;
+    use crate::irc;
+    use crate::message::IRCMessage;
+    use tokio::time::{Duration, Instant};
+
+    fn process(
+        bypass: &mut DuplicateMessageBypass,
+        channel: &str,
+        text: &str,
+        now: Instant,
+    ) -> String {
+        let mut message = irc!["PRIVMSG", format!("#{channel}"), text];
+        bypass.process(&mut message, now);
+        message.params[1].clone()
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut bypass = DuplicateMessageBypass::default();
+        let now = Instant::now();
+
+        assert_eq!(process(&mut bypass, "pajlada", ". hi", now), ". hi");
+        let mut message = irc!["PRIVMSG", "#pajlada", ". hi"];
+        let record = bypass.process(&mut message, now).unwrap();
+        assert_eq!(message.params[1], ". hi \u{E0000}");
+        // the message with the suffix was rejected, so ". hi" is still the last message
+        bypass.undo(record);
+        assert_eq!(
+            process(&mut bypass, "pajlada", ". hi", now),
+            ". hi \u{E0000}"
+        );
+
+        let mut message = irc!["PRIVMSG", "#forsen", ". hi"];
+        let record = bypass.process(&mut message, now).unwrap();
+        bypass.undo(record);
+        assert!(!bypass.last_messages.contains_key("forsen"));
+    }
+
+    #[test]
+    fn test_prunes_old_messages() {
+        let mut bypass = DuplicateMessageBypass::default();
+        let now = Instant::now();
+
+        process(&mut bypass, "pajlada", ". hi", now);
+        process(&mut bypass, "forsen", ". hi", now + Duration::from_secs(20));
+        process(
+            &mut bypass,
+            "randers",
+            ". hi",
+            now + Duration::from_secs(40),
+        );
+        let mut channels = bypass.last_messages.keys().collect::<Vec<_>>();
+        channels.sort();
+        assert_eq!(channels, vec!["forsen", "randers"]);
+    }
+
+    #[test]
+    fn test_alternates_suffix() {
+        let mut bypass = DuplicateMessageBypass::default();
+        let now = Instant::now();
+
+        assert_eq!(process(&mut bypass, "pajlada", ". hi", now), ". hi");
+        assert_eq!(
+            process(&mut bypass, "pajlada", ". hi", now),
+            ". hi \u{E0000}"
+        );
+        assert_eq!(process(&mut bypass, "pajlada", ". hi", now), ". hi");
+        // other channels are tracked separately
+        assert_eq!(process(&mut bypass, "forsen", ". hi", now), ". hi");
+        assert_eq!(process(&mut bypass, "pajlada", ". hello", now), ". hello");
+        assert_eq!(process(&mut bypass, "pajlada", ". hi", now), ". hi");
+    }
+
+    #[test]
+    fn test_expires() {
+        let mut bypass = DuplicateMessageBypass::default();
+        let now = Instant::now();
+
+        assert_eq!(process(&mut bypass, "pajlada", ". hi", now), ". hi");
+        assert_eq!(
+            process(
+                &mut bypass,
+                "pajlada",
+                ". hi",
+                now + Duration::from_secs(30)
+            ),
+            ". hi"
+        );
+    }
+
+    #[test]
+    fn test_ignores_malformed_messages() {
+        let mut bypass = DuplicateMessageBypass::default();
+        let mut message: IRCMessage = irc!["PRIVMSG", "#pajlada"];
+        bypass.process(&mut message, Instant::now());
+        assert_eq!(message, irc!["PRIVMSG", "#pajlada"]);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::Error;
+use crate::login::LoginCredentials;
+use crate::transport::Transport;
+
+/// An event about the connections and channels of a [`TwitchIRCClient`](crate::TwitchIRCClient),
+/// as received via [`TwitchIRCClient::subscribe_events`](crate::TwitchIRCClient::subscribe_events).
+///
+/// Connections are identified by an ID that is unique within the client. Events concerning
+/// channels refer to the connection the channel was joined (or parted) on.
+///
+/// This enum is marked as `#[non_exhaustive]` so that more events may be added to it in the
+/// future, without the need for a breaking release.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ClientEvent<T: Transport, L: LoginCredentials> {
+    /// A new connection was established. Logging in and joining channels takes place
+    /// after this.
+    ConnectionOpened {
+        /// ID of the connection.
+        connection_id: usize,
+    },
+    /// A connection was closed because of an error (including a `RECONNECT` requested by
+    /// Twitch), or failed to connect in the first place. The client will automatically rejoin
+    /// the channels of that connection on other connections.
+    ///
+    /// This is also emitted for every connection when the client is
+    /// [shut down](crate::TwitchIRCClient::shutdown), with [`Error::ClientShutDown`] as the
+    /// `cause`. No channels are rejoined in that case.
+    ConnectionClosed {
+        /// ID of the connection.
+        connection_id: usize,
+        /// The reason the connection was closed.
+        cause: Error<T, L>,
+    },
+    /// A connection that was no longer needed was closed by the client, because
+    /// [rebalancing](crate::TwitchIRCClient::rebalance) moved all its channels to other
+    /// connections, or because it was [idle](crate::ClientConfig::idle_connection_timeout).
+    ConnectionRetired {
+        /// ID of the connection.
+        connection_id: usize,
+    },
+    /// The server confirmed that a channel was joined.
+    ChannelJoined {
+        /// ID of the connection the channel was joined on.
+        connection_id: usize,
+        /// Login name of the channel.
+        channel_login: String,
+    },
+    /// The server did not confirm a `JOIN` within the
+    /// [configured timeout](crate::ClientConfig::join_timeout). This usually means that the
+    /// channel does not exist or is suspended.
+    ChannelJoinTimedOut {
+        /// ID of the connection the `JOIN` was sent on.
+        connection_id: usize,
+        /// Login name of the channel.
+        channel_login: String,
+    },
+    /// The server confirmed that a channel was parted.
+    ChannelParted {
+        /// ID of the connection the channel was parted on.
+        connection_id: usize,
+        /// Login name of the channel.
+        channel_login: String,
+    },
+}
+
+impl<T: Transport, L: LoginCredentials> Clone for ClientEvent<T, L> {
+    fn clone(&self) -> Self {
+        match self {
+            ClientEvent::ConnectionOpened { connection_id } => ClientEvent::ConnectionOpened {
+                connection_id: *connection_id,
+            },
+            ClientEvent::ConnectionClosed {
+                connection_id,
+                cause,
+            } => ClientEvent::ConnectionClosed {
+                connection_id: *connection_id,
+                cause: cause.clone(),
+            },
+            ClientEvent::ConnectionRetired { connection_id } => ClientEvent::ConnectionRetired {
+                connection_id: *connection_id,
+            },
+            ClientEvent::ChannelJoined {
+                connection_id,
+                channel_login,
+            } => ClientEvent::ChannelJoined {
+                connection_id: *connection_id,
+                channel_login: channel_login.clone(),
+            },
+            ClientEvent::ChannelJoinTimedOut {
+                connection_id,
+                channel_login,
+            } => ClientEvent::ChannelJoinTimedOut {
+                connection_id: *connection_id,
+                channel_login: channel_login.clone(),
+            },
+            ClientEvent::ChannelParted {
+                connection_id,
+                channel_login,
+            } => ClientEvent::ChannelParted {
+                connection_id: *connection_id,
+                channel_login: channel_login.clone(),
+            },
+        }
+    }
+}
@@ -1,22 +1,40 @@
-use crate::client::pool_connection::PoolConnection;
-#[cfg(feature = "metrics-collection")]
-use crate::client::pool_connection::ReportedConnectionState;
+use crate::client::MessagePriority;
+use crate::client::backoff::Backoff;
+use crate::client::channel_status::{ChannelInfo, ChannelStatus, JoinFailure, RoomState};
+use crate::client::duplicate_bypass::{DuplicateMessageBypass, SentMessageRecord};
+use crate::client::event::ClientEvent;
+use crate::client::incoming::IncomingMessagesSender;
+use crate::client::pool_connection::{
+    ChannelCommand, ConfirmationSender, PendingConfirmation, PoolConnection,
+};
+use crate::client::pool_snapshot::{ConnectionSnapshot, PoolConnectionState};
+use crate::client::rate_limiter::PrivmsgRateLimiter;
+use crate::client::subscription::Subscription;
 use crate::config::ClientConfig;
-use crate::connection::event_loop::ConnectionLoopCommand;
+use crate::connection::event_loop::{ConnectionLoopCommand, OutgoingMessage};
 use crate::connection::{Connection, ConnectionIncomingMessage};
-use crate::error::Error;
+use crate::error::{DeliveryError, Error, JoinError, ShutdownTimeout};
 use crate::irc;
 use crate::login::LoginCredentials;
 use crate::message::commands::ServerMessage;
-use crate::message::{IRCMessage, JoinMessage, PartMessage};
+use crate::message::{
+    IRCMessage, JoinMessage, NoticeId, NoticeMessage, PartMessage, UserStateMessage,
+};
 #[cfg(feature = "metrics-collection")]
 use crate::metrics::MetricsBundle;
 use crate::transport::Transport;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Weak};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::{Duration, Instant};
 use tracing::{Instrument, info_span};
 
+/// Used to report back whether a message was sent successfully.
+pub(crate) type SendReturnSender<T, L> = oneshot::Sender<Result<(), Error<T, L>>>;
+
+/// Used to report back whether the server confirmed a `JOIN`.
+pub(crate) type JoinReturnSender = oneshot::Sender<Result<(), JoinError>>;
+
 #[derive(Debug)]
 pub(crate) enum ClientLoopCommand<T: Transport, L: LoginCredentials> {
     Connect {
@@ -24,15 +42,51 @@ pub(crate) enum ClientLoopCommand<T: Transport, L: LoginCredentials> {
     },
     SendMessage {
         message: IRCMessage,
+        priority: MessagePriority,
         return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
     },
+    /// Send a message, and report back whether Twitch accepted it.
+    SendConfirmedMessage {
+        message: IRCMessage,
+        return_sender: SendReturnSender<T, L>,
+        confirmation_sender: ConfirmationSender<T, L>,
+    },
+    /// Send multiple messages in order, all over the same connection.
+    SendMessages {
+        messages: Vec<(IRCMessage, SendReturnSender<T, L>)>,
+    },
     Join {
         channel_login: String,
     },
+    /// Join a channel, and report back once the server confirmed or refused the `JOIN`.
+    JoinAndWait {
+        channel_login: String,
+        return_sender: JoinReturnSender,
+    },
     GetChannelStatus {
         channel_login: String,
-        return_sender: oneshot::Sender<(bool, bool)>,
+        return_sender: oneshot::Sender<ChannelStatus>,
     },
+    ListChannels {
+        return_sender: oneshot::Sender<Vec<ChannelStatus>>,
+    },
+    GetRoomState {
+        channel_login: String,
+        return_sender: oneshot::Sender<Option<RoomState>>,
+    },
+    GetUserState {
+        channel_login: String,
+        return_sender: oneshot::Sender<Option<UserStateMessage>>,
+    },
+    GetPoolSnapshot {
+        return_sender: oneshot::Sender<Vec<ConnectionSnapshot>>,
+    },
+    /// Move channels off under-filled connections and close connections that are empty.
+    Rebalance {
+        return_sender: Option<oneshot::Sender<()>>,
+    },
+    /// Close connections that have been idle for longer than the configured timeout.
+    CloseIdleConnections,
     Part {
         channel_login: String,
     },
@@ -42,10 +96,58 @@ pub(crate) enum ClientLoopCommand<T: Transport, L: LoginCredentials> {
     Ping {
         return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
     },
+    /// Also deliver the incoming messages selected by the filter to the subscriber.
+    Subscribe {
+        subscription: Subscription,
+    },
     IncomingMessage {
         source_connection_id: usize,
         message: Box<ConnectionIncomingMessage<T, L>>,
     },
+    ProcessPrivmsgQueue,
+    /// Send the `JOIN`s queued by the join rate limiter that can be sent now.
+    ProcessJoinQueue,
+    /// Send the batched `JOIN`s and `PART`s of a connection.
+    FlushChannelBatches {
+        connection_id: usize,
+    },
+    /// Check whether the `JOIN` for the given channel was confirmed in time.
+    CheckJoinConfirmed {
+        connection_id: usize,
+        channel_login: String,
+    },
+    /// Send another `JOIN` for the channel if it is still wanted but not confirmed.
+    RetryJoin {
+        channel_login: String,
+    },
+    Shutdown {
+        return_sender: oneshot::Sender<Result<(), ShutdownTimeout>>,
+    },
+    /// All connections closed by the shutdown have ended.
+    ConnectionsClosed,
+    /// The shutdown did not finish within the configured timeout.
+    ShutdownTimedOut,
+}
+
+/// Progress of shutting down the client, see `TwitchIRCClient::shutdown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShutdownState {
+    Running,
+    /// Waiting for the queued `PRIVMSG`s to be sent out.
+    Flushing,
+    /// Waiting for the connections to be closed.
+    Closing,
+    Finished,
+}
+
+/// A `PRIVMSG` held back by the rate limiter.
+struct QueuedPrivmsg<T: Transport, L: LoginCredentials> {
+    channel_login: String,
+    message: OutgoingMessage<T, L>,
+    queued_at: Instant,
+    /// ID of the pool connection the message has to be sent over, if any.
+    connection_id: Option<usize>,
+    confirmation_sender: Option<ConfirmationSender<T, L>>,
 }
 
 pub(crate) struct ClientLoopWorker<T: Transport, L: LoginCredentials> {
@@ -58,7 +160,39 @@ pub(crate) struct ClientLoopWorker<T: Transport, L: LoginCredentials> {
     client_loop_rx: mpsc::UnboundedReceiver<ClientLoopCommand<T, L>>,
     connections: VecDeque<PoolConnection<T, L>>,
     client_loop_tx: Weak<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
-    client_incoming_messages_tx: mpsc::UnboundedSender<ServerMessage>,
+    client_incoming_messages_tx: IncomingMessagesSender,
+    client_events_tx: broadcast::Sender<ClientEvent<T, L>>,
+    /// Subscribers of `TwitchIRCClient::subscribe`. Removed once their receiver is dropped.
+    subscriptions: Vec<Subscription>,
+    /// `None` if rate limiting of `PRIVMSG`s is disabled in the config.
+    privmsg_rate_limiter: Option<PrivmsgRateLimiter>,
+    /// `PRIVMSG`s waiting for the rate limiter to allow them to be sent, in the order
+    /// they were sent by the user.
+    privmsg_queue: VecDeque<QueuedPrivmsg<T, L>>,
+    /// When a `ProcessPrivmsgQueue` command is next scheduled to be sent to this worker.
+    privmsg_queue_wakeup: Option<Instant>,
+    /// `JOIN`s held back by the join rate limiter, as connection ID and channel, oldest first.
+    join_queue: VecDeque<(usize, String)>,
+    /// When a `ProcessJoinQueue` command is next scheduled to be sent to this worker.
+    join_queue_wakeup: Option<Instant>,
+    /// `None` if the duplicate message bypass is disabled in the config.
+    duplicate_message_bypass: Option<DuplicateMessageBypass>,
+    /// `None` if the reconnect backoff is disabled in the config.
+    reconnect_backoff: Option<Backoff>,
+    /// Delays new connections after failed logins, even if the reconnect backoff is disabled.
+    login_failure_backoff: Backoff,
+    /// Callers of `TwitchIRCClient::join_and_wait` waiting for the server to confirm
+    /// the `JOIN` to a channel.
+    join_waiters: HashMap<String, Vec<JoinReturnSender>>,
+    /// What is tracked about each wanted channel beyond the channel sets of the connections.
+    channels: HashMap<String, ChannelInfo>,
+    /// Channels that are being moved to another connection by the rebalancer, with the ID of
+    /// the connection they are moved away from. The channel is parted on that connection once
+    /// the `JOIN` on the new connection is confirmed.
+    migrations: HashMap<String, usize>,
+    shutdown_state: ShutdownState,
+    /// Callers of `TwitchIRCClient::shutdown` waiting for the shutdown to finish.
+    shutdown_waiters: Vec<oneshot::Sender<Result<(), ShutdownTimeout>>>,
     #[cfg(feature = "metrics-collection")]
     metrics: Option<MetricsBundle>,
 }
@@ -68,7 +202,8 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         config: Arc<ClientConfig<L>>,
         client_loop_tx: Weak<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
         client_loop_rx: mpsc::UnboundedReceiver<ClientLoopCommand<T, L>>,
-        client_incoming_messages_tx: mpsc::UnboundedSender<ServerMessage>,
+        client_incoming_messages_tx: IncomingMessagesSender,
+        client_events_tx: broadcast::Sender<ClientEvent<T, L>>,
         #[cfg(feature = "metrics-collection")] metrics: Option<MetricsBundle>,
     ) {
         let span = if let Some(s) = &config.tracing_identifier {
@@ -77,6 +212,17 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             info_span!("client_loop")
         };
 
+        let privmsg_rate_limiter = config
+            .privmsg_rate_limits
+            .clone()
+            .map(PrivmsgRateLimiter::new);
+
+        let reconnect_backoff = config.reconnect_backoff.clone().map(Backoff::new);
+
+        let duplicate_message_bypass = config
+            .duplicate_message_bypass
+            .then(DuplicateMessageBypass::default);
+
         let worker = ClientLoopWorker {
             config,
             next_connection_id: 0,
@@ -85,13 +231,65 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             connections: VecDeque::new(),
             client_loop_tx,
             client_incoming_messages_tx,
+            client_events_tx,
+            subscriptions: vec![],
+            privmsg_rate_limiter,
+            privmsg_queue: VecDeque::new(),
+            privmsg_queue_wakeup: None,
+            join_queue: VecDeque::new(),
+            join_queue_wakeup: None,
+            duplicate_message_bypass,
+            reconnect_backoff,
+            login_failure_backoff: Backoff::for_login_failures(),
+            join_waiters: HashMap::new(),
+            channels: HashMap::new(),
+            migrations: HashMap::new(),
+            shutdown_state: ShutdownState::Running,
+            shutdown_waiters: vec![],
             #[cfg(feature = "metrics-collection")]
             metrics,
         };
 
+        if let Some(rebalance_interval) = worker.config.rebalance_interval {
+            tokio::spawn(ClientLoopWorker::run_periodic_command(
+                worker.client_loop_tx.clone(),
+                rebalance_interval,
+                || ClientLoopCommand::Rebalance {
+                    return_sender: None,
+                },
+            ));
+        }
+        if let Some(idle_connection_timeout) = worker.config.idle_connection_timeout {
+            // idle connections are closed between one and one and a half times the timeout
+            // after they were last used
+            let check_interval = (idle_connection_timeout / 2).max(Duration::from_secs(1));
+            tokio::spawn(ClientLoopWorker::run_periodic_command(
+                worker.client_loop_tx.clone(),
+                check_interval,
+                || ClientLoopCommand::CloseIdleConnections,
+            ));
+        }
         tokio::spawn(worker.run().instrument(span));
     }
 
+    /// Sends the command made by `make_command` to the worker in the given interval, until
+    /// the worker is gone.
+    async fn run_periodic_command(
+        client_loop_tx: Weak<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
+        period: Duration,
+        make_command: impl Fn() -> ClientLoopCommand<T, L>,
+    ) {
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            let Some(client_loop_tx) = client_loop_tx.upgrade() else {
+                // all TwitchIRCClient handles have been dropped
+                break;
+            };
+            client_loop_tx.send(make_command()).ok();
+        }
+    }
+
     async fn run(mut self) {
         tracing::debug!("Spawned client event loop");
         while let Some(command) = self.client_loop_rx.recv().await {
@@ -101,6 +299,11 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
     }
 
     fn process_command(&mut self, command: ClientLoopCommand<T, L>) {
+        if self.shutdown_state != ShutdownState::Running {
+            self.process_command_after_shutdown(command);
+            return;
+        }
+
         match command {
             ClientLoopCommand::Connect { return_sender } => {
                 if self.connections.is_empty() {
@@ -111,25 +314,275 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 return_sender.send(()).ok();
             }
             ClientLoopCommand::SendMessage {
+                message,
+                priority,
+                return_sender,
+            } => {
+                let message = self.outgoing_message(message, priority, return_sender);
+                self.dispatch_message(None, message, None);
+            }
+            ClientLoopCommand::SendConfirmedMessage {
                 message,
                 return_sender,
-            } => self.send_message(message, return_sender),
+                confirmation_sender,
+            } => {
+                let message =
+                    self.outgoing_message(message, MessagePriority::Normal, return_sender);
+                self.dispatch_message(None, message, Some(confirmation_sender));
+            }
+            ClientLoopCommand::SendMessages { messages } => self.send_messages(messages),
             ClientLoopCommand::Join { channel_login } => self.join(channel_login),
+            ClientLoopCommand::JoinAndWait {
+                channel_login,
+                return_sender,
+            } => self.join_and_wait(channel_login, return_sender),
             ClientLoopCommand::SetWantedChannels { channels } => self.set_wanted_channels(channels),
             ClientLoopCommand::GetChannelStatus {
                 channel_login,
                 return_sender,
             } => {
                 return_sender
-                    .send(self.get_channel_status(channel_login))
+                    .send(self.get_channel_status(&channel_login))
                     .ok();
             }
+            ClientLoopCommand::ListChannels { return_sender } => {
+                return_sender.send(self.list_channels()).ok();
+            }
+            ClientLoopCommand::GetRoomState {
+                channel_login,
+                return_sender,
+            } => {
+                return_sender.send(self.get_room_state(&channel_login)).ok();
+            }
+            ClientLoopCommand::GetUserState {
+                channel_login,
+                return_sender,
+            } => {
+                return_sender.send(self.get_user_state(&channel_login)).ok();
+            }
+            ClientLoopCommand::GetPoolSnapshot { return_sender } => {
+                return_sender.send(self.pool_snapshot()).ok();
+            }
+            ClientLoopCommand::Rebalance { return_sender } => {
+                self.rebalance();
+                if let Some(return_sender) = return_sender {
+                    return_sender.send(()).ok();
+                }
+            }
+            ClientLoopCommand::CloseIdleConnections => self.close_idle_connections(),
+            ClientLoopCommand::ProcessJoinQueue => {
+                self.join_queue_wakeup = None;
+                self.process_join_queue();
+            }
+            ClientLoopCommand::FlushChannelBatches { connection_id } => {
+                self.flush_channel_batches(connection_id)
+            }
             ClientLoopCommand::Part { channel_login } => self.part(channel_login),
             ClientLoopCommand::Ping { return_sender } => self.ping(return_sender),
+            ClientLoopCommand::Subscribe { subscription } => self.subscriptions.push(subscription),
             ClientLoopCommand::IncomingMessage {
                 source_connection_id,
                 message,
             } => self.on_incoming_message(source_connection_id, *message),
+            ClientLoopCommand::CheckJoinConfirmed {
+                connection_id,
+                channel_login,
+            } => self.check_join_confirmed(connection_id, channel_login),
+            ClientLoopCommand::RetryJoin { channel_login } => self.retry_join(channel_login),
+            ClientLoopCommand::ProcessPrivmsgQueue => {
+                if self
+                    .privmsg_queue_wakeup
+                    .is_some_and(|wakeup| wakeup <= Instant::now())
+                {
+                    self.privmsg_queue_wakeup = None;
+                }
+                self.process_privmsg_queue();
+            }
+            ClientLoopCommand::Shutdown { return_sender } => self.shutdown(return_sender),
+            ClientLoopCommand::ConnectionsClosed | ClientLoopCommand::ShutdownTimedOut => {}
+        }
+    }
+
+    /// Once the client is shutting down, no new connections are made and no more
+    /// messages are accepted.
+    fn process_command_after_shutdown(&mut self, command: ClientLoopCommand<T, L>) {
+        match command {
+            ClientLoopCommand::Connect { return_sender } => {
+                return_sender.send(()).ok();
+            }
+            ClientLoopCommand::SendMessage { return_sender, .. }
+            | ClientLoopCommand::SendConfirmedMessage { return_sender, .. }
+            | ClientLoopCommand::Ping { return_sender } => {
+                return_sender.send(Err(Error::ClientShutDown)).ok();
+            }
+            ClientLoopCommand::SendMessages { messages } => {
+                for (_message, return_sender) in messages {
+                    return_sender.send(Err(Error::ClientShutDown)).ok();
+                }
+            }
+            ClientLoopCommand::JoinAndWait { return_sender, .. } => {
+                return_sender.send(Err(JoinError::ClientShutDown)).ok();
+            }
+            ClientLoopCommand::Join { .. }
+            | ClientLoopCommand::Part { .. }
+            | ClientLoopCommand::SetWantedChannels { .. }
+            | ClientLoopCommand::CheckJoinConfirmed { .. }
+            | ClientLoopCommand::RetryJoin { .. } => {}
+            ClientLoopCommand::GetChannelStatus {
+                channel_login,
+                return_sender,
+            } => {
+                return_sender
+                    .send(self.get_channel_status(&channel_login))
+                    .ok();
+            }
+            ClientLoopCommand::ListChannels { return_sender } => {
+                return_sender.send(self.list_channels()).ok();
+            }
+            ClientLoopCommand::GetRoomState {
+                channel_login,
+                return_sender,
+            } => {
+                return_sender.send(self.get_room_state(&channel_login)).ok();
+            }
+            ClientLoopCommand::GetUserState {
+                channel_login,
+                return_sender,
+            } => {
+                return_sender.send(self.get_user_state(&channel_login)).ok();
+            }
+            ClientLoopCommand::GetPoolSnapshot { return_sender } => {
+                return_sender.send(self.pool_snapshot()).ok();
+            }
+            ClientLoopCommand::Rebalance { return_sender } => {
+                if let Some(return_sender) = return_sender {
+                    return_sender.send(()).ok();
+                }
+            }
+            ClientLoopCommand::CloseIdleConnections => {}
+            // messages from the connections that are still closing are delivered
+            ClientLoopCommand::Subscribe { subscription } => self.subscriptions.push(subscription),
+            ClientLoopCommand::ProcessJoinQueue => {
+                self.join_queue_wakeup = None;
+            }
+            ClientLoopCommand::FlushChannelBatches { .. } => {}
+            ClientLoopCommand::IncomingMessage {
+                source_connection_id,
+                message,
+            } => self.on_incoming_message(source_connection_id, *message),
+            ClientLoopCommand::ProcessPrivmsgQueue => {
+                if self
+                    .privmsg_queue_wakeup
+                    .is_some_and(|wakeup| wakeup <= Instant::now())
+                {
+                    self.privmsg_queue_wakeup = None;
+                }
+                self.process_privmsg_queue();
+            }
+            ClientLoopCommand::Shutdown { return_sender } => {
+                if self.shutdown_state == ShutdownState::Finished {
+                    return_sender.send(Ok(())).ok();
+                } else {
+                    self.shutdown_waiters.push(return_sender);
+                }
+            }
+            ClientLoopCommand::ConnectionsClosed => {
+                if self.shutdown_state == ShutdownState::Closing {
+                    self.finish_shutdown(Ok(()));
+                }
+            }
+            ClientLoopCommand::ShutdownTimedOut => {
+                if self.shutdown_state != ShutdownState::Finished {
+                    tracing::warn!("Shutdown timed out, closing remaining connections forcibly");
+                    for queued_message in self.privmsg_queue.drain(..) {
+                        queued_message.message.fail(Error::ClientShutDown);
+                    }
+                    self.finish_shutdown(Err(ShutdownTimeout));
+                }
+            }
+        }
+    }
+
+    fn shutdown(&mut self, return_sender: oneshot::Sender<Result<(), ShutdownTimeout>>) {
+        tracing::info!("Shutting down client");
+        self.shutdown_state = ShutdownState::Flushing;
+        self.shutdown_waiters.push(return_sender);
+        for (_channel_login, join_waiters) in self.join_waiters.drain() {
+            for join_waiter in join_waiters {
+                join_waiter.send(Err(JoinError::ClientShutDown)).ok();
+            }
+        }
+
+        let deadline = Instant::now() + self.config.shutdown_timeout;
+        let client_loop_tx = self.client_loop_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline).await;
+            if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                client_loop_tx
+                    .send(ClientLoopCommand::ShutdownTimedOut)
+                    .ok();
+            }
+        });
+
+        self.close_connections_if_flushed();
+    }
+
+    /// Once all queued `PRIVMSG`s have been sent, parts all channels and closes all connections.
+    fn close_connections_if_flushed(&mut self) {
+        if self.shutdown_state != ShutdownState::Flushing || !self.privmsg_queue.is_empty() {
+            return;
+        }
+        self.shutdown_state = ShutdownState::Closing;
+        self.channels.clear();
+        self.join_queue.clear();
+
+        let mut done_receivers = Vec::with_capacity(self.connections.len());
+        for c in &mut self.connections {
+            // the PARTs and the QUIT are sent after the messages that were sent before
+            c.join_batch.clear();
+            c.part_batch.clear();
+            let channel_logins = c.wanted_channels.drain().collect::<Vec<_>>();
+            c.part_after_queued_messages(&channel_logins);
+
+            let (done_tx, done_rx) = oneshot::channel();
+            c.connection
+                .connection_loop_tx
+                .send(ConnectionLoopCommand::Close(done_tx))
+                .unwrap();
+            done_receivers.push(done_rx);
+        }
+
+        let client_loop_tx = self.client_loop_tx.clone();
+        tokio::spawn(async move {
+            for done_rx in done_receivers {
+                done_rx.await.ok();
+            }
+            if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                client_loop_tx
+                    .send(ClientLoopCommand::ConnectionsClosed)
+                    .ok();
+            }
+        });
+        self.update_metrics();
+    }
+
+    fn finish_shutdown(&mut self, result: Result<(), ShutdownTimeout>) {
+        self.shutdown_state = ShutdownState::Finished;
+        for mut pool_connection in std::mem::take(&mut self.connections) {
+            for pending_confirmation in pool_connection.pending_confirmations.drain(..) {
+                pending_confirmation.fail(Error::ClientShutDown);
+            }
+            self.emit_event(ClientEvent::ConnectionClosed {
+                connection_id: pool_connection.id,
+                cause: Error::ClientShutDown,
+            });
+        }
+        self.current_whisper_connection_id = None;
+        self.update_metrics();
+
+        tracing::info!("Client was shut down");
+        for waiter in self.shutdown_waiters.drain(..) {
+            waiter.send(result).ok();
         }
     }
 
@@ -146,6 +599,8 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         let (connection_incoming_messages_rx, connection) = Connection::new(
             Arc::clone(&self.config),
             connection_id,
+            self.connect_delay(),
+            self.client_incoming_messages_tx.backpressure(),
             #[cfg(feature = "metrics-collection")]
             self.metrics.clone(),
         );
@@ -172,6 +627,17 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         pool_conn
     }
 
+    /// How long a new connection should wait before connecting, to back off after
+    /// connections failed.
+    fn connect_delay(&mut self) -> Duration {
+        let now = Instant::now();
+        let reconnect_delay = self
+            .reconnect_backoff
+            .as_mut()
+            .map_or(Duration::ZERO, |backoff| backoff.next_delay(now));
+        reconnect_delay.max(self.login_failure_backoff.next_delay(now))
+    }
+
     /// forwards messages from a Connection to the client event loop.
     async fn run_incoming_forward_task(
         mut connection_incoming_messages_rx: mpsc::UnboundedReceiver<
@@ -208,15 +674,70 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         }
     }
 
+    /// Wraps a message sent by the library user, low priority messages expire after the
+    /// configured time.
+    fn outgoing_message(
+        &self,
+        message: IRCMessage,
+        priority: MessagePriority,
+        return_sender: SendReturnSender<T, L>,
+    ) -> OutgoingMessage<T, L> {
+        let expiry = match priority {
+            MessagePriority::Low => self.config.low_priority_message_expiry,
+            _ => None,
+        };
+        OutgoingMessage {
+            expires_at: expiry.map(|expiry| Instant::now() + expiry),
+            reply_sender: Some(return_sender),
+            ..OutgoingMessage::new(message, priority)
+        }
+    }
+
+    /// Sends the message right away, or queues it if it is subject to rate limiting.
+    fn dispatch_message(
+        &mut self,
+        connection_id: Option<usize>,
+        message: OutgoingMessage<T, L>,
+        confirmation_sender: Option<ConfirmationSender<T, L>>,
+    ) {
+        if message.message.command == "PRIVMSG" && self.privmsg_rate_limiter.is_some() {
+            self.enqueue_privmsg(connection_id, message, confirmation_sender);
+        } else {
+            self.send_message_on_connection(connection_id, message, confirmation_sender);
+        }
+    }
+
     fn send_message(
         &mut self,
         message: IRCMessage,
         return_sender: oneshot::Sender<Result<(), Error<T, L>>>,
     ) {
-        let mut pool_connection = self
-            .connections
-            .iter()
-            .position(|c| c.not_busy())
+        let message = self.outgoing_message(message, MessagePriority::Normal, return_sender);
+        self.send_message_on_connection(None, message, None);
+    }
+
+    /// Sends the message over the pool connection with the given ID, or over any connection
+    /// if `connection_id` is `None` or that connection no longer exists.
+    ///
+    /// If a `confirmation_sender` is given, it is notified once Twitch has confirmed
+    /// or rejected the message.
+    fn send_message_on_connection(
+        &mut self,
+        connection_id: Option<usize>,
+        mut message: OutgoingMessage<T, L>,
+        confirmation_sender: Option<ConfirmationSender<T, L>>,
+    ) {
+        let mut sent_message_record = None;
+        if message.message.command == "PRIVMSG"
+            && let Some(duplicate_message_bypass) = self.duplicate_message_bypass.as_mut()
+        {
+            sent_message_record =
+                duplicate_message_bypass.process(&mut message.message, Instant::now());
+        }
+
+        let mut pool_connection = connection_id
+            .and_then(|id| self.connections.iter().position(|c| c.id == id))
+            .or_else(|| self.connections.iter().position(|c| c.not_busy()))
             // take what we found
             .map(|pos| self.connections.remove(pos).unwrap())
             // or else make a new one
@@ -224,13 +745,27 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
 
         pool_connection.register_sent_message();
 
+        // recorded once the connection writes the message, see `SendingPrivmsg`
+        if message.message.command == "PRIVMSG" {
+            pool_connection.unreported_privmsgs += 1;
+            let channel_login = message
+                .message
+                .params
+                .first()
+                .map(|channel| channel.trim_start_matches('#').to_owned())
+                .unwrap_or_default();
+            message.pending_confirmation = Some(PendingConfirmation {
+                channel_login,
+                confirmation_sender,
+                sent_at: Instant::now(),
+                sent_message_record,
+            });
+        }
+
         pool_connection
             .connection
             .connection_loop_tx
-            .send(ConnectionLoopCommand::SendMessage(
-                message,
-                Some(return_sender),
-            ))
+            .send(ConnectionLoopCommand::SendMessage(message))
             .unwrap();
 
         // put the connection back to the end of the queue
@@ -245,6 +780,147 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         self.update_metrics();
     }
 
+    fn send_messages(&mut self, messages: Vec<(IRCMessage, SendReturnSender<T, L>)>) {
+        // all messages are sent over the same connection, so they can't be reordered
+        // by being sent over different connections.
+        let connection_id = if let Some(c) = self.connections.iter().find(|c| c.not_busy()) {
+            c.id
+        } else {
+            let new_connection = self.make_new_connection();
+            let connection_id = new_connection.id;
+            self.connections.push_back(new_connection);
+            self.update_metrics();
+            connection_id
+        };
+
+        for (message, return_sender) in messages {
+            let message = self.outgoing_message(message, MessagePriority::Normal, return_sender);
+            self.dispatch_message(Some(connection_id), message, None);
+        }
+    }
+
+    fn enqueue_privmsg(
+        &mut self,
+        connection_id: Option<usize>,
+        message: OutgoingMessage<T, L>,
+        confirmation_sender: Option<ConfirmationSender<T, L>>,
+    ) {
+        let channel_login = message
+            .message
+            .params
+            .first()
+            .map(|channel| channel.trim_start_matches('#').to_owned())
+            .unwrap_or_default();
+
+        // the queue is ordered by priority, and by the time the messages were queued within
+        // the same priority
+        let priority = message.priority.index();
+        let pos = self
+            .privmsg_queue
+            .iter()
+            .position(|queued_message| queued_message.message.priority.index() > priority)
+            .unwrap_or(self.privmsg_queue.len());
+        self.privmsg_queue.insert(
+            pos,
+            QueuedPrivmsg {
+                channel_login,
+                message,
+                queued_at: Instant::now(),
+                connection_id,
+                confirmation_sender,
+            },
+        );
+        self.process_privmsg_queue();
+    }
+
+    /// Sends out all queued `PRIVMSG`s that the rate limiter allows to be sent right now,
+    /// and schedules the next run of this method if messages remain in the queue.
+    fn process_privmsg_queue(&mut self) {
+        let Some(rate_limiter) = self.privmsg_rate_limiter.as_mut() else {
+            return;
+        };
+
+        let now = Instant::now();
+        let mut ready_to_send = vec![];
+        // messages to the same channel must not overtake messages of the same or a higher
+        // priority. Once a message to a channel has to wait, all later messages to that channel
+        // wait too.
+        let mut waiting_channels = HashSet::new();
+        let mut next_wakeup: Option<Instant> = None;
+
+        let mut i = 0;
+        while i < self.privmsg_queue.len() {
+            if self.privmsg_queue[i]
+                .message
+                .expires_at
+                .is_some_and(|expires_at| expires_at <= now)
+            {
+                let queued_message = self.privmsg_queue.remove(i).unwrap();
+                tracing::debug!(
+                    "Dropping PRIVMSG to #{} that waited too long to be sent",
+                    queued_message.channel_login
+                );
+                queued_message.message.fail(Error::MessageExpired);
+                continue;
+            }
+
+            let channel_login = &self.privmsg_queue[i].channel_login;
+            if waiting_channels.contains(channel_login) {
+                i += 1;
+                continue;
+            }
+
+            let ready_at = rate_limiter.ready_at(channel_login, now);
+            if ready_at <= now {
+                rate_limiter.register_sent_message(channel_login, now);
+                ready_to_send.push(self.privmsg_queue.remove(i).unwrap());
+            } else {
+                next_wakeup = Some(next_wakeup.map_or(ready_at, |w| w.min(ready_at)));
+                waiting_channels.insert(channel_login.clone());
+                i += 1;
+            }
+        }
+
+        for queued_message in ready_to_send {
+            if queued_message.queued_at < now {
+                tracing::debug!(
+                    "Sending PRIVMSG to #{} after it was delayed by the rate limiter for {:?}",
+                    queued_message.channel_login,
+                    now - queued_message.queued_at
+                );
+                #[cfg(feature = "metrics-collection")]
+                if let Some(ref metrics) = self.metrics {
+                    metrics.privmsgs_delayed.inc();
+                }
+            }
+            self.send_message_on_connection(
+                queued_message.connection_id,
+                queued_message.message,
+                queued_message.confirmation_sender,
+            );
+        }
+
+        if let Some(next_wakeup) = next_wakeup
+            && self
+                .privmsg_queue_wakeup
+                .is_none_or(|wakeup| next_wakeup < wakeup)
+        {
+            self.privmsg_queue_wakeup = Some(next_wakeup);
+            let client_loop_tx = self.client_loop_tx.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep_until(next_wakeup).await;
+                if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                    client_loop_tx
+                        .send(ClientLoopCommand::ProcessPrivmsgQueue)
+                        .ok();
+                }
+            });
+        }
+
+        self.update_metrics();
+        self.close_connections_if_flushed();
+    }
+
     /// Instructs the client to now start "wanting to be joined" to that channel.
     ///
     /// The client will make best attempts to stay joined to this channel. I/O errors will be
@@ -259,6 +935,12 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             return;
         }
 
+        self.channels
+            .entry(channel_login.clone())
+            .or_default()
+            .join_requested_at
+            .get_or_insert_with(Instant::now);
+
         let mut pool_connection = self
             .connections
             .iter()
@@ -277,17 +959,7 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
             // or else make a new connection
             .unwrap_or_else(|| self.make_new_connection());
 
-        // delegate join command to connection
-        pool_connection
-            .connection
-            .connection_loop_tx
-            .send(ConnectionLoopCommand::SendMessage(
-                irc!["JOIN", format!("#{}", channel_login)],
-                None,
-            ))
-            .unwrap();
-
-        pool_connection.register_sent_message();
+        self.send_join(&mut pool_connection, channel_login.clone());
         pool_connection.wanted_channels.insert(channel_login);
 
         // put the connection back to the end of the queue
@@ -296,66 +968,591 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         self.update_metrics();
     }
 
-    fn set_wanted_channels(&mut self, channels: HashSet<String>) {
-        // part channels as needed
-        self.connections
-            .iter()
-            .flat_map(|conn| conn.wanted_channels.difference(&channels))
-            .cloned()
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|channel_login| self.part(channel_login));
+    /// Delegates the `JOIN` to the connection, and schedules the check whether the server
+    /// confirmed it in time.
+    fn send_join(&mut self, pool_connection: &mut PoolConnection<T, L>, channel_login: String) {
+        if let Some(join_rate_limiter) = &self.config.join_rate_limiter {
+            // JOINs must not overtake the ones already queued
+            let now = Instant::now();
+            let acquired = if self.join_queue.is_empty() {
+                join_rate_limiter.try_acquire(now)
+            } else {
+                Err(now)
+            };
+            if let Err(ready_at) = acquired {
+                tracing::debug!(
+                    "JOIN to {} on connection {} is delayed by the join rate limiter",
+                    channel_login,
+                    pool_connection.id
+                );
+                self.join_queue
+                    .push_back((pool_connection.id, channel_login));
+                self.schedule_join_queue_wakeup(ready_at);
+                self.update_metrics();
+                return;
+            }
+        }
+        self.send_join_now(pool_connection, channel_login);
+    }
 
-        // join all wanted channels. Channels already joined will be detected
-        // inside the join method.
-        for channel_login in channels {
-            self.join(channel_login);
+    fn send_join_now(&mut self, pool_connection: &mut PoolConnection<T, L>, channel_login: String) {
+        self.send_channel_command(pool_connection, ChannelCommand::Join, channel_login.clone());
+
+        if let Some(channel_info) = self.channels.get_mut(&channel_login) {
+            channel_info.join_attempts += 1;
         }
+        // the JOIN might only be sent once the batch window has passed
+        let join_deadline = Instant::now()
+            + self.config.channel_batch_window.unwrap_or_default()
+            + self.config.join_timeout;
+        pool_connection
+            .pending_joins
+            .insert(channel_login.clone(), join_deadline);
+        self.schedule_join_check(pool_connection.id, channel_login, join_deadline);
     }
 
-    fn get_channel_status(&mut self, channel_login: String) -> (bool, bool) {
-        let wanted = self
-            .connections
-            .iter()
-            .any(|c| c.wanted_channels.contains(&channel_login));
-        let joined_on_server = self
-            .connections
-            .iter()
-            .any(|c| c.server_channels.contains(&channel_login));
-        (wanted, joined_on_server)
+    /// Sends the `JOIN` or `PART`, or adds it to the batch of the connection.
+    fn send_channel_command(
+        &self,
+        pool_connection: &mut PoolConnection<T, L>,
+        command: ChannelCommand,
+        channel_login: String,
+    ) {
+        if pool_connection.send_channel_command(command, channel_login) {
+            self.schedule_channel_batch_flush(pool_connection.id);
+        }
     }
 
-    fn part(&mut self, channel_login: String) {
-        // skip the PART altogether if the last message we sent regarding that channel was a PART
-        // (or nothing at all, for that matter).
-        if self
-            .connections
-            .iter()
-            .all(|c| !c.wanted_channels.contains(&channel_login))
-        {
+    fn schedule_channel_batch_flush(&self, connection_id: usize) {
+        let Some(channel_batch_window) = self.config.channel_batch_window else {
             return;
+        };
+        let client_loop_tx = self.client_loop_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(channel_batch_window).await;
+            if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                client_loop_tx
+                    .send(ClientLoopCommand::FlushChannelBatches { connection_id })
+                    .ok();
+            }
+        });
+    }
+
+    fn flush_channel_batches(&mut self, connection_id: usize) {
+        if let Some(c) = self.connections.iter_mut().find(|c| c.id == connection_id) {
+            c.flush_channel_batches();
         }
+    }
 
-        // now grab the connection that has that channel
-        let mut pool_connection = self
+    /// Sends the queued `JOIN`s for as long as the join rate limiter permits.
+    fn process_join_queue(&mut self) {
+        let Some(join_rate_limiter) = self.config.join_rate_limiter.clone() else {
+            return;
+        };
+
+        while let Some((connection_id, channel_login)) = self.join_queue.front() {
+            // the channel might have been parted, or the connection might have failed (in
+            // which case its channels are rejoined on other connections) in the meantime
+            let Some(pos) = self.connections.iter().position(|c| {
+                c.id == *connection_id
+                    && c.wanted_channels.contains(channel_login)
+                    && !c.server_channels.contains(channel_login)
+                    && !c.pending_joins.contains_key(channel_login)
+            }) else {
+                self.join_queue.pop_front();
+                continue;
+            };
+
+            let now = Instant::now();
+            if let Err(ready_at) = join_rate_limiter.try_acquire(now) {
+                self.schedule_join_queue_wakeup(ready_at);
+                break;
+            }
+
+            let (_, channel_login) = self.join_queue.pop_front().unwrap();
+            let mut pool_connection = self.connections.remove(pos).unwrap();
+            self.send_join_now(&mut pool_connection, channel_login);
+            self.connections.insert(pos, pool_connection);
+        }
+
+        self.update_metrics();
+    }
+
+    fn schedule_join_queue_wakeup(&mut self, wakeup: Instant) {
+        if self.join_queue_wakeup.is_some_and(|w| w <= wakeup) {
+            return;
+        }
+        self.join_queue_wakeup = Some(wakeup);
+        let client_loop_tx = self.client_loop_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(wakeup).await;
+            if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                client_loop_tx
+                    .send(ClientLoopCommand::ProcessJoinQueue)
+                    .ok();
+            }
+        });
+    }
+
+    fn join_and_wait(&mut self, channel_login: String, return_sender: JoinReturnSender) {
+        self.join(channel_login.clone());
+
+        let channel_confirmed_joined = self.connections.iter().any(|c| {
+            c.wanted_channels.contains(&channel_login) && c.server_channels.contains(&channel_login)
+        });
+        if channel_confirmed_joined {
+            return_sender.send(Ok(())).ok();
+        } else {
+            let join_waiters = self.join_waiters.entry(channel_login).or_default();
+            // forget callers that have given up waiting
+            join_waiters.retain(|join_waiter| !join_waiter.is_closed());
+            join_waiters.push(return_sender);
+        }
+    }
+
+    /// Reports the result of joining the channel to everyone waiting for it.
+    fn resolve_join_waiters(&mut self, channel_login: &str, result: &Result<(), JoinError>) {
+        if let Some(join_waiters) = self.join_waiters.remove(channel_login) {
+            for join_waiter in join_waiters {
+                join_waiter.send(result.clone()).ok();
+            }
+        }
+    }
+
+    fn schedule_join_check(&self, connection_id: usize, channel_login: String, deadline: Instant) {
+        let client_loop_tx = self.client_loop_tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline).await;
+            if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                client_loop_tx
+                    .send(ClientLoopCommand::CheckJoinConfirmed {
+                        connection_id,
+                        channel_login,
+                    })
+                    .ok();
+            }
+        });
+    }
+
+    fn check_join_confirmed(&mut self, connection_id: usize, channel_login: String) {
+        let Some(c) = self.connections.iter_mut().find(|c| c.id == connection_id) else {
+            // the connection failed in the meantime
+            return;
+        };
+
+        // the JOIN might have been confirmed, or sent again in the meantime
+        if c.pending_joins
+            .get(&channel_login)
+            .is_none_or(|deadline| *deadline > Instant::now())
+        {
+            return;
+        }
+        c.pending_joins.remove(&channel_login);
+        if let Some(channel_info) = self.channels.get_mut(&channel_login) {
+            channel_info.last_failure = Some(JoinFailure::TimedOut);
+        }
+
+        tracing::warn!(
+            "JOIN to {} on connection {} was not confirmed in time",
+            channel_login,
+            connection_id
+        );
+        self.abort_migration(&channel_login);
+        self.emit_event(ClientEvent::ChannelJoinTimedOut {
+            connection_id,
+            channel_login: channel_login.clone(),
+        });
+
+        if let Some(join_retry_interval) = self.config.join_retry_interval {
+            let client_loop_tx = self.client_loop_tx.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(join_retry_interval).await;
+                if let Some(client_loop_tx) = client_loop_tx.upgrade() {
+                    client_loop_tx
+                        .send(ClientLoopCommand::RetryJoin { channel_login })
+                        .ok();
+                }
+            });
+        }
+    }
+
+    fn retry_join(&mut self, channel_login: String) {
+        // the channel might have been parted, joined or rejoined on another connection
+        // in the meantime
+        let Some(pos) = self.connections.iter().position(|c| {
+            c.wanted_channels.contains(&channel_login)
+                && !c.server_channels.contains(&channel_login)
+                && !c.pending_joins.contains_key(&channel_login)
+        }) else {
+            return;
+        };
+        if self
+            .join_queue
+            .iter()
+            .any(|(_, queued_channel)| *queued_channel == channel_login)
+        {
+            return;
+        }
+
+        tracing::info!("Retrying JOIN to {}", channel_login);
+        let mut pool_connection = self.connections.remove(pos).unwrap();
+        self.send_join(&mut pool_connection, channel_login);
+        self.connections.push_back(pool_connection);
+    }
+
+    /// Moves channels off under-filled connections, so as few connections as possible are
+    /// needed, and closes connections that are empty.
+    ///
+    /// Only open connections take part whose channels are all confirmed to be joined, and that
+    /// are not involved in a migration already. Channels are moved by first joining them on
+    /// the new connection, and parting them on the old connection once the `JOIN` is confirmed.
+    fn rebalance(&mut self) {
+        let max_channels = self.config.max_channels_per_connection.max(1);
+        let migrating_connections = self
+            .migrations
+            .iter()
+            .flat_map(|(channel_login, from_id)| {
+                let to_id = self
+                    .connections
+                    .iter()
+                    .find(|c| c.wanted_channels.contains(channel_login))
+                    .map(|c| c.id);
+                std::iter::once(*from_id).chain(to_id)
+            })
+            .collect::<HashSet<_>>();
+
+        let mut candidates = self
             .connections
             .iter()
-            .position(|c| c.wanted_channels.contains(&channel_login))
-            .and_then(|pos| self.connections.remove(pos))
+            .filter(|c| {
+                c.reported_state == PoolConnectionState::Open
+                    && c.wanted_channels == c.server_channels
+                    && c.pending_joins.is_empty()
+                    && !migrating_connections.contains(&c.id)
+            })
+            .map(|c| (c.id, c.wanted_channels.len()))
+            .collect::<Vec<_>>();
+        // the fullest connections are kept
+        candidates.sort_unstable_by_key(|(id, num_channels)| (usize::MAX - num_channels, *id));
+
+        let total_channels = candidates
+            .iter()
+            .map(|(_, num_channels)| num_channels)
+            .sum::<usize>();
+        let needed_connections = total_channels.div_ceil(max_channels).max(1);
+        if candidates.len() > needed_connections {
+            let (targets, sources) = candidates.split_at_mut(needed_connections);
+            for (source_id, _) in sources.iter() {
+                let mut channels = self
+                    .connections
+                    .iter()
+                    .find(|c| c.id == *source_id)
+                    .unwrap()
+                    .wanted_channels
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                channels.sort_unstable();
+
+                for channel_login in channels {
+                    // there is always room, since the channels fit on the kept connections
+                    let (target_id, target_num_channels) = targets
+                        .iter_mut()
+                        .find(|(_, num_channels)| *num_channels < max_channels)
+                        .unwrap();
+                    *target_num_channels += 1;
+                    self.migrate_channel(channel_login, *source_id, *target_id);
+                }
+            }
+        }
+
+        let empty_connections = self
+            .connections
+            .iter()
+            .filter(|c| Self::is_retirable(c))
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        for connection_id in empty_connections {
+            self.retire_connection_if_empty(connection_id);
+        }
+        self.update_metrics();
+    }
+
+    /// Closes connections that have no channels and were not used to send a message within
+    /// the configured idle timeout.
+    fn close_idle_connections(&mut self) {
+        let Some(idle_connection_timeout) = self.config.idle_connection_timeout else {
+            return;
+        };
+        let idle_connections = self
+            .connections
+            .iter()
+            .filter(|c| Self::is_retirable(c) && c.last_used.elapsed() >= idle_connection_timeout)
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        for connection_id in idle_connections {
+            self.retire_connection_if_empty(connection_id);
+        }
+    }
+
+    fn migrate_channel(&mut self, channel_login: String, from_id: usize, to_id: usize) {
+        tracing::info!(
+            "Moving channel {} from connection {} to connection {}",
+            channel_login,
+            from_id,
+            to_id
+        );
+        let from = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == from_id)
             .unwrap();
+        from.wanted_channels.remove(&channel_login);
 
-        // delegate part command to connection
+        let pos = self.connections.iter().position(|c| c.id == to_id).unwrap();
+        let mut to = self.connections.remove(pos).unwrap();
+        self.send_join(&mut to, channel_login.clone());
+        to.wanted_channels.insert(channel_login.clone());
+        self.connections.push_back(to);
+
+        self.migrations.insert(channel_login, from_id);
+    }
+
+    /// Called once the `JOIN` of a channel that is being migrated was confirmed on the new
+    /// connection. The channel is then parted on the old connection.
+    fn finish_migration(&mut self, channel_login: &str) {
+        let Some(from_id) = self.migrations.get(channel_login) else {
+            return;
+        };
+        let Some(from) = self.connections.iter_mut().find(|c| c.id == *from_id) else {
+            // the old connection failed in the meantime
+            self.migrations.remove(channel_login);
+            return;
+        };
+
+        if from.send_channel_command(ChannelCommand::Part, channel_login.to_owned()) {
+            let from_id = from.id;
+            self.schedule_channel_batch_flush(from_id);
+        }
+    }
+
+    /// Called when the `JOIN` of a channel that is being migrated was not confirmed on the new
+    /// connection. The channel then stays on the old connection.
+    fn abort_migration(&mut self, channel_login: &str) {
+        let Some(from_id) = self.migrations.remove(channel_login) else {
+            return;
+        };
+        let Some(from) = self.connections.iter_mut().find(|c| c.id == from_id) else {
+            return;
+        };
+        if !from.server_channels.contains(channel_login) {
+            return;
+        }
+        tracing::info!(
+            "Moving channel {} back to connection {}",
+            channel_login,
+            from_id
+        );
+        from.wanted_channels.insert(channel_login.to_owned());
+
+        if let Some(to) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id != from_id && c.wanted_channels.contains(channel_login))
+        {
+            to.wanted_channels.remove(channel_login);
+            // in case the JOIN is confirmed after all
+            if to.send_channel_command(ChannelCommand::Part, channel_login.to_owned()) {
+                let to_id = to.id;
+                self.schedule_channel_batch_flush(to_id);
+            }
+        }
+    }
+
+    /// Whether the connection has no channels, and nothing else is going on that would need
+    /// it to stay open.
+    fn is_retirable(c: &PoolConnection<T, L>) -> bool {
+        c.reported_state == PoolConnectionState::Open
+            && c.wanted_channels.is_empty()
+            && c.server_channels.is_empty()
+            && c.unreported_privmsgs == 0
+            && !c.awaits_confirmations()
+    }
+
+    /// Closes the connection if it is empty, unless it is the last connection (which is kept
+    /// open to receive whispers).
+    fn retire_connection_if_empty(&mut self, connection_id: usize) {
+        if self.connections.len() <= 1 || self.migrations.values().any(|id| *id == connection_id) {
+            return;
+        }
+        let Some(pos) = self
+            .connections
+            .iter()
+            .position(|c| c.id == connection_id && Self::is_retirable(c))
+        else {
+            return;
+        };
+
+        tracing::info!(
+            "Closing connection {}, it is no longer needed",
+            connection_id
+        );
+        let pool_connection = self.connections.remove(pos).unwrap();
+        // the connection loop reports back when it is done, which is not needed here
+        let (done_tx, _) = oneshot::channel();
         pool_connection
             .connection
             .connection_loop_tx
-            .send(ConnectionLoopCommand::SendMessage(
-                irc!["PART", format!("#{}", channel_login)],
-                None,
-            ))
+            .send(ConnectionLoopCommand::Close(done_tx))
             .unwrap();
 
-        pool_connection.register_sent_message();
+        if self.current_whisper_connection_id == Some(connection_id) {
+            self.current_whisper_connection_id = None;
+        }
+        self.update_metrics();
+        self.emit_event(ClientEvent::ConnectionRetired { connection_id });
+    }
+
+    fn emit_event(&self, event: ClientEvent<T, L>) {
+        // ignore if the library user is not subscribed to events
+        self.client_events_tx.send(event).ok();
+    }
+
+    fn set_wanted_channels(&mut self, channels: HashSet<String>) {
+        // part channels as needed
+        self.connections
+            .iter()
+            .flat_map(|conn| conn.wanted_channels.difference(&channels))
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .for_each(|channel_login| self.part(channel_login));
+
+        // join all wanted channels. Channels already joined will be detected
+        // inside the join method.
+        for channel_login in channels {
+            self.join(channel_login);
+        }
+    }
+
+    fn get_channel_status(&self, channel_login: &str) -> ChannelStatus {
+        let wanted_on = self
+            .connections
+            .iter()
+            .find(|c| c.wanted_channels.contains(channel_login));
+        let joined_on = self
+            .connections
+            .iter()
+            .find(|c| c.server_channels.contains(channel_login));
+        let channel_info = self.channels.get(channel_login);
+
+        ChannelStatus {
+            channel_login: channel_login.to_owned(),
+            wanted: wanted_on.is_some(),
+            joined: joined_on.is_some(),
+            connection_id: wanted_on.or(joined_on).map(|c| c.id),
+            join_requested_at: channel_info
+                .and_then(|i| i.join_requested_at)
+                .map(Instant::into_std),
+            joined_at: channel_info
+                .and_then(|i| i.joined_at)
+                .map(Instant::into_std),
+            join_attempts: channel_info.map_or(0, |i| i.join_attempts),
+            last_failure: channel_info.and_then(|i| i.last_failure.clone()),
+            room_state: channel_info.and_then(|i| i.room_state.clone()),
+        }
+    }
+
+    fn get_room_state(&self, channel_login: &str) -> Option<RoomState> {
+        self.channels
+            .get(channel_login)
+            .and_then(|i| i.merged_room_state.clone())
+    }
+
+    fn get_user_state(&self, channel_login: &str) -> Option<UserStateMessage> {
+        self.channels
+            .get(channel_login)
+            .and_then(|i| i.user_state.clone())
+    }
+
+    /// The state of every pool connection, ordered by connection ID.
+    fn pool_snapshot(&self) -> Vec<ConnectionSnapshot> {
+        let now = Instant::now();
+        let sorted = |channels: &HashSet<String>| {
+            let mut channels = channels.iter().cloned().collect::<Vec<_>>();
+            channels.sort_unstable();
+            channels
+        };
+
+        let mut snapshot = self
+            .connections
+            .iter()
+            .map(|c| ConnectionSnapshot {
+                connection_id: c.id,
+                state: c.reported_state,
+                uptime: c.opened_at.map(|opened_at| now - opened_at),
+                wanted_channels: sorted(&c.wanted_channels),
+                server_channels: sorted(&c.server_channels),
+                recent_messages_sent: c.message_send_times.len(),
+                is_whisper_connection: self.current_whisper_connection_id == Some(c.id),
+            })
+            .collect::<Vec<_>>();
+        snapshot.sort_unstable_by_key(|c| c.connection_id);
+        snapshot
+    }
+
+    /// The status of every channel that is wanted or joined, ordered by channel login.
+    fn list_channels(&self) -> Vec<ChannelStatus> {
+        let mut channel_logins = self
+            .connections
+            .iter()
+            .flat_map(|c| c.wanted_channels.iter().chain(&c.server_channels))
+            .collect::<Vec<_>>();
+        channel_logins.sort_unstable();
+        channel_logins.dedup();
+
+        channel_logins
+            .into_iter()
+            .map(|channel_login| self.get_channel_status(channel_login))
+            .collect()
+    }
+
+    fn part(&mut self, channel_login: String) {
+        // skip the PART altogether if the last message we sent regarding that channel was a PART
+        // (or nothing at all, for that matter).
+        if self
+            .connections
+            .iter()
+            .all(|c| !c.wanted_channels.contains(&channel_login))
+        {
+            return;
+        }
+
+        // now grab the connection that has that channel
+        let mut pool_connection = self
+            .connections
+            .iter()
+            .position(|c| c.wanted_channels.contains(&channel_login))
+            .and_then(|pos| self.connections.remove(pos))
+            .unwrap();
+
+        // delegate part command to connection
+        self.send_channel_command(
+            &mut pool_connection,
+            ChannelCommand::Part,
+            channel_login.clone(),
+        );
         pool_connection.wanted_channels.remove(&channel_login);
+        self.channels.remove(&channel_login);
+
+        // if the channel was being moved to this connection, it also has to be parted on the
+        // connection it was moved away from
+        if self.migrations.contains_key(&channel_login)
+            && !pool_connection.server_channels.contains(&channel_login)
+        {
+            pool_connection.pending_joins.remove(&channel_login);
+            self.finish_migration(&channel_login);
+        }
 
         // put the connection back to the end of the queue
         self.connections.push_back(pool_connection);
@@ -367,13 +1564,166 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         self.send_message(irc!["PING", "tmi.twitch.tv"], return_sender);
     }
 
+    /// Called when a `USERSTATE` was received. If it is the answer to a `PRIVMSG`, it confirms
+    /// the oldest pending message sent to that channel.
+    fn confirm_delivery(&mut self, source_connection_id: usize, user_state: &UserStateMessage) {
+        // Twitch also sends a USERSTATE after a JOIN or when the user is modded or unmodded.
+        // Only the USERSTATE answering a PRIVMSG carries the ID of the sent message.
+        if !user_state.source.tags.0.contains_key("id") {
+            return;
+        }
+
+        let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == source_connection_id)
+        else {
+            return;
+        };
+
+        if let Some(confirmation_sender) = c
+            .take_pending_confirmation(&user_state.channel_login)
+            .and_then(|pending_confirmation| pending_confirmation.confirmation_sender)
+        {
+            confirmation_sender.send(Ok(())).ok();
+        }
+    }
+
+    fn on_notice(&mut self, source_connection_id: usize, notice: &NoticeMessage) {
+        // a JOIN is sent before the messages to the channel that follow it, so Twitch
+        // answers the JOIN first
+        if !self.reject_join(source_connection_id, notice) {
+            self.reject_delivery(source_connection_id, notice);
+        }
+    }
+
+    /// Called when a `NOTICE` was received. If it refuses a `JOIN` sent over that connection
+    /// that was not confirmed yet, the join fails. Returns whether it did.
+    fn reject_join(&mut self, source_connection_id: usize, notice: &NoticeMessage) -> bool {
+        let (Some(channel_login), Some(reason @ (NoticeId::ChannelSuspended | NoticeId::Banned))) =
+            (&notice.channel_login, &notice.notice_id)
+        else {
+            return false;
+        };
+
+        // `msg_banned` also answers messages sent to a channel that is already joined
+        let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == source_connection_id)
+        else {
+            return false;
+        };
+        if c.pending_joins.remove(channel_login).is_none() {
+            return false;
+        }
+
+        tracing::warn!(
+            "JOIN to {} on connection {} was refused: {:?}",
+            channel_login,
+            source_connection_id,
+            reason
+        );
+        if let Some(channel_info) = self.channels.get_mut(channel_login) {
+            channel_info.last_failure = Some(JoinFailure::Rejected(reason.clone()));
+        }
+        self.abort_migration(channel_login);
+        self.resolve_join_waiters(
+            channel_login,
+            &Err(JoinError::Rejected {
+                reason: reason.clone(),
+                notice: Box::new(notice.clone()),
+            }),
+        );
+        true
+    }
+
+    /// Called when a `NOTICE` was received. If it reports that a message could not be sent,
+    /// the oldest pending message sent to that channel is rejected.
+    fn reject_delivery(&mut self, source_connection_id: usize, notice: &NoticeMessage) {
+        let (Some(channel_login), Some(reason)) =
+            (notice.channel_login.as_deref(), notice.notice_id.as_ref())
+        else {
+            return;
+        };
+        if !reason.is_message_rejection() {
+            return;
+        }
+
+        let Some(c) = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == source_connection_id)
+        else {
+            return;
+        };
+
+        let Some(pending_confirmation) = c.take_pending_confirmation(channel_login) else {
+            return;
+        };
+        self.undo_sent_message(pending_confirmation.sent_message_record);
+        if let Some(confirmation_sender) = pending_confirmation.confirmation_sender {
+            confirmation_sender
+                .send(Err(DeliveryError::Rejected {
+                    reason: reason.clone(),
+                    notice: Box::new(notice.clone()),
+                }))
+                .ok();
+        }
+    }
+
+    /// Called when a message was not accepted or not sent, see [`DuplicateMessageBypass::undo`].
+    fn undo_sent_message(&mut self, sent_message_record: Option<SentMessageRecord>) {
+        if let (Some(duplicate_message_bypass), Some(sent_message_record)) =
+            (self.duplicate_message_bypass.as_mut(), sent_message_record)
+        {
+            duplicate_message_bypass.undo(sent_message_record);
+        }
+    }
+
     fn on_incoming_message(
         &mut self,
         source_connection_id: usize,
         message: ConnectionIncomingMessage<T, L>,
     ) {
+        // a message that was not sent is answered even if its connection is closed already
+        let message = match message {
+            ConnectionIncomingMessage::PrivmsgNotSent(pending_confirmation, error) => {
+                if let Some(c) = self
+                    .connections
+                    .iter_mut()
+                    .find(|c| c.id == source_connection_id)
+                {
+                    c.unreported_privmsgs = c.unreported_privmsgs.saturating_sub(1);
+                }
+                let sent_message_record = pending_confirmation.fail(error);
+                self.undo_sent_message(sent_message_record);
+                return;
+            }
+            message => message,
+        };
+
+        // messages can still arrive from connections that were closed by the client already,
+        // e.g. because they were retired or the client was shut down
+        if !self
+            .connections
+            .iter()
+            .any(|c| c.id == source_connection_id)
+        {
+            // the answer to a message still written by such a connection is ignored
+            if let ConnectionIncomingMessage::SendingPrivmsg(pending_confirmation) = message {
+                let error = if self.shutdown_state == ShutdownState::Running {
+                    Error::RemoteUnexpectedlyClosedConnection
+                } else {
+                    Error::ClientShutDown
+                };
+                pending_confirmation.fail(error);
+            }
+            return;
+        }
+
         match message {
-            ConnectionIncomingMessage::IncomingMessage(message) => {
+            ConnectionIncomingMessage::IncomingMessage(message, permit) => {
                 let is_whisper = matches!(*message, ServerMessage::Whisper(_));
                 if is_whisper {
                     if let Some(current_whisper_connection_id) = self.current_whisper_connection_id
@@ -413,9 +1763,19 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                             .find(|c| c.id == source_connection_id)
                             .unwrap();
                         c.server_channels.insert(channel_login.clone());
+                        c.pending_joins.remove(channel_login);
+                        if let Some(channel_info) = self.channels.get_mut(channel_login) {
+                            channel_info.joined_at = Some(Instant::now());
+                        }
+                        self.resolve_join_waiters(channel_login, &Ok(()));
+                        self.finish_migration(channel_login);
 
                         // update metrics about channel numbers
                         self.update_metrics();
+                        self.emit_event(ClientEvent::ChannelJoined {
+                            connection_id: source_connection_id,
+                            channel_login: channel_login.clone(),
+                        });
                     }
                     ServerMessage::Part(PartMessage { channel_login, .. }) => {
                         // we successfully parted a channel
@@ -425,24 +1785,119 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                             .find(|c| c.id == source_connection_id)
                             .unwrap();
                         c.server_channels.remove(channel_login);
+                        // unless the channel was only moved to another connection
+                        if !self.channels.contains_key(channel_login)
+                            && let Some(rate_limiter) = self.privmsg_rate_limiter.as_mut()
+                        {
+                            rate_limiter.forget_channel(channel_login);
+                        }
+                        if self.migrations.get(channel_login) == Some(&source_connection_id) {
+                            self.migrations.remove(channel_login);
+                            self.retire_connection_if_empty(source_connection_id);
+                        }
 
                         // update metrics about channel numbers
                         self.update_metrics();
+                        self.emit_event(ClientEvent::ChannelParted {
+                            connection_id: source_connection_id,
+                            channel_login: channel_login.clone(),
+                        });
+                    }
+                    ServerMessage::Generic(_) if message.source().command == "001" => {
+                        // the welcome message is sent after a successful login
+                        let now = Instant::now();
+                        if let Some(c) = self
+                            .connections
+                            .iter_mut()
+                            .find(|c| c.id == source_connection_id)
+                        {
+                            c.healthy_since = Some(now);
+                        }
+                        if let Some(backoff) = self.reconnect_backoff.as_mut() {
+                            backoff.register_healthy(now);
+                        }
+                        self.login_failure_backoff.register_healthy(now);
+                    }
+                    ServerMessage::UserState(user_state) => {
+                        if let Some(channel_info) = self.channels.get_mut(&user_state.channel_login)
+                        {
+                            channel_info.user_state = Some(user_state.clone());
+                        }
+                        self.confirm_delivery(source_connection_id, user_state);
+                        if let Some(rate_limiter) = self.privmsg_rate_limiter.as_mut() {
+                            rate_limiter.update_user_state(user_state);
+                            // we might have become moderator, allowing messages to be sent earlier
+                            self.process_privmsg_queue();
+                        }
+                    }
+                    ServerMessage::RoomState(room_state) => {
+                        if let Some(channel_info) = self.channels.get_mut(&room_state.channel_login)
+                        {
+                            channel_info.room_state = Some(room_state.clone());
+                            match channel_info.merged_room_state.as_mut() {
+                                Some(merged_room_state) => merged_room_state.update(room_state),
+                                None => {
+                                    channel_info.merged_room_state = Some(room_state.into());
+                                }
+                            }
+                        }
+                        self.resolve_join_waiters(&room_state.channel_login, &Ok(()));
+                        if let Some(rate_limiter) = self.privmsg_rate_limiter.as_mut() {
+                            rate_limiter.update_room_state(room_state);
+                        }
+                    }
+                    ServerMessage::Notice(notice) => {
+                        self.on_notice(source_connection_id, notice);
                     }
                     _ => {}
                 }
 
-                self.client_incoming_messages_tx.send(*message).ok(); // ignore if the library user is not using the incoming messages
+                if self.is_duplicate_of_migrated_channel(source_connection_id, &message) {
+                    return;
+                }
+
+                let mut dropped = 0;
+                self.subscriptions.retain(|subscription| {
+                    dropped += subscription.deliver(&message);
+                    !subscription.is_closed()
+                });
+                dropped += self.client_incoming_messages_tx.send(*message);
+                // the message took the room reserved for it
+                drop(permit);
+                if dropped > 0 {
+                    tracing::trace!("Dropped {} incoming message(s), receiver is full", dropped);
+                    #[cfg(feature = "metrics-collection")]
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.incoming_messages_dropped.inc_by(dropped as f64);
+                    }
+                }
+            }
+            ConnectionIncomingMessage::SendingPrivmsg(mut pending_confirmation) => {
+                let c = self
+                    .connections
+                    .iter_mut()
+                    .find(|c| c.id == source_connection_id)
+                    .unwrap();
+                c.unreported_privmsgs = c.unreported_privmsgs.saturating_sub(1);
+                // the confirmation timeout starts once the message is actually sent
+                pending_confirmation.sent_at = Instant::now();
+                c.push_pending_confirmation(pending_confirmation);
+            }
+            ConnectionIncomingMessage::PrivmsgNotSent(..) => {
+                unreachable!("handled before the connection is looked up")
             }
-            #[cfg(feature = "metrics-collection")]
             ConnectionIncomingMessage::StateOpen => {
                 let c = self
                     .connections
                     .iter_mut()
                     .find(|c| c.id == source_connection_id)
                     .unwrap();
-                c.reported_state = ReportedConnectionState::Open;
+                c.reported_state = PoolConnectionState::Open;
+                c.opened_at = Some(Instant::now());
                 self.update_metrics();
+                self.emit_event(ClientEvent::ConnectionOpened {
+                    connection_id: source_connection_id,
+                });
             }
             ConnectionIncomingMessage::StateClosed { cause } => {
                 tracing::error!(
@@ -469,15 +1924,34 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 // also update twitch_irc_channels and twitch_irc_connections gauges
                 self.update_metrics();
 
-                // rejoin channels
-                tracing::debug!(
-                    "Pool connection {} previously was joined to {} channels ({:?}), rejoining them",
-                    source_connection_id,
-                    pool_connection.wanted_channels.len(),
-                    pool_connection.wanted_channels
-                );
-                for channel in pool_connection.wanted_channels.drain() {
-                    self.join(channel);
+                // a RECONNECT requested by Twitch is routine, not a failure
+                if !matches!(cause, Error::ReconnectCmd)
+                    && let Some(backoff) = self.reconnect_backoff.as_mut()
+                {
+                    backoff.register_failure(Instant::now(), pool_connection.healthy_since);
+                }
+                if matches!(cause, Error::LoginFailed(_)) {
+                    self.login_failure_backoff
+                        .register_failure(Instant::now(), None);
+                }
+                self.emit_event(ClientEvent::ConnectionClosed {
+                    connection_id: source_connection_id,
+                    cause: cause.clone(),
+                });
+
+                for channel_login in &pool_connection.wanted_channels {
+                    if let Some(channel_info) = self.channels.get_mut(channel_login) {
+                        channel_info.joined_at = None;
+                        channel_info.last_failure =
+                            Some(JoinFailure::ConnectionClosed(cause.to_string()));
+                    }
+                }
+
+                // messages sent over this connection can no longer be confirmed. Newest first,
+                // so the duplicate message bypass is undone back to the oldest message
+                for pending_confirmation in pool_connection.pending_confirmations.drain(..).rev() {
+                    let sent_message_record = pending_confirmation.fail(cause.clone());
+                    self.undo_sent_message(sent_message_record);
                 }
 
                 // remove it from role of "current whisper connection" if it was whisper conn before
@@ -489,6 +1963,30 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                     self.current_whisper_connection_id = None;
                 }
 
+                // the channels are not rejoined while the client is shutting down
+                if self.shutdown_state != ShutdownState::Running {
+                    return;
+                }
+
+                // rejoin channels
+                tracing::debug!(
+                    "Pool connection {} previously was joined to {} channels ({:?}), rejoining them",
+                    source_connection_id,
+                    pool_connection.wanted_channels.len(),
+                    pool_connection.wanted_channels
+                );
+                for channel in pool_connection.wanted_channels.drain() {
+                    // channels that were being moved to this connection stay where they were
+                    if let Some(from_id) = self.migrations.remove(&channel)
+                        && let Some(from) = self.connections.iter_mut().find(|c| c.id == from_id)
+                        && from.server_channels.contains(&channel)
+                    {
+                        from.wanted_channels.insert(channel);
+                        continue;
+                    }
+                    self.join(channel);
+                }
+
                 // make sure we stay connected in order to receive whispers
                 if self.connections.is_empty() {
                     let new_connection = self.make_new_connection();
@@ -499,6 +1997,28 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
         }
     }
 
+    /// While a channel is joined on two connections because it is being moved to another
+    /// connection, messages sent to it are received twice. The ones received on the old
+    /// connection are ignored.
+    fn is_duplicate_of_migrated_channel(
+        &self,
+        source_connection_id: usize,
+        message: &ServerMessage,
+    ) -> bool {
+        let Some(channel_login) = message.channel_login() else {
+            return false;
+        };
+        if self.migrations.get(channel_login) != Some(&source_connection_id) {
+            return false;
+        }
+        // received on the old connection. Only a duplicate if the new connection is joined
+        self.connections.iter().any(|c| {
+            c.id != source_connection_id
+                && c.wanted_channels.contains(channel_login)
+                && c.server_channels.contains(channel_login)
+        })
+    }
+
     #[cfg(feature = "metrics-collection")]
     fn update_metrics(&mut self) {
         if let Some(ref metrics) = self.metrics {
@@ -506,8 +2026,8 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 .connections
                 .iter()
                 .map(|c| match &c.reported_state {
-                    ReportedConnectionState::Initializing => (1i64, 0i64),
-                    ReportedConnectionState::Open => (0i64, 1i64),
+                    PoolConnectionState::Initializing => (1i64, 0i64),
+                    PoolConnectionState::Open => (0i64, 1i64),
                 })
                 // sum up all the tuples (like vectors)
                 .fold((0i64, 0i64), |(a, b), (c, d)| (a + c, b + d));
@@ -541,6 +2061,19 @@ impl<T: Transport, L: LoginCredentials> ClientLoopWorker<T, L> {
                 .channels
                 .with_label_values(&["server"])
                 .set(num_server);
+
+            let now = Instant::now();
+            let reconnect_backoff = self
+                .reconnect_backoff
+                .as_mut()
+                .map_or(Duration::ZERO, |backoff| backoff.current_delay(now))
+                .max(self.login_failure_backoff.current_delay(now));
+            metrics
+                .reconnect_backoff
+                .set(reconnect_backoff.as_secs_f64());
+
+            metrics.privmsgs_queued.set(self.privmsg_queue.len() as i64);
+            metrics.joins_queued.set(self.join_queue.len() as i64);
         }
     }
 
@@ -0,0 +1,446 @@
+use crate::message::ServerMessage;
+use std::collections::VecDeque;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+
+/// What a client created with [`TwitchIRCClient::new_bounded`](crate::TwitchIRCClient::new_bounded)
+/// does with incoming messages while its [`BoundedReceiver`] is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Stop reading from the connections until there is room again. No messages are dropped.
+    ///
+    /// Note that Twitch disconnects clients that do not read their messages for too long.
+    Block,
+    /// Drop the incoming message.
+    DropNewest,
+    /// Drop the oldest message in the receiver to make room for the incoming message.
+    DropOldest,
+    /// Prefer dropping messages of little value, namely `JOIN`, `PART`, `PING` and `PONG`
+    /// messages. If the incoming message is one of them, it is dropped. Otherwise, the oldest
+    /// one of them in the receiver is dropped. If the receiver holds none of them, the oldest
+    /// message in the receiver is dropped, like with [`DropOldest`](OverflowPolicy::DropOldest).
+    /// The capacity is never exceeded.
+    DropLowValue,
+}
+
+fn is_low_value(message: &ServerMessage) -> bool {
+    matches!(
+        message,
+        ServerMessage::Join(_)
+            | ServerMessage::Part(_)
+            | ServerMessage::Ping(_)
+            | ServerMessage::Pong(_)
+    )
+}
+
+#[derive(Debug)]
+struct QueueState {
+    messages: VecDeque<ServerMessage>,
+    /// room held by `IncomingPermit`s for messages that were read from a connection, but
+    /// not added yet.
+    reserved: usize,
+    /// the client has shut down, no more messages will be added.
+    sender_closed: bool,
+    /// the `BoundedReceiver` was dropped, nobody is interested in the messages anymore.
+    receiver_closed: bool,
+}
+
+/// The messages waiting to be received from a [`BoundedReceiver`].
+#[derive(Debug)]
+pub(crate) struct IncomingQueue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+    message_available: Notify,
+    space_available: Notify,
+    /// queues of the subscriptions that also need room before a connection may read the next
+    /// message, see [`IncomingQueue::new_subscription_queue`].
+    subscription_queues: Mutex<Vec<Arc<IncomingQueue>>>,
+}
+
+impl IncomingQueue {
+    pub fn new(capacity: usize, overflow_policy: OverflowPolicy) -> IncomingQueue {
+        IncomingQueue {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::with_capacity(capacity),
+                reserved: 0,
+                sender_closed: false,
+                receiver_closed: false,
+            }),
+            capacity,
+            overflow_policy,
+            message_available: Notify::new(),
+            space_available: Notify::new(),
+            subscription_queues: Mutex::new(vec![]),
+        }
+    }
+
+    /// Creates a queue for a subscription with the same capacity and overflow policy. With
+    /// [`OverflowPolicy::Block`], [`reserve`](Self::reserve) waits for room in it too.
+    pub fn new_subscription_queue(&self) -> Arc<IncomingQueue> {
+        let queue = Arc::new(IncomingQueue::new(self.capacity, self.overflow_policy));
+        if self.overflow_policy == OverflowPolicy::Block {
+            self.subscription_queues
+                .lock()
+                .unwrap()
+                .push(Arc::clone(&queue));
+        }
+        queue
+    }
+
+    fn is_closed(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.sender_closed || state.receiver_closed
+    }
+
+    /// Adds the message according to the overflow policy. Returns how many messages
+    /// were dropped to do so.
+    pub fn push(&self, message: ServerMessage) -> usize {
+        let mut state = self.state.lock().unwrap();
+        if state.receiver_closed {
+            return 0;
+        }
+
+        let mut dropped = 0;
+        if state.messages.len() < self.capacity {
+            state.messages.push_back(message);
+        } else {
+            match self.overflow_policy {
+                OverflowPolicy::Block => state.messages.push_back(message),
+                OverflowPolicy::DropNewest => dropped = 1,
+                OverflowPolicy::DropOldest => {
+                    state.messages.pop_front();
+                    state.messages.push_back(message);
+                    dropped = 1;
+                }
+                OverflowPolicy::DropLowValue => {
+                    if is_low_value(&message) {
+                        dropped = 1;
+                    } else {
+                        match state.messages.iter().position(is_low_value) {
+                            Some(index) => state.messages.remove(index),
+                            None => state.messages.pop_front(),
+                        };
+                        state.messages.push_back(message);
+                        dropped = 1;
+                    }
+                }
+            }
+        }
+        drop(state);
+
+        self.message_available.notify_one();
+        dropped
+    }
+
+    /// Waits until there is room for one more message in this queue and the queues of the
+    /// subscriptions, and reserves it until the returned permit is dropped. Queues whose client
+    /// or receiver is gone are skipped.
+    pub async fn reserve(self: &Arc<Self>) -> IncomingPermit {
+        // built up as the room is reserved, so the room is released if this is cancelled
+        let mut permit = IncomingPermit { queues: vec![] };
+        self.reserve_room().await;
+        permit.queues.push(Arc::clone(self));
+
+        let subscription_queues = {
+            let mut subscription_queues = self.subscription_queues.lock().unwrap();
+            subscription_queues.retain(|queue| !queue.is_closed());
+            subscription_queues.clone()
+        };
+        for queue in subscription_queues {
+            queue.reserve_room().await;
+            permit.queues.push(queue);
+        }
+        permit
+    }
+
+    async fn reserve_room(&self) {
+        loop {
+            let mut space_available = pin!(self.space_available.notified());
+            // register before checking, so room made in between is not missed
+            space_available.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.messages.len() + state.reserved < self.capacity
+                    || state.sender_closed
+                    || state.receiver_closed
+                {
+                    state.reserved += 1;
+                    return;
+                }
+            }
+            space_available.await;
+        }
+    }
+
+    fn try_pop(&self) -> Result<ServerMessage, TryRecvError> {
+        let mut state = self.state.lock().unwrap();
+        match state.messages.pop_front() {
+            Some(message) => {
+                drop(state);
+                self.space_available.notify_waiters();
+                Ok(message)
+            }
+            None if state.sender_closed => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    fn close_sender(&self) {
+        self.state.lock().unwrap().sender_closed = true;
+        self.message_available.notify_one();
+        self.space_available.notify_waiters();
+    }
+
+    fn close_receiver(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.receiver_closed = true;
+        state.messages.clear();
+        drop(state);
+        self.space_available.notify_waiters();
+    }
+}
+
+/// Room reserved in `IncomingQueue`s for a message on its way from a connection to the
+/// queues. The message is added (or discarded) before the permit is dropped.
+#[derive(Debug)]
+pub(crate) struct IncomingPermit {
+    queues: Vec<Arc<IncomingQueue>>,
+}
+
+impl Drop for IncomingPermit {
+    fn drop(&mut self) {
+        for queue in &self.queues {
+            queue.state.lock().unwrap().reserved -= 1;
+            queue.space_available.notify_waiters();
+        }
+    }
+}
+
+/// Receives the incoming messages of a client created with
+/// [`TwitchIRCClient::new_bounded`](crate::TwitchIRCClient::new_bounded), or of a
+/// [subscription](crate::TwitchIRCClient::subscribe) to such a client.
+///
+/// Works like a bounded [`mpsc::Receiver`]: It holds up to a fixed number of messages that
+/// have not been received yet, and the [`OverflowPolicy`] of the client decides what happens
+/// to incoming messages while it is full.
+#[derive(Debug)]
+pub struct BoundedReceiver {
+    queue: Arc<IncomingQueue>,
+}
+
+impl BoundedReceiver {
+    /// Receives the next message, waiting for one to arrive if necessary. Returns `None` once
+    /// the client has shut down and all remaining messages have been received.
+    pub async fn recv(&mut self) -> Option<ServerMessage> {
+        loop {
+            match self.queue.try_pop() {
+                Ok(message) => return Some(message),
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => self.queue.message_available.notified().await,
+            }
+        }
+    }
+
+    /// Receives the next message if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Result<ServerMessage, TryRecvError> {
+        self.queue.try_pop()
+    }
+
+    /// The number of messages waiting to be received.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queue.state.lock().unwrap().messages.len()
+    }
+
+    /// Whether there are no messages waiting to be received.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The maximum number of messages this receiver holds.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity
+    }
+}
+
+impl Drop for BoundedReceiver {
+    fn drop(&mut self) {
+        self.queue.close_receiver();
+    }
+}
+
+/// Where the client loop delivers the incoming messages to.
+#[derive(Debug)]
+pub(crate) enum IncomingMessagesSender {
+    Unbounded(mpsc::UnboundedSender<ServerMessage>),
+    Bounded(Arc<IncomingQueue>),
+}
+
+impl IncomingMessagesSender {
+    pub fn new_bounded(
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> (IncomingMessagesSender, BoundedReceiver) {
+        let queue = Arc::new(IncomingQueue::new(capacity, overflow_policy));
+        (
+            IncomingMessagesSender::Bounded(Arc::clone(&queue)),
+            BoundedReceiver { queue },
+        )
+    }
+
+    /// Creates the sender and receiver of a subscription to a bounded client, see
+    /// [`IncomingQueue::new_subscription_queue`].
+    pub fn new_bounded_subscription(
+        client_queue: &IncomingQueue,
+    ) -> (IncomingMessagesSender, BoundedReceiver) {
+        let queue = client_queue.new_subscription_queue();
+        (
+            IncomingMessagesSender::Bounded(Arc::clone(&queue)),
+            BoundedReceiver { queue },
+        )
+    }
+
+    /// Delivers the message. Returns how many messages were dropped because the receiver
+    /// was full.
+    pub fn send(&self, message: ServerMessage) -> usize {
+        match self {
+            // ignore if the library user is not using the incoming messages
+            IncomingMessagesSender::Unbounded(tx) => {
+                tx.send(message).ok();
+                0
+            }
+            IncomingMessagesSender::Bounded(queue) => queue.push(message),
+        }
+    }
+
+    /// Whether the receiver was dropped.
+    pub fn is_closed(&self) -> bool {
+        match self {
+            IncomingMessagesSender::Unbounded(tx) => tx.is_closed(),
+            IncomingMessagesSender::Bounded(queue) => queue.state.lock().unwrap().receiver_closed,
+        }
+    }
+
+    /// The queue of a bounded client, which its subscriptions are modelled after.
+    pub fn queue(&self) -> Option<Arc<IncomingQueue>> {
+        match self {
+            IncomingMessagesSender::Unbounded(_) => None,
+            IncomingMessagesSender::Bounded(queue) => Some(Arc::clone(queue)),
+        }
+    }
+
+    /// The queue the connections wait on before reading more messages, if the overflow policy
+    /// is [`OverflowPolicy::Block`].
+    pub fn backpressure(&self) -> Option<Arc<IncomingQueue>> {
+        match self {
+            IncomingMessagesSender::Bounded(queue)
+                if queue.overflow_policy == OverflowPolicy::Block =>
+            {
+                Some(Arc::clone(queue))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for IncomingMessagesSender {
+    fn drop(&mut self) {
+        if let IncomingMessagesSender::Bounded(queue) = self {
+            queue.close_sender();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::incoming::{IncomingMessagesSender, OverflowPolicy};
+    use crate::irc;
+    use crate::message::{IRCMessage, ServerMessage};
+    use tokio::sync::mpsc::error::TryRecvError;
+
+    fn generic(text: &str) -> ServerMessage {
+        ServerMessage::new_generic(irc!["FOO", text])
+    }
+
+    fn join() -> ServerMessage {
+        ServerMessage::try_from(
+            IRCMessage::parse(":randers!randers@randers.tmi.twitch.tv JOIN #pajlada").unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn received(receiver: &mut super::BoundedReceiver) -> Vec<String> {
+        let mut messages = vec![];
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(message.source().params[0].clone());
+        }
+        messages
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        let (sender, mut receiver) =
+            IncomingMessagesSender::new_bounded(2, OverflowPolicy::DropNewest);
+        assert_eq!(sender.send(generic("1")), 0);
+        assert_eq!(sender.send(generic("2")), 0);
+        assert_eq!(sender.send(generic("3")), 1);
+        assert_eq!(received(&mut receiver), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let (sender, mut receiver) =
+            IncomingMessagesSender::new_bounded(2, OverflowPolicy::DropOldest);
+        assert_eq!(sender.send(generic("1")), 0);
+        assert_eq!(sender.send(generic("2")), 0);
+        assert_eq!(sender.send(generic("3")), 1);
+        assert_eq!(received(&mut receiver), vec!["2", "3"]);
+    }
+
+    #[test]
+    fn test_drop_low_value() {
+        let (sender, mut receiver) =
+            IncomingMessagesSender::new_bounded(2, OverflowPolicy::DropLowValue);
+        assert_eq!(sender.send(generic("1")), 0);
+        assert_eq!(sender.send(join()), 0);
+        // the JOIN makes room
+        assert_eq!(sender.send(generic("2")), 1);
+        // incoming JOIN is dropped
+        assert_eq!(sender.send(join()), 1);
+        // nothing of little value left, the oldest message is dropped
+        assert_eq!(sender.send(generic("3")), 1);
+        assert_eq!(received(&mut receiver), vec!["2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_block() {
+        let (sender, mut receiver) = IncomingMessagesSender::new_bounded(1, OverflowPolicy::Block);
+        let queue = sender.backpressure().unwrap();
+        let permit = queue.reserve().await;
+        assert_eq!(sender.send(generic("1")), 0);
+        drop(permit);
+
+        let reserve_task = tokio::spawn(async move { queue.reserve().await });
+        tokio::task::yield_now().await;
+        assert!(!reserve_task.is_finished());
+
+        assert_eq!(receiver.recv().await.unwrap().source().params[0], "1");
+        reserve_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_closed_after_client_is_gone() {
+        let (sender, mut receiver) = IncomingMessagesSender::new_bounded(5, OverflowPolicy::Block);
+        sender.send(generic("1"));
+        drop(sender);
+        assert_eq!(receiver.recv().await.unwrap().source().params[0], "1");
+        assert!(receiver.recv().await.is_none());
+        assert_eq!(receiver.try_recv().unwrap_err(), TryRecvError::Disconnected);
+    }
+}
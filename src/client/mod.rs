@@ -1,15 +1,37 @@
 //! The chat client and its accompanying types.
 
+mod backoff;
+mod channel_status;
+mod duplicate_bypass;
+mod event;
 pub(crate) mod event_loop;
-mod pool_connection;
+pub(crate) mod incoming;
+pub(crate) mod pool_connection;
+mod pool_snapshot;
+mod priority;
+mod rate_limiter;
+mod split;
+mod subscription;
 
+pub use crate::client::channel_status::{ChannelStatus, JoinFailure, RoomState};
+pub use crate::client::event::ClientEvent;
+pub use crate::client::incoming::{BoundedReceiver, OverflowPolicy};
+pub use crate::client::pool_snapshot::{ConnectionSnapshot, PoolConnectionState};
+pub use crate::client::priority::MessagePriority;
+pub use crate::client::rate_limiter::JoinRateLimiter;
+pub use crate::client::subscription::{SubscriptionFilter, SubscriptionReceiver};
+
+use crate::client::duplicate_bypass::BYPASS_SUFFIX;
 use crate::client::event_loop::{ClientLoopCommand, ClientLoopWorker};
+use crate::client::incoming::{IncomingMessagesSender, IncomingQueue};
+use crate::client::split::split_message;
+use crate::client::subscription::Subscription;
 use crate::config::ClientConfig;
-use crate::error::Error;
+use crate::error::{DeliveryError, Error, JoinError, ShutdownTimeout};
 use crate::login::LoginCredentials;
 use crate::message::IRCTags;
 use crate::message::commands::ServerMessage;
-use crate::message::{IRCMessage, ReplyToMessage};
+use crate::message::{IRCMessage, ReplyToMessage, UserStateMessage};
 #[cfg(feature = "metrics-collection")]
 use crate::metrics::MetricsBundle;
 use crate::transport::Transport;
@@ -17,7 +39,12 @@ use crate::validate::validate_login;
 use crate::{irc, validate};
 use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// How many events are buffered for every subscriber of the client events, see
+/// [`TwitchIRCClient::subscribe_events`].
+const CLIENT_EVENTS_CAPACITY: usize = 1024;
 
 /// A send-only handle to control the Twitch IRC Client.
 #[derive(Debug)]
@@ -32,6 +59,11 @@ pub struct TwitchIRCClient<T: Transport, L: LoginCredentials> {
     // it always only holds a Weak<> and has to check whether the weak reference is still
     // valid before sending itself messages.
     client_loop_tx: Arc<mpsc::UnboundedSender<ClientLoopCommand<T, L>>>,
+    client_events_tx: broadcast::Sender<ClientEvent<T, L>>,
+    config: Arc<ClientConfig<L>>,
+    // the queue behind the `BoundedReceiver` of a client created with `new_bounded`, which the
+    // queues of subscriptions are modelled after.
+    incoming_queue: Option<Arc<IncomingQueue>>,
 }
 
 // we have to implement Debug and Clone manually, the derive macro places
@@ -40,6 +72,9 @@ impl<T: Transport, L: LoginCredentials> Clone for TwitchIRCClient<T, L> {
     fn clone(&self) -> Self {
         TwitchIRCClient {
             client_loop_tx: self.client_loop_tx.clone(),
+            client_events_tx: self.client_events_tx.clone(),
+            config: Arc::clone(&self.config),
+            incoming_queue: self.incoming_queue.clone(),
         }
     }
 }
@@ -55,32 +90,155 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         mpsc::UnboundedReceiver<ServerMessage>,
         TwitchIRCClient<T, L>,
     ) {
+        let (client_incoming_messages_tx, client_incoming_messages_rx) = mpsc::unbounded_channel();
+        let client = TwitchIRCClient::new_with_incoming_messages_sender(
+            config,
+            IncomingMessagesSender::Unbounded(client_incoming_messages_tx),
+        );
+        (client_incoming_messages_rx, client)
+    }
+
+    /// Create a new client from the given configuration, whose incoming messages are received
+    /// from a [`BoundedReceiver`] holding at most `capacity` messages. If the application falls
+    /// behind on receiving the messages, the `overflow_policy` decides whether the client stops
+    /// reading from the connections or drops messages. See [`OverflowPolicy`] for the options.
+    ///
+    /// With the `metrics-collection` feature, the dropped messages are counted by the
+    /// `twitchirc_incoming_messages_dropped` metric.
+    ///
+    /// Like [`new`](TwitchIRCClient::new), this spawns a background task.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is 0.
+    pub fn new_bounded(
+        config: ClientConfig<L>,
+        capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> (BoundedReceiver, TwitchIRCClient<T, L>) {
+        assert!(capacity > 0, "capacity must be greater than 0");
+        let (client_incoming_messages_tx, client_incoming_messages_rx) =
+            IncomingMessagesSender::new_bounded(capacity, overflow_policy);
+        let client =
+            TwitchIRCClient::new_with_incoming_messages_sender(config, client_incoming_messages_tx);
+        (client_incoming_messages_rx, client)
+    }
+
+    fn new_with_incoming_messages_sender(
+        config: ClientConfig<L>,
+        client_incoming_messages_tx: IncomingMessagesSender,
+    ) -> TwitchIRCClient<T, L> {
+        let mut config = config;
+        // commands are matched case-insensitively, and Twitch sends them in uppercase
+        if let Some(parsed_commands) = &mut config.parsed_commands {
+            *parsed_commands = parsed_commands
+                .drain()
+                .map(|command| command.to_ascii_uppercase())
+                .collect();
+        }
         let config = Arc::new(config);
         let (client_loop_tx, client_loop_rx) = mpsc::unbounded_channel();
         let client_loop_tx = Arc::new(client_loop_tx);
-        let (client_incoming_messages_tx, client_incoming_messages_rx) = mpsc::unbounded_channel();
+        let (client_events_tx, _) = broadcast::channel(CLIENT_EVENTS_CAPACITY);
 
         #[cfg(feature = "metrics-collection")]
         let metrics = MetricsBundle::new(&config.metrics_config);
+        let incoming_queue = client_incoming_messages_tx.queue();
 
         ClientLoopWorker::spawn(
-            config,
+            Arc::clone(&config),
             // the worker gets only a weak reference
             Arc::downgrade(&client_loop_tx),
             client_loop_rx,
             client_incoming_messages_tx,
+            client_events_tx.clone(),
             #[cfg(feature = "metrics-collection")]
             metrics,
         );
 
-        (
-            client_incoming_messages_rx,
-            TwitchIRCClient { client_loop_tx },
-        )
+        TwitchIRCClient {
+            client_loop_tx,
+            client_events_tx,
+            config,
+            incoming_queue,
+        }
     }
 }
 
 impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
+    /// Subscribe to events about the connections and channels of this client, e.g. to monitor
+    /// connection failures. See [`ClientEvent`] for the possible events.
+    ///
+    /// The returned receiver receives all events that happen after this call. Events are
+    /// buffered for every subscriber, but if a subscriber falls behind by more than 1024 events,
+    /// the oldest events are dropped and the receiver reports
+    /// [`RecvError::Lagged`](tokio::sync::broadcast::error::RecvError::Lagged).
+    pub fn subscribe_events(&self) -> broadcast::Receiver<ClientEvent<T, L>> {
+        self.client_events_tx.subscribe()
+    }
+
+    /// Receive the incoming messages selected by the given filter on a separate receiver,
+    /// e.g. to let different parts of an application each handle their own channels.
+    ///
+    /// The returned receiver gets a copy of every matching message that arrives after this
+    /// call. Messages are still delivered to the receiver returned by
+    /// [`TwitchIRCClient::new`] and to all other subscriptions. Drop the returned receiver to
+    /// end the subscription.
+    ///
+    /// On a client created with [`TwitchIRCClient::new_bounded`], the receiver holds as many
+    /// messages as the receiver of the client, and the same [`OverflowPolicy`] applies to it.
+    /// With [`OverflowPolicy::Block`], the client stops reading from the connections while any
+    /// of the receivers is full. Messages dropped from it are counted by the
+    /// `twitchirc_incoming_messages_dropped` metric too.
+    ///
+    /// Subscribing to a channel does not join it, use [`TwitchIRCClient::join`] for that.
+    ///
+    /// Returns a [`validate::Error`] if the filter is a [`SubscriptionFilter::Channel`] with a
+    /// channel login of [invalid format](crate::validate::validate_login).
+    pub fn subscribe(
+        &self,
+        filter: SubscriptionFilter,
+    ) -> Result<SubscriptionReceiver, validate::Error> {
+        if let SubscriptionFilter::Channel(channel_login) = &filter {
+            validate_login(channel_login)?;
+        }
+
+        let (sender, receiver) = match &self.incoming_queue {
+            Some(incoming_queue) => {
+                let (sender, receiver) =
+                    IncomingMessagesSender::new_bounded_subscription(incoming_queue);
+                (sender, SubscriptionReceiver::bounded(receiver))
+            }
+            None => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                (
+                    IncomingMessagesSender::Unbounded(sender),
+                    SubscriptionReceiver::unbounded(receiver),
+                )
+            }
+        };
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        self.client_loop_tx
+            .send(ClientLoopCommand::Subscribe {
+                subscription: Subscription { filter, sender },
+            })
+            .unwrap();
+        Ok(receiver)
+    }
+
+    /// Receive the incoming messages sent to the given channel on a separate receiver.
+    /// Shorthand for [`TwitchIRCClient::subscribe`] with a [`SubscriptionFilter::Channel`].
+    ///
+    /// This does not join the channel, use [`TwitchIRCClient::join`] for that.
+    ///
+    /// Returns a [`validate::Error`] if the passed `channel_login` is of
+    /// [invalid format](crate::validate::validate_login).
+    pub fn subscribe_channel(
+        &self,
+        channel_login: String,
+    ) -> Result<SubscriptionReceiver, validate::Error> {
+        self.subscribe(SubscriptionFilter::Channel(channel_login))
+    }
+
     /// Connect to Twitch IRC without joining any channels.
     ///
     /// **You typically do not need to call this method.** This is only provided for the rare
@@ -101,12 +259,40 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
 
     /// Send an arbitrary IRC message to one of the connections in the connection pool.
     ///
+    /// If enabled, `PRIVMSG`s are subject to the
+    /// [rate limiting](ClientConfig::privmsg_rate_limits) of outgoing chat messages, so the returned future can take a while to resolve if the
+    /// message has to wait before it can be sent.
+    ///
     /// An error is returned in case the message could not be sent over the picked connection.
     pub async fn send_message(&self, message: IRCMessage) -> Result<(), Error<T, L>> {
+        self.send_message_with_priority(message, MessagePriority::Normal)
+            .await
+    }
+
+    /// Send an arbitrary IRC message with the given priority.
+    ///
+    /// Messages waiting to be sent out over a connection are sent in the order of their
+    /// priority, so e.g. moderation actions sent with [`MessagePriority::High`] are not held
+    /// up by a flood of announcements sent with [`MessagePriority::Low`]. Messages with the
+    /// same priority are sent in order. `PRIVMSG`s held back by the
+    /// [rate limiting](ClientConfig::privmsg_rate_limits) are also sent in the order of their
+    /// priority, but the priority does not let them bypass the rate limiting. The `JOIN`s and
+    /// `PART`s sent by the client have high priority, so messages can't overtake the `JOIN`
+    /// to their channel.
+    ///
+    /// Low priority messages that wait longer than
+    /// [`ClientConfig::low_priority_message_expiry`] are dropped, and
+    /// [`Error::MessageExpired`] is returned.
+    pub async fn send_message_with_priority(
+        &self,
+        message: IRCMessage,
+        priority: MessagePriority,
+    ) -> Result<(), Error<T, L>> {
         let (return_tx, return_rx) = oneshot::channel();
         self.client_loop_tx
             .send(ClientLoopCommand::SendMessage {
                 message,
+                priority,
                 return_sender: return_tx,
             })
             .unwrap();
@@ -114,6 +300,28 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         return_rx.await.unwrap()
     }
 
+    /// Send multiple IRC messages in the given order. All messages are sent over the same
+    /// connection, so they can not be reordered by being sent over different connections.
+    ///
+    /// Returns the first error that occurs while sending the messages.
+    async fn send_messages(&self, messages: Vec<IRCMessage>) -> Result<(), Error<T, L>> {
+        let (messages, return_receivers): (Vec<_>, Vec<_>) = messages
+            .into_iter()
+            .map(|message| {
+                let (return_tx, return_rx) = oneshot::channel();
+                ((message, return_tx), return_rx)
+            })
+            .unzip();
+        self.client_loop_tx
+            .send(ClientLoopCommand::SendMessages { messages })
+            .unwrap();
+        for return_rx in return_receivers {
+            // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+            return_rx.await.unwrap()?;
+        }
+        Ok(())
+    }
+
     /// Send a `PRIVMSG`-type IRC message to a Twitch channel. The `message` can be a normal
     /// chat message or a chat command like `/ban` or similar. [Note however that the usage
     /// of chat commands via IRC is deprecated and scheduled to be removed by
@@ -121,6 +329,10 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ///
     /// If you want to just send a normal chat message, `say()` should be preferred since it
     /// prevents commands like `/ban` from accidentally being executed.
+    ///
+    /// If [`ClientConfig::privmsg_rate_limits`] is set, the message is held back until it can
+    /// be sent without exceeding the Twitch rate limits. The returned future resolves once the
+    /// message has been sent.
     pub async fn privmsg(&self, channel_login: String, message: String) -> Result<(), Error<T, L>> {
         self.send_message(irc!["PRIVMSG", format!("#{}", channel_login), message])
             .await
@@ -134,11 +346,81 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ///
     /// No particular filtering is performed on the message. If the message is too long for chat,
     /// it will not be cut short or split into multiple messages (what happens is determined
-    /// by the behaviour of the Twitch IRC server).
+    /// by the behaviour of the Twitch IRC server). Use [`say_split()`](TwitchIRCClient::say_split)
+    /// to split overlong messages into multiple messages.
     pub async fn say(&self, channel_login: String, message: String) -> Result<(), Error<T, L>> {
         self.privmsg(channel_login, format!(". {message}")).await
     }
 
+    /// Say a chat message in the given Twitch channel, with the given priority. See
+    /// [`send_message_with_priority()`](TwitchIRCClient::send_message_with_priority) for how
+    /// the priority is applied.
+    pub async fn say_with_priority(
+        &self,
+        channel_login: String,
+        message: String,
+        priority: MessagePriority,
+    ) -> Result<(), Error<T, L>> {
+        self.send_message_with_priority(
+            irc![
+                "PRIVMSG",
+                format!("#{}", channel_login),
+                format!(". {message}")
+            ],
+            priority,
+        )
+        .await
+    }
+
+    /// Say a chat message in the given Twitch channel, and wait for Twitch to confirm that the
+    /// message was accepted.
+    ///
+    /// Like [`say()`](TwitchIRCClient::say), but instead of resolving once the message has been
+    /// sent, the returned future resolves once Twitch has responded to the message:
+    ///
+    /// - Twitch responds to an accepted message with a `USERSTATE` for the channel that carries
+    ///   the ID of the message, in which case `Ok(())` is returned.
+    /// - If the message was not accepted (e.g. because the user is banned, or slow mode
+    ///   is enabled), Twitch responds with a `NOTICE`, and [`DeliveryError::Rejected`] is
+    ///   returned.
+    /// - If Twitch does not respond within the
+    ///   [configured timeout](ClientConfig::delivery_confirmation_timeout),
+    ///   [`DeliveryError::Timeout`] is returned. Note that Twitch does not respond to messages
+    ///   sent by anonymous users at all.
+    ///
+    /// Responses are matched to messages by channel and in the order the messages were sent.
+    /// The time spent waiting for the [rate limiter](ClientConfig::privmsg_rate_limits) does not
+    /// count towards the timeout.
+    pub async fn say_confirmed(
+        &self,
+        channel_login: String,
+        message: String,
+    ) -> Result<(), DeliveryError<T, L>> {
+        let message = irc![
+            "PRIVMSG",
+            format!("#{channel_login}"),
+            format!(". {message}")
+        ];
+        let (return_tx, return_rx) = oneshot::channel();
+        let (confirmation_tx, confirmation_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::SendConfirmedMessage {
+                message,
+                return_sender: return_tx,
+                confirmation_sender: confirmation_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap().map_err(DeliveryError::Send)?;
+
+        match tokio::time::timeout(self.config.delivery_confirmation_timeout, confirmation_rx).await
+        {
+            // the confirmation is only dropped without an answer if the client loop has ended
+            Ok(result) => result.unwrap_or(Err(DeliveryError::Send(Error::ClientShutDown))),
+            Err(_) => Err(DeliveryError::Timeout),
+        }
+    }
+
     /// Say a `/me` chat message in the given Twitch channel. These messages are usually
     /// shown in Twitch chat in italics or in the bot's name color, and without the colon
     /// normally separating name and message, e.g.:
@@ -159,7 +441,8 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ///
     /// No particular filtering is performed on the message. If the message is too long for chat,
     /// it will not be cut short or split into multiple messages (what happens is determined
-    /// by the behaviour of the Twitch IRC server).
+    /// by the behaviour of the Twitch IRC server). Use [`me_split()`](TwitchIRCClient::me_split)
+    /// to split overlong messages into multiple messages.
     pub async fn me(&self, channel_login: String, message: String) -> Result<(), Error<T, L>> {
         self.privmsg(channel_login, format!("/me {message}")).await
     }
@@ -174,7 +457,8 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ///
     /// No particular filtering is performed on the message. If the message is too long for chat,
     /// it will not be cut short or split into multiple messages (what happens is determined
-    /// by the behaviour of the Twitch IRC server).
+    /// by the behaviour of the Twitch IRC server). Use [`say_in_reply_to_split()`](TwitchIRCClient::say_in_reply_to_split)
+    /// to split overlong messages into multiple messages.
     ///
     /// The given parameter can be anything that implements [`ReplyToMessage`], which can
     /// be one of the following:
@@ -209,7 +493,8 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     ///
     /// No particular filtering is performed on the message. If the message is too long for chat,
     /// it will not be cut short or split into multiple messages (what happens is determined
-    /// by the behaviour of the Twitch IRC server).
+    /// by the behaviour of the Twitch IRC server). Use [`me_in_reply_to_split()`](TwitchIRCClient::me_in_reply_to_split)
+    /// to split overlong messages into multiple messages.
     ///
     /// The given parameter can be anything that implements [`ReplyToMessage`], which can
     /// be one of the following:
@@ -255,6 +540,114 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         self.send_message(irc_message).await
     }
 
+    /// Like [`say()`](TwitchIRCClient::say), but if the message is too long for chat, it is
+    /// split into multiple messages. The maximum length of each message and the marker appended
+    /// to all messages but the last one are configured via
+    /// [`ClientConfig::message_splitting`].
+    ///
+    /// Messages are split between words if possible, and between grapheme clusters if a single
+    /// word is too long for one message (so e.g. emoji are not split in the middle). A grapheme
+    /// cluster that is too long for one message by itself is split between characters.
+    /// Whitespace between the words is collapsed into single spaces if the message is split.
+    ///
+    /// The resulting messages are sent in order over the same connection, and the returned
+    /// future resolves once all messages are sent. The first error that occurs is returned.
+    ///
+    /// If the message is too long, but the configured maximum length leaves no room for any
+    /// text once the `". "` prefix, the continuation marker and the suffix of the
+    /// [duplicate message bypass](ClientConfig::duplicate_message_bypass) are taken into
+    /// account, or if the message consists only of whitespace, nothing is sent and
+    /// [`Error::MessageNotSplittable`] is returned.
+    pub async fn say_split(
+        &self,
+        channel_login: String,
+        message: String,
+    ) -> Result<(), Error<T, L>> {
+        self.privmsg_split(&channel_login, ". ", &message, None)
+            .await
+    }
+
+    /// Like [`me()`](TwitchIRCClient::me), but splits overlong messages into multiple
+    /// messages. See [`say_split()`](TwitchIRCClient::say_split) for details.
+    pub async fn me_split(
+        &self,
+        channel_login: String,
+        message: String,
+    ) -> Result<(), Error<T, L>> {
+        self.privmsg_split(&channel_login, "/me ", &message, None)
+            .await
+    }
+
+    /// Like [`say_in_reply_to()`](TwitchIRCClient::say_in_reply_to), but splits overlong
+    /// messages into multiple messages. All of the messages are sent in reply to the given
+    /// message. See [`say_split()`](TwitchIRCClient::say_split) for details.
+    pub async fn say_in_reply_to_split(
+        &self,
+        reply_to: &impl ReplyToMessage,
+        message: String,
+    ) -> Result<(), Error<T, L>> {
+        self.privmsg_split(
+            reply_to.channel_login(),
+            ". ",
+            &message,
+            Some(reply_to.message_id()),
+        )
+        .await
+    }
+
+    /// Like [`me_in_reply_to()`](TwitchIRCClient::me_in_reply_to), but splits overlong
+    /// messages into multiple messages. All of the messages are sent in reply to the given
+    /// message. See [`say_split()`](TwitchIRCClient::say_split) for details.
+    pub async fn me_in_reply_to_split(
+        &self,
+        reply_to: &impl ReplyToMessage,
+        message: String,
+    ) -> Result<(), Error<T, L>> {
+        self.privmsg_split(
+            reply_to.channel_login(),
+            "/me ",
+            &message,
+            Some(reply_to.message_id()),
+        )
+        .await
+    }
+
+    async fn privmsg_split(
+        &self,
+        channel_login: &str,
+        prefix: &str,
+        message: &str,
+        reply_parent_message_id: Option<&str>,
+    ) -> Result<(), Error<T, L>> {
+        let splitting = &self.config.message_splitting;
+        let mut max_length = splitting
+            .max_message_length
+            .saturating_sub(prefix.chars().count());
+        if self.config.duplicate_message_bypass {
+            // leave room for the suffix the bypass might append
+            max_length = max_length.saturating_sub(BYPASS_SUFFIX.chars().count());
+        }
+
+        let messages = split_message(message, max_length, &splitting.continuation_marker)
+            .ok_or(Error::MessageNotSplittable)?
+            .into_iter()
+            .map(|chunk| {
+                let mut tags = IRCTags::new();
+                if let Some(message_id) = reply_parent_message_id {
+                    tags.0
+                        .insert("reply-parent-msg-id".to_owned(), message_id.to_owned());
+                }
+                IRCMessage::new(
+                    tags,
+                    None,
+                    "PRIVMSG".to_owned(),
+                    vec![format!("#{channel_login}"), format!("{prefix}{chunk}")],
+                )
+            })
+            .collect();
+        self.send_messages(messages).await
+    }
+
     /// Join the given Twitch channel (When a channel is joined, the client will receive messages
     /// sent to it).
     ///
@@ -275,8 +668,10 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
     /// However if the server *did not* confirm the successful `JOIN` command previously, then the
     /// `JOIN` is attempted again.
     ///
-    /// You can use this mechanism to e.g. periodically re-try `JOIN`ing a given channel if
-    /// joining to freshly created channels or freshly renamed channels is a concern in your application.
+    /// If [`join_retry_interval`](ClientConfig::join_retry_interval) is set, the client also
+    /// does this on its own: a `JOIN` that is not confirmed within
+    /// [`join_timeout`](ClientConfig::join_timeout) is retried every `join_retry_interval`. Use
+    /// [`join_and_wait()`](TwitchIRCClient::join_and_wait) to wait for the confirmation.
     ///
     /// Another note on Twitch behaviour: If a channel gets suspended, the `JOIN` membership stays
     /// active as long as the connection with that `JOIN` membership stays active. For this reason,
@@ -300,6 +695,39 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         Ok(())
     }
 
+    /// Join the given Twitch channel, and wait for the server to confirm the `JOIN`.
+    ///
+    /// This works like [`join()`](TwitchIRCClient::join), but resolves once the server
+    /// echoes the `JOIN` (or sends the `ROOMSTATE` for the channel), or right away if the
+    /// channel is already confirmed to be joined. Fails with [`JoinError::Rejected`] if Twitch
+    /// refuses the `JOIN` with a `msg_channel_suspended` or `msg_banned` notice, and with
+    /// [`JoinError::Timeout`] if there is no answer within `timeout`.
+    ///
+    /// If this fails, the channel still stays wanted, so the client keeps
+    /// [retrying the `JOIN`](ClientConfig::join_retry_interval) if that is enabled. Use
+    /// [`part()`](TwitchIRCClient::part) to give up on the channel.
+    pub async fn join_and_wait(
+        &self,
+        channel_login: String,
+        timeout: Duration,
+    ) -> Result<(), JoinError> {
+        validate_login(&channel_login)?;
+
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::JoinAndWait {
+                channel_login,
+                return_sender: return_tx,
+            })
+            .unwrap();
+
+        match tokio::time::timeout(timeout, return_rx).await {
+            // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+            Ok(result) => result.unwrap(),
+            Err(_) => Err(JoinError::Timeout),
+        }
+    }
+
     /// Instruct the client to only be connected to these channels. Channels currently joined
     /// but not in the given set are parted, and channels in the set that are not currently
     /// joined are joined.
@@ -320,30 +748,13 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         Ok(())
     }
 
-    /// Query the client for what status a certain channel is in.
-    ///
-    /// Returns two booleans: The first indicates whether a channel is `wanted`. This is true
-    /// if the last operation for this channel was a `join()` method, or alternatively whether
-    /// it was included in the set of channels in a `set_wanted_channels` call.
-    ///
-    /// The second boolean indicates whether this channel is currently joined server-side.
-    /// (This is purely based on `JOIN` and `PART` messages being received from the server).
-    ///
-    /// Note that any combination of `true` and `false` is possible here.
-    ///
-    /// For example, `(true, false)` could indicate that the `JOIN` message to join this channel is currently
-    /// being sent or already sent, but no response confirming the `JOIN` has been received yet.
-    /// **Note this status can also mean that the server did not answer the `JOIN` request because
-    /// the channel did not exist/was suspended or similar conditions.**
-    ///
-    /// `(false, true)` might on the other hand (similarly) that a `PART` message is sent but not
-    /// answered yet by the server.
+    /// Query the client for what status a certain channel is in. See [`ChannelStatus`] for
+    /// the details that are reported.
     ///
-    /// `(true, true)` confirms that the channel is currently successfully joined in a normal fashion.
-    ///
-    /// `(false, false)` is returned for a channel that has not been joined previously at all
-    /// or where a previous `PART` command has completed.
-    pub async fn get_channel_status(&self, channel_login: String) -> (bool, bool) {
+    /// A channel that is both `wanted` and `joined` is currently successfully joined in a
+    /// normal fashion. A channel that is neither has not been joined previously at all, or a
+    /// previous `PART` command has completed.
+    pub async fn get_channel_status(&self, channel_login: String) -> ChannelStatus {
         // channel_login format sanity check not really needed here, the code will deal with arbitrary strings just fine
 
         let (return_tx, return_rx) = oneshot::channel();
@@ -357,6 +768,86 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         return_rx.await.unwrap()
     }
 
+    /// Query the status of every channel that is wanted or joined, ordered by channel login.
+    /// See [`get_channel_status()`](TwitchIRCClient::get_channel_status) for details.
+    pub async fn list_channels(&self) -> Vec<ChannelStatus> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::ListChannels {
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Query the settings of a wanted channel, merged from all `ROOMSTATE`s received for it.
+    /// Returns `None` if the channel is not wanted or no `ROOMSTATE` was received for it yet.
+    pub async fn room_state(&self, channel_login: String) -> Option<RoomState> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetRoomState {
+                channel_login,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Query the state of the logged in user (badges, name color and emote sets) in a wanted
+    /// channel, as reported by the latest `USERSTATE` received for it. Returns `None` if the
+    /// channel is not wanted or no `USERSTATE` was received for it yet (e.g. because the client
+    /// is logged in anonymously).
+    pub async fn user_state(&self, channel_login: String) -> Option<UserStateMessage> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetUserState {
+                channel_login,
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Query the state of every connection of the connection pool, ordered by connection ID.
+    /// This is intended for monitoring and debugging, e.g. to check how the channels are
+    /// distributed across the connections.
+    pub async fn pool_snapshot(&self) -> Vec<ConnectionSnapshot> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::GetPoolSnapshot {
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+
+    /// Rebalance the connections: channels are moved off under-filled connections, so that
+    /// as few connections as possible are needed, and connections left without channels are
+    /// closed. The last connection is always kept open to receive whispers.
+    ///
+    /// To avoid missing messages, a channel is first joined on its new connection, and only
+    /// parted on its old connection once the `JOIN` is confirmed (messages received twice in
+    /// the meantime are only forwarded once). If the `JOIN` is not confirmed in time, the
+    /// channel stays on the old connection. Connections with channels that are not confirmed
+    /// to be joined are left alone.
+    ///
+    /// The future resolves once the channels have been scheduled to be moved. This can also be
+    /// done periodically in the background, see [`ClientConfig::rebalance_interval`].
+    pub async fn rebalance(&self) {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::Rebalance {
+                return_sender: Some(return_tx),
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap();
+    }
+
     /// Part (leave) a channel, to stop receiving messages sent to that channel.
     ///
     /// This has the same semantics as `join()`. Similarly, a `part()` call will have no effect
@@ -382,4 +873,1508 @@ impl<T: Transport, L: LoginCredentials> TwitchIRCClient<T, L> {
         // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
         return_rx.await.unwrap()
     }
+
+    /// Gracefully shut down the client.
+    ///
+    /// Messages that are still queued (e.g. by the [rate limiter](ClientConfig::privmsg_rate_limits))
+    /// are sent out first. Then all channels are parted, a `QUIT` is sent on every connection
+    /// and the connections are closed. The future resolves once all connections have been
+    /// closed and their background tasks have ended.
+    ///
+    /// If this does not finish within the [configured timeout](ClientConfig::shutdown_timeout),
+    /// messages that are still queued are failed, the remaining connections are closed
+    /// forcibly and [`ShutdownTimeout`] is returned.
+    ///
+    /// After calling this method, the client no longer joins channels or opens connections,
+    /// and sending messages fails with [`Error::ClientShutDown`]. Calling this method again
+    /// waits for the same shutdown to finish.
+    pub async fn shutdown(&self) -> Result<(), ShutdownTimeout> {
+        let (return_tx, return_rx) = oneshot::channel();
+        self.client_loop_tx
+            .send(ClientLoopCommand::Shutdown {
+                return_sender: return_tx,
+            })
+            .unwrap();
+        // unwrap: ClientLoopWorker should not die before all sender handles have been dropped
+        return_rx.await.unwrap()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::TwitchIRCClient;
+    use crate::client::{
+        ClientEvent, JoinFailure, JoinRateLimiter, MessagePriority, OverflowPolicy,
+        PoolConnectionState, RoomState, SubscriptionFilter, SubscriptionReceiver,
+    };
+    use crate::error::{DeliveryError, Error, JoinError, ShutdownTimeout};
+    use crate::login::{CredentialsPair, LoginCredentials, StaticLoginCredentials};
+    use crate::message::{FollowersOnlyMode, IRCMessage, NoticeId, ServerMessage};
+    use crate::transport::mock::{FakeTwitchServer, MockTransport};
+    use crate::{ClientConfig, PrivmsgRateLimits, ReconnectBackoff, irc};
+    use async_trait::async_trait;
+    use std::collections::HashSet;
+    use std::convert::Infallible;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::{Semaphore, mpsc};
+    use tokio::time::{Duration, Instant};
+
+    pub(crate) type MockClient = TwitchIRCClient<MockTransport, StaticLoginCredentials>;
+
+    pub(crate) fn test_config(server: &FakeTwitchServer) -> ClientConfig<StaticLoginCredentials> {
+        ClientConfig {
+            server_endpoint: server.endpoint(),
+            // several clients registering into the default registry would collide
+            #[cfg(feature = "metrics-collection")]
+            metrics_config: crate::MetricsConfig::Disabled,
+            ..ClientConfig::new_simple(StaticLoginCredentials::new(
+                "randers".to_owned(),
+                Some("token".to_owned()),
+            ))
+        }
+    }
+
+    pub(crate) fn new_client(
+        server: &FakeTwitchServer,
+    ) -> (mpsc::UnboundedReceiver<ServerMessage>, MockClient) {
+        MockClient::new(test_config(server))
+    }
+
+    pub(crate) async fn wait_for_join(
+        incoming_messages: &mut mpsc::UnboundedReceiver<ServerMessage>,
+        channel_login: &str,
+    ) {
+        while let Some(message) = incoming_messages.recv().await {
+            if let ServerMessage::Join(msg) = message
+                && msg.channel_login == channel_login
+            {
+                return;
+            }
+        }
+        panic!("incoming messages ended before JOIN to {channel_login} was received");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_join() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = new_client(&server);
+
+        let status = client.get_channel_status("pajlada".to_owned()).await;
+        assert_eq!((status.wanted, status.joined), (false, false));
+        client.join("pajlada".to_owned()).unwrap();
+        let status = client.get_channel_status("pajlada".to_owned()).await;
+        assert_eq!((status.wanted, status.joined), (true, false));
+
+        let mut connection = server.accept().await;
+        let join = connection.recv_command("JOIN").await.unwrap();
+        assert_eq!(join.params, vec!["#pajlada".to_owned()]);
+
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+        let status = client.get_channel_status("pajlada".to_owned()).await;
+        assert_eq!((status.wanted, status.joined), (true, true));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_channel_status() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = new_client(&server);
+        server.set_channel_suspended("suspended", true);
+
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("suspended".to_owned()).unwrap();
+        let mut connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+
+        let status = client.get_channel_status("pajlada".to_owned()).await;
+        assert!(status.wanted && status.joined);
+        assert_eq!(status.connection_id, Some(0));
+        assert_eq!(status.join_attempts, 1);
+        assert!(status.join_requested_at.unwrap() <= status.joined_at.unwrap());
+        assert_eq!(status.last_failure, None);
+        assert_eq!(status.room_state.unwrap().channel_login, "pajlada");
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        let status = client.get_channel_status("suspended".to_owned()).await;
+        assert!(status.wanted && !status.joined);
+        assert_eq!(status.joined_at, None);
+        assert_eq!(status.last_failure, Some(JoinFailure::TimedOut));
+
+        connection.disconnect();
+        let _connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+        let channels = client.list_channels().await;
+        assert_eq!(
+            channels
+                .iter()
+                .map(|status| status.channel_login.as_str())
+                .collect::<Vec<_>>(),
+            vec!["pajlada", "suspended"]
+        );
+        let status = &channels[0];
+        assert_eq!(status.connection_id, Some(1));
+        assert_eq!(status.join_attempts, 2);
+        assert_eq!(
+            status.last_failure,
+            Some(JoinFailure::ConnectionClosed(
+                "Remote server unexpectedly closed connection".to_owned()
+            ))
+        );
+
+        client.part("suspended".to_owned());
+        let status = client.get_channel_status("suspended".to_owned()).await;
+        assert_eq!(status.join_attempts, 0);
+        assert_eq!(client.list_channels().await.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_room_and_user_state() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = new_client(&server);
+        assert_eq!(client.room_state("pajlada".to_owned()).await, None);
+
+        client.join("pajlada".to_owned()).unwrap();
+        let connection = server.accept().await;
+        let mut wait_for = async |command: &str| {
+            while let Some(message) = incoming_messages.recv().await {
+                if message.source().command == command {
+                    return;
+                }
+            }
+            panic!("incoming messages ended before {command} was received");
+        };
+        wait_for("ROOMSTATE").await;
+
+        let room_state = client.room_state("pajlada".to_owned()).await.unwrap();
+        assert_eq!(room_state.slow_mode, Some(Duration::ZERO));
+        assert_eq!(room_state.followers_only, Some(FollowersOnlyMode::Disabled));
+
+        connection.send_raw(&format!(
+            "@room-id={};slow=10 :tmi.twitch.tv ROOMSTATE #pajlada",
+            room_state.channel_id
+        ));
+        wait_for("ROOMSTATE").await;
+        let updated_room_state = client.room_state("pajlada".to_owned()).await.unwrap();
+        assert_eq!(
+            updated_room_state,
+            RoomState {
+                slow_mode: Some(Duration::from_secs(10)),
+                ..room_state
+            }
+        );
+
+        let user_state = client.user_state("pajlada".to_owned()).await.unwrap();
+        assert!(user_state.badges.is_empty());
+        connection.set_badges("pajlada", "moderator/1");
+        wait_for("USERSTATE").await;
+        let user_state = client.user_state("pajlada".to_owned()).await.unwrap();
+        assert_eq!(user_state.badges[0].name, "moderator");
+
+        client.part("pajlada".to_owned());
+        assert_eq!(client.room_state("pajlada".to_owned()).await, None);
+        assert_eq!(client.user_state("pajlada".to_owned()).await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_parsed_commands() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            // not case-sensitive
+            parsed_commands: Some(HashSet::from(["ClearChat".to_owned()])),
+            ..test_config(&server)
+        });
+
+        client.join("pajlada".to_owned()).unwrap();
+        let connection = server.accept().await;
+        // JOIN is always parsed
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+
+        connection.send_raw(
+            "@badge-info=;badges=;color=#0000FF;display-name=JuN1oRRRR;emotes=;flags=;id=e9d998c3-36f1-430f-89ec-6b887c28af36;mod=0;room-id=11148817;subscriber=0;tmi-sent-ts=1594545155039;turbo=0;user-id=29803735;user-type= :jun1orrrr!jun1orrrr@jun1orrrr.tmi.twitch.tv PRIVMSG #pajlada :dank cam",
+        );
+        connection.send_raw("@room-id=1;tmi-sent-ts=1 :tmi.twitch.tv CLEARCHAT #pajlada");
+
+        let mut wait_for = async |command: &str| loop {
+            let message = incoming_messages.recv().await.unwrap();
+            if message.source().command == command {
+                return message;
+            }
+        };
+        let privmsg = wait_for("PRIVMSG").await;
+        assert!(!matches!(privmsg, ServerMessage::Privmsg(_)));
+        assert!(matches!(
+            ServerMessage::try_from(IRCMessage::from(privmsg)),
+            Ok(ServerMessage::Privmsg(_))
+        ));
+        assert!(matches!(
+            wait_for("CLEARCHAT").await,
+            ServerMessage::ClearChat(_)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_subscriptions() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = new_client(&server);
+        let mut pajlada = client.subscribe_channel("pajlada".to_owned()).unwrap();
+        let mut foos = client
+            .subscribe(SubscriptionFilter::Command("FOO".to_owned()))
+            .unwrap();
+        let mut replies = client
+            .subscribe(SubscriptionFilter::predicate(|message| {
+                message.source().params.get(1).is_some_and(|p| p == "reply")
+            }))
+            .unwrap();
+        drop(client.subscribe_channel("forsen".to_owned()));
+        // channel logins are validated like for join()
+        assert!(client.subscribe_channel("#pajlada".to_owned()).is_err());
+        assert!(
+            client
+                .subscribe(SubscriptionFilter::Channel("Pajlada".to_owned()))
+                .is_err()
+        );
+
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("forsen".to_owned()).unwrap();
+        let connection = server.accept().await;
+        connection.send_raw(":tmi.twitch.tv FOO #pajlada :1");
+        connection.send_raw(":tmi.twitch.tv BAR #pajlada :reply");
+        connection.send_raw(":tmi.twitch.tv FOO #forsen :3");
+        connection.send_raw(":tmi.twitch.tv BAR #forsen :4");
+        connection.send_raw(":tmi.twitch.tv DONE");
+        // subscribers receive a message before the main receiver does
+        while incoming_messages.recv().await.unwrap().source().command != "DONE" {}
+
+        let received = |receiver: &mut SubscriptionReceiver| {
+            let mut messages = vec![];
+            while let Ok(message) = receiver.try_recv() {
+                let source = message.source();
+                if source.command == "FOO" || source.command == "BAR" {
+                    messages.push(format!("{} {}", source.params[0], source.params[1]));
+                }
+            }
+            messages
+        };
+        assert_eq!(received(&mut pajlada), vec!["#pajlada 1", "#pajlada reply"]);
+        assert_eq!(received(&mut foos), vec!["#pajlada 1", "#forsen 3"]);
+        assert_eq!(received(&mut replies), vec!["#pajlada reply"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bounded_incoming_messages() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) =
+            MockClient::new_bounded(test_config(&server), 2, OverflowPolicy::Block);
+
+        client.join("pajlada".to_owned()).unwrap();
+        let connection = server.accept().await;
+        for i in 0..20 {
+            connection.send_raw(&format!(":tmi.twitch.tv FOO #pajlada :{i}"));
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        // the connection stopped reading while the receiver was full
+        assert_eq!(incoming_messages.len(), 2);
+
+        let mut received = vec![];
+        while received.len() < 20 {
+            let message = incoming_messages.recv().await.unwrap();
+            if message.source().command == "FOO" {
+                received.push(message.source().params[1].clone());
+            }
+        }
+        assert_eq!(received, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bounded_subscriptions() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) =
+            MockClient::new_bounded(test_config(&server), 2, OverflowPolicy::Block);
+        let mut foos = client
+            .subscribe(SubscriptionFilter::Command("FOO".to_owned()))
+            .unwrap();
+        // the main receiver does not hold up the connection
+        tokio::spawn(async move { while incoming_messages.recv().await.is_some() {} });
+
+        client.join("pajlada".to_owned()).unwrap();
+        let connection = server.accept().await;
+        for i in 0..20 {
+            connection.send_raw(&format!(":tmi.twitch.tv FOO #pajlada :{i}"));
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        // the connection stopped reading while the subscription was full
+        assert_eq!(foos.len(), 2);
+
+        let mut received = vec![];
+        while received.len() < 20 {
+            received.push(foos.recv().await.unwrap().source().params[1].clone());
+        }
+        assert_eq!(received, (0..20).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pool_snapshot() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            max_channels_per_connection: 2,
+            channel_batch_window: Some(Duration::from_millis(50)),
+            ..test_config(&server)
+        });
+
+        client.join("forsen".to_owned()).unwrap();
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("sodapoppin".to_owned()).unwrap();
+        let snapshot = client.pool_snapshot().await;
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].state, PoolConnectionState::Initializing);
+        assert_eq!(snapshot[0].uptime, None);
+
+        let _first_connection = server.accept().await;
+        let second_connection = server.accept().await;
+        for _ in 0..3 {
+            while !matches!(incoming_messages.recv().await, Some(ServerMessage::Join(_))) {}
+        }
+        let start = Instant::now();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        second_connection.send_raw(
+            "@badges=;color=#19E6E6;display-name=randers;emotes=;message-id=1;thread-id=40286300_553170741;turbo=0;user-id=40286300;user-type= :randers!randers@randers.tmi.twitch.tv WHISPER randers :hello",
+        );
+        while !matches!(
+            incoming_messages.recv().await,
+            Some(ServerMessage::Whisper(_))
+        ) {}
+
+        let snapshot = client.pool_snapshot().await;
+        assert_eq!(
+            snapshot
+                .iter()
+                .map(|c| (c.connection_id, c.state, c.wanted_channels.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    0,
+                    PoolConnectionState::Open,
+                    vec!["forsen".to_owned(), "pajlada".to_owned()]
+                ),
+                (1, PoolConnectionState::Open, vec!["sodapoppin".to_owned()]),
+            ]
+        );
+        assert_eq!(snapshot[1].server_channels, vec!["sodapoppin".to_owned()]);
+        assert!(snapshot[0].uptime.unwrap() >= start.elapsed());
+        // both JOINs were sent in one batch
+        assert_eq!(snapshot[0].recent_messages_sent, 1);
+        assert!(!snapshot[0].is_whisper_connection);
+        assert!(snapshot[1].is_whisper_connection);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rebalance() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            max_channels_per_connection: 2,
+            ..test_config(&server)
+        });
+        let mut events = client.subscribe_events();
+
+        client.join("forsen".to_owned()).unwrap();
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("sodapoppin".to_owned()).unwrap();
+        let mut first_connection = server.accept().await;
+        let mut second_connection = server.accept().await;
+        for _ in 0..3 {
+            while !matches!(incoming_messages.recv().await, Some(ServerMessage::Join(_))) {}
+        }
+        client.part("pajlada".to_owned());
+        while !matches!(incoming_messages.recv().await, Some(ServerMessage::Part(_))) {}
+
+        client.rebalance().await;
+        // the JOINs sent before rebalancing come first
+        while first_connection.recv_command("JOIN").await.unwrap().params
+            != vec!["#sodapoppin".to_owned()]
+        {}
+        let part = second_connection.recv_command("PART").await.unwrap();
+        assert_eq!(part.params, vec!["#sodapoppin".to_owned()]);
+        second_connection.recv_command("QUIT").await.unwrap();
+        assert!(second_connection.recv().await.is_none());
+
+        loop {
+            if let ClientEvent::ConnectionRetired { connection_id } = events.recv().await.unwrap() {
+                assert_eq!(connection_id, 1);
+                break;
+            }
+        }
+        let snapshot = client.pool_snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].connection_id, 0);
+        assert_eq!(
+            snapshot[0].wanted_channels,
+            vec!["forsen".to_owned(), "sodapoppin".to_owned()]
+        );
+        assert_eq!(snapshot[0].server_channels, snapshot[0].wanted_channels);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_messages_from_retired_connection() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            max_channels_per_connection: 2,
+            ..test_config(&server)
+        });
+        let mut events = client.subscribe_events();
+
+        client.join("forsen".to_owned()).unwrap();
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("sodapoppin".to_owned()).unwrap();
+        let _first_connection = server.accept().await;
+        let mut second_connection = server.accept().await;
+        for _ in 0..3 {
+            while !matches!(incoming_messages.recv().await, Some(ServerMessage::Join(_))) {}
+        }
+        client.part("pajlada".to_owned());
+        while !matches!(incoming_messages.recv().await, Some(ServerMessage::Part(_))) {}
+
+        let rebalance = tokio::spawn({
+            let client = client.clone();
+            async move { client.rebalance().await }
+        });
+        // Twitch closes the connection right after answering the PART that makes the client
+        // retire it, so the connection reports being closed after it was retired. This runs in
+        // a separate task to send the RECONNECT before the client has processed the PART.
+        let reconnect = tokio::spawn(async move {
+            second_connection.recv_command("PART").await.unwrap();
+            second_connection.send_reconnect();
+            second_connection
+        });
+
+        rebalance.await.unwrap();
+        let _second_connection = reconnect.await.unwrap();
+        loop {
+            if let ClientEvent::ConnectionRetired { connection_id } = events.recv().await.unwrap() {
+                assert_eq!(connection_id, 1);
+                break;
+            }
+        }
+        let snapshot = client.pool_snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(
+            snapshot[0].wanted_channels,
+            vec!["forsen".to_owned(), "sodapoppin".to_owned()]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_closes_idle_connections() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            max_waiting_messages_per_connection: 1,
+            idle_connection_timeout: Some(Duration::from_secs(60)),
+            ..test_config(&server)
+        });
+        let mut events = client.subscribe_events();
+
+        // the JOIN makes the first connection busy, so a second one is made for the PING
+        client.join("forsen".to_owned()).unwrap();
+        client
+            .send_message(irc!["PING", "tmi.twitch.tv"])
+            .await
+            .unwrap();
+        let mut first_connection = server.accept().await;
+        let mut second_connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "forsen").await;
+        assert_eq!(client.pool_snapshot().await.len(), 2);
+
+        tokio::time::sleep(Duration::from_secs(100)).await;
+        second_connection.recv_command("QUIT").await.unwrap();
+        assert!(second_connection.recv().await.is_none());
+        loop {
+            if let ClientEvent::ConnectionRetired { connection_id } = events.recv().await.unwrap() {
+                assert_eq!(connection_id, 1);
+                break;
+            }
+        }
+
+        // the connection with a channel stays open, even though it was not used either
+        tokio::time::sleep(Duration::from_secs(100)).await;
+        assert!(first_connection.is_connected());
+        while let Some(message) = first_connection.try_recv() {
+            assert_ne!(message.command, "QUIT");
+        }
+        let snapshot = client.pool_snapshot().await;
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].connection_id, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_join_rate_limiter() {
+        let join_rate_limiter = Arc::new(JoinRateLimiter::new(2, Duration::from_secs(10)));
+        let mut first_server = FakeTwitchServer::new();
+        let (_, first_client) = MockClient::new(ClientConfig {
+            join_rate_limiter: Some(Arc::clone(&join_rate_limiter)),
+            ..test_config(&first_server)
+        });
+        let mut second_server = FakeTwitchServer::new();
+        let (_, second_client) = MockClient::new(ClientConfig {
+            join_rate_limiter: Some(join_rate_limiter),
+            ..test_config(&second_server)
+        });
+
+        let start = Instant::now();
+        first_client
+            .set_wanted_channels(
+                ["forsen", "pajlada", "sodapoppin"]
+                    .into_iter()
+                    .map(|c| c.to_owned())
+                    .collect(),
+            )
+            .unwrap();
+        // the limiter is shared, so this has to wait for the first client's JOINs
+        second_client.join("randers".to_owned()).unwrap();
+
+        let mut first_connection = first_server.accept().await;
+        let mut second_connection = second_server.accept().await;
+        for _ in 0..2 {
+            first_connection.recv_command("JOIN").await.unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        first_connection.recv_command("JOIN").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+        second_connection.recv_command("JOIN").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+        let status = first_client.list_channels().await;
+        assert!(status.iter().all(|c| c.join_attempts == 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_batches_joins_and_parts() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            channel_batch_window: Some(Duration::from_millis(50)),
+            ..test_config(&server)
+        });
+
+        // 40 channels with 24 characters each do not fit into one line
+        let channels = (0..40)
+            .map(|i| format!("channel_{i:0>16}"))
+            .collect::<Vec<_>>();
+        client
+            .set_wanted_channels(channels.iter().cloned().collect())
+            .unwrap();
+        let mut connection = server.accept().await;
+
+        let mut joined_channels = HashSet::new();
+        while joined_channels.len() < channels.len() {
+            let join = connection.recv_command("JOIN").await.unwrap();
+            assert_eq!(join.params.len(), 1);
+            assert!(join.params[0].len() <= 490);
+            for channel in join.params[0].split(',') {
+                assert!(joined_channels.insert(channel.trim_start_matches('#').to_owned()));
+            }
+        }
+        assert_eq!(joined_channels, channels.iter().cloned().collect());
+        for _ in 0..channels.len() {
+            while !matches!(incoming_messages.recv().await, Some(ServerMessage::Join(_))) {}
+        }
+        // every channel is tracked individually
+        let status = client.list_channels().await;
+        assert_eq!(status.len(), 40);
+        assert!(status.iter().all(|c| c.wanted && c.joined));
+
+        client.part(channels[0].clone());
+        client.part(channels[1].clone());
+        let part = connection.recv_command("PART").await.unwrap();
+        assert_eq!(
+            part.params,
+            vec![format!("#{},#{}", channels[0], channels[1])]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_message_priority() {
+        let mut server = FakeTwitchServer::new();
+        let (_incoming_messages, client) = new_client(&server);
+        let client = Arc::new(client);
+
+        // the messages are queued at the same time, and are then sent by priority
+        let mut send_tasks = vec![];
+        for (text, priority) in [
+            ("low", MessagePriority::Low),
+            ("normal", MessagePriority::Normal),
+            ("high", MessagePriority::High),
+        ] {
+            let client = Arc::clone(&client);
+            send_tasks.push(tokio::spawn(async move {
+                client
+                    .send_message_with_priority(irc!["PRIVMSG", "#pajlada", text], priority)
+                    .await
+            }));
+        }
+
+        let mut connection = server.accept().await;
+        let mut texts = vec![];
+        for _ in 0..3 {
+            texts.push(connection.recv_command("PRIVMSG").await.unwrap().params[1].clone());
+        }
+        assert_eq!(texts, vec!["high", "normal", "low"]);
+        for send_task in send_tasks {
+            send_task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited_message_priority() {
+        let mut server = FakeTwitchServer::new();
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            privmsg_rate_limits: Some(PrivmsgRateLimits::default()),
+            ..test_config(&server)
+        });
+        let client = Arc::new(client);
+
+        client
+            .say("pajlada".to_owned(), "first".to_owned())
+            .await
+            .unwrap();
+        // these are held back by the rate limiter, the high priority message is sent first
+        let mut send_tasks = vec![];
+        for (text, priority) in [
+            ("low 1", MessagePriority::Low),
+            ("low 2", MessagePriority::Low),
+            ("high", MessagePriority::High),
+        ] {
+            let client = Arc::clone(&client);
+            send_tasks.push(tokio::spawn(async move {
+                client
+                    .say_with_priority("pajlada".to_owned(), text.to_owned(), priority)
+                    .await
+            }));
+        }
+
+        let mut connection = server.accept().await;
+        let mut texts = vec![];
+        for _ in 0..4 {
+            texts.push(connection.recv_command("PRIVMSG").await.unwrap().params[1].clone());
+        }
+        assert_eq!(texts, vec![". first", ". high", ". low 1", ". low 2"]);
+        for send_task in send_tasks {
+            send_task.await.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_low_priority_message_expiry() {
+        let mut server = FakeTwitchServer::new();
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            low_priority_message_expiry: Some(Duration::from_millis(500)),
+            privmsg_rate_limits: Some(PrivmsgRateLimits::default()),
+            ..test_config(&server)
+        });
+
+        client
+            .say_with_priority(
+                "pajlada".to_owned(),
+                "first".to_owned(),
+                MessagePriority::Low,
+            )
+            .await
+            .unwrap();
+        // held back by the rate limiter for 1 second, which is longer than the expiry
+        let result = client
+            .say_with_priority(
+                "pajlada".to_owned(),
+                "second".to_owned(),
+                MessagePriority::Low,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::MessageExpired)));
+        // other priorities do not expire
+        client
+            .say_with_priority(
+                "pajlada".to_owned(),
+                "third".to_owned(),
+                MessagePriority::Normal,
+            )
+            .await
+            .unwrap();
+
+        let mut connection = server.accept().await;
+        let mut texts = vec![];
+        for _ in 0..2 {
+            texts.push(connection.recv_command("PRIVMSG").await.unwrap().params[1].clone());
+        }
+        assert_eq!(texts, vec![". first", ". third"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rejoins_after_reconnect() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = new_client(&server);
+
+        client.join("pajlada".to_owned()).unwrap();
+        let first_connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+
+        first_connection.send_reconnect();
+
+        let mut second_connection = server.accept().await;
+        let join = second_connection.recv_command("JOIN").await.unwrap();
+        assert_eq!(join.params, vec!["#pajlada".to_owned()]);
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+        let status = client.get_channel_status("pajlada".to_owned()).await;
+        assert_eq!((status.wanted, status.joined), (true, true));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnects_after_disconnect() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = new_client(&server);
+
+        client.join("pajlada".to_owned()).unwrap();
+        let mut first_connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+
+        first_connection.disconnect();
+
+        let mut second_connection = server.accept().await;
+        second_connection.recv_command("JOIN").await.unwrap();
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+    }
+
+    /// Credentials that count how often they were rejected.
+    #[derive(Debug, Default)]
+    struct CountingCredentials {
+        failed_logins: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LoginCredentials for CountingCredentials {
+        type Error = Infallible;
+
+        async fn get_credentials(&self) -> Result<CredentialsPair, Infallible> {
+            Ok(CredentialsPair {
+                login: "randers".to_owned(),
+                token: Some("token".to_owned()),
+            })
+        }
+
+        async fn on_login_failed(&self, credentials: &CredentialsPair) {
+            assert_eq!(credentials.token.as_deref(), Some("token"));
+            self.failed_logins.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_login_failed_backoff() {
+        let mut server = FakeTwitchServer::new();
+        server.reject_logins(true);
+        let credentials = CountingCredentials::default();
+        let failed_logins = Arc::clone(&credentials.failed_logins);
+        let config = ClientConfig {
+            server_endpoint: server.endpoint(),
+            new_connection_every: Duration::ZERO,
+            reconnect_backoff: Some(ReconnectBackoff {
+                jitter: 0.0,
+                ..ReconnectBackoff::default()
+            }),
+            #[cfg(feature = "metrics-collection")]
+            metrics_config: crate::MetricsConfig::Disabled,
+            ..ClientConfig::new_simple(credentials)
+        };
+        let (mut incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, CountingCredentials>::new(config);
+
+        client.join("pajlada".to_owned()).unwrap();
+        let start = Instant::now();
+        let mut connections = vec![];
+        for expected_time in [0, 1, 3] {
+            let connection = server.accept().await;
+            assert_eq!(start.elapsed(), Duration::from_secs(expected_time));
+            connections.push(connection);
+        }
+
+        server.reject_logins(false);
+        let mut connection = server.accept().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(7));
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+        assert_eq!(failed_logins.load(Ordering::SeqCst), 3);
+
+        // the backoff is reset once the connection was healthy for long enough
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        connection.disconnect();
+        server.accept().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(68));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_login_failed_backoff_without_reconnect_backoff() {
+        let mut server = FakeTwitchServer::new();
+        server.reject_logins(true);
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            new_connection_every: Duration::ZERO,
+            reconnect_backoff: None,
+            ..test_config(&server)
+        });
+
+        client.join("pajlada".to_owned()).unwrap();
+        let start = Instant::now();
+        let mut connections = vec![];
+        for expected_time in [0, 1, 3, 7] {
+            let connection = server.accept().await;
+            assert_eq!(start.elapsed(), Duration::from_secs(expected_time));
+            connections.push(connection);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_is_not_a_failure() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            new_connection_every: Duration::ZERO,
+            reconnect_backoff: Some(ReconnectBackoff {
+                jitter: 0.0,
+                ..ReconnectBackoff::default()
+            }),
+            ..test_config(&server)
+        });
+
+        client.join("pajlada".to_owned()).unwrap();
+        let connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+
+        let start = Instant::now();
+        connection.send_reconnect();
+        server.accept().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_join_rejected() {
+        let mut server = FakeTwitchServer::new();
+        server.set_channel_suspended("suspended", true);
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            join_retry_interval: Some(Duration::from_secs(60)),
+            ..test_config(&server)
+        });
+        let mut events = client.subscribe_events();
+
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("suspended".to_owned()).unwrap();
+        let mut connection = server.accept().await;
+        while connection.recv_command("JOIN").await.unwrap().params[0] != "#suspended" {}
+        connection.send_notice(
+            Some("suspended"),
+            Some("msg_channel_suspended"),
+            "This channel has been suspended.",
+        );
+        // answers a message sent to a channel that is joined already
+        connection.send_notice(
+            Some("pajlada"),
+            Some("msg_banned"),
+            "You are permanently banned from talking in pajlada.",
+        );
+
+        // the refused JOIN neither times out nor is retried
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        while let Ok(event) = events.try_recv() {
+            assert!(
+                !matches!(event, ClientEvent::ChannelJoinTimedOut { .. }),
+                "unexpected event: {event:?}"
+            );
+        }
+        while let Some(message) = connection.try_recv() {
+            assert_ne!(message.command, "JOIN");
+        }
+        assert_eq!(
+            client
+                .get_channel_status("suspended".to_owned())
+                .await
+                .last_failure,
+            Some(JoinFailure::Rejected(NoticeId::ChannelSuspended))
+        );
+
+        let status = client.get_channel_status("pajlada".to_owned()).await;
+        assert!(status.joined);
+        assert_eq!(status.last_failure, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_client_events() {
+        let mut server = FakeTwitchServer::new();
+        server.set_channel_suspended("suspended", true);
+        let (_incoming_messages, client) = new_client(&server);
+        let mut events = client.subscribe_events();
+
+        client.join("pajlada".to_owned()).unwrap();
+        client.join("suspended".to_owned()).unwrap();
+        let mut connection = server.accept().await;
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ClientEvent::ConnectionOpened { connection_id: 0 }
+        ));
+        match events.recv().await.unwrap() {
+            ClientEvent::ChannelJoined {
+                connection_id: 0,
+                channel_login,
+            } => assert_eq!(channel_login, "pajlada"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        let start = Instant::now();
+        match events.recv().await.unwrap() {
+            ClientEvent::ChannelJoinTimedOut {
+                connection_id: 0,
+                channel_login,
+            } => assert_eq!(channel_login, "suspended"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+
+        client.part("pajlada".to_owned());
+        match events.recv().await.unwrap() {
+            ClientEvent::ChannelParted {
+                connection_id: 0,
+                channel_login,
+            } => assert_eq!(channel_login, "pajlada"),
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        connection.disconnect();
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ClientEvent::ConnectionClosed {
+                connection_id: 0,
+                cause: Error::RemoteUnexpectedlyClosedConnection
+            }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ClientEvent::ConnectionOpened { connection_id: 1 }
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_join_and_wait() {
+        let mut server = FakeTwitchServer::new();
+        let (_incoming_messages, client) = new_client(&server);
+        server.set_channel_suspended("suspended", true);
+
+        let (result, mut connection) = tokio::join!(
+            client.join_and_wait("pajlada".to_owned(), Duration::from_secs(5)),
+            server.accept()
+        );
+        result.unwrap();
+        // already joined
+        client
+            .join_and_wait("pajlada".to_owned(), Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        let start = Instant::now();
+        let result = client
+            .join_and_wait("suspended".to_owned(), Duration::from_secs(1))
+            .await;
+        assert!(matches!(result, Err(JoinError::Timeout)));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        let waiting_client = client.clone();
+        let join = tokio::spawn(async move {
+            waiting_client
+                .join_and_wait("suspended".to_owned(), Duration::from_secs(5))
+                .await
+        });
+        while connection.recv_command("JOIN").await.unwrap().params[0] != "#suspended" {}
+        connection.send_notice(
+            Some("suspended"),
+            Some("msg_channel_suspended"),
+            "This channel has been suspended.",
+        );
+        assert!(matches!(
+            join.await.unwrap(),
+            Err(JoinError::Rejected {
+                reason: NoticeId::ChannelSuspended,
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            client
+                .join_and_wait("Invalid".to_owned(), Duration::ZERO)
+                .await,
+            Err(JoinError::InvalidChannelLogin(_))
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_join_retry() {
+        let mut server = FakeTwitchServer::new();
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            join_retry_interval: Some(Duration::from_secs(60)),
+            ..test_config(&server)
+        });
+        server.set_channel_suspended("suspended", true);
+
+        let start = Instant::now();
+        client.join("suspended".to_owned()).unwrap();
+        let mut connection = server.accept().await;
+        connection.recv_command("JOIN").await.unwrap();
+
+        // the JOIN is retried after the join timeout plus the retry interval, for as long as
+        // it is not confirmed
+        connection.recv_command("JOIN").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(70));
+        server.set_channel_suspended("suspended", false);
+        connection.recv_command("JOIN").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(140));
+        let status = client.get_channel_status("suspended".to_owned()).await;
+        assert_eq!((status.wanted, status.joined), (true, true));
+
+        // no more retries once the channel is joined
+        tokio::time::sleep(Duration::from_secs(300)).await;
+        assert!(std::iter::from_fn(|| connection.try_recv()).all(|m| m.command != "JOIN"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            privmsg_rate_limits: Some(PrivmsgRateLimits::default()),
+            ..test_config(&server)
+        });
+        client.join("pajlada".to_owned()).unwrap();
+        let mut connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+        while connection.try_recv().is_some() {}
+
+        let start = Instant::now();
+        let (first, second, shutdown) = tokio::join!(
+            client.say("pajlada".to_owned(), "first".to_owned()),
+            client.say("pajlada".to_owned(), "second".to_owned()),
+            client.shutdown()
+        );
+        first.unwrap();
+        second.unwrap();
+        shutdown.unwrap();
+        // the second message had to wait for the rate limiter
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        let mut commands = vec![];
+        while let Some(message) = connection.recv().await {
+            commands.push(message.command);
+        }
+        assert_eq!(commands, vec!["PRIVMSG", "PRIVMSG", "PART", "QUIT"]);
+        assert!(!connection.is_connected());
+
+        assert!(matches!(
+            client.say("pajlada".to_owned(), "hi".to_owned()).await,
+            Err(Error::ClientShutDown)
+        ));
+        client.join("forsen".to_owned()).unwrap();
+        client.shutdown().await.unwrap();
+        assert!(server.try_accept().is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_timeout() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, client) = MockClient::new(ClientConfig {
+            shutdown_timeout: Duration::from_millis(1500),
+            privmsg_rate_limits: Some(PrivmsgRateLimits::default()),
+            ..test_config(&server)
+        });
+        client.join("pajlada".to_owned()).unwrap();
+        let mut connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+        while connection.try_recv().is_some() {}
+
+        let start = Instant::now();
+        let (first, second, third, shutdown) = tokio::join!(
+            client.say("pajlada".to_owned(), "first".to_owned()),
+            client.say("pajlada".to_owned(), "second".to_owned()),
+            client.say("pajlada".to_owned(), "third".to_owned()),
+            client.shutdown()
+        );
+        first.unwrap();
+        second.unwrap();
+        assert!(matches!(third, Err(Error::ClientShutDown)));
+        assert_eq!(shutdown, Err(ShutdownTimeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(1500));
+
+        // the connection was dropped without parting channels or sending a QUIT
+        let mut commands = vec![];
+        while let Some(message) = connection.recv().await {
+            commands.push(message.command);
+        }
+        assert_eq!(commands, vec!["PRIVMSG", "PRIVMSG"]);
+        assert!(!connection.is_connected());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_privmsg_rate_limit() {
+        let mut server = FakeTwitchServer::new();
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            privmsg_rate_limits: Some(PrivmsgRateLimits::default()),
+            ..test_config(&server)
+        });
+
+        tokio::spawn(async move {
+            for i in 0..3 {
+                client
+                    .say("pajlada".to_owned(), format!("message {i}"))
+                    .await
+                    .unwrap();
+            }
+            client
+                .say("forsen".to_owned(), "hi".to_owned())
+                .await
+                .unwrap();
+        });
+
+        let mut connection = server.accept().await;
+        let start = Instant::now();
+        for expected_delay in [0, 1, 2] {
+            let privmsg = connection.recv_command("PRIVMSG").await.unwrap();
+            assert_eq!(privmsg.params[0], "#pajlada");
+            assert_eq!(start.elapsed(), Duration::from_secs(expected_delay));
+        }
+        // not subject to the per-channel limit of #pajlada
+        let privmsg = connection.recv_command("PRIVMSG").await.unwrap();
+        assert_eq!(privmsg.params[0], "#forsen");
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_privmsg_rate_limit_moderator() {
+        let mut server = FakeTwitchServer::new();
+        let mut config = test_config(&server);
+        // keep all messages on one connection
+        config.max_waiting_messages_per_connection = 100;
+        config.privmsg_rate_limits = Some(PrivmsgRateLimits::default());
+        let (mut incoming_messages, client) = MockClient::new(config);
+
+        client.join("pajlada".to_owned()).unwrap();
+        let connection = server.accept().await;
+        wait_for_join(&mut incoming_messages, "pajlada").await;
+
+        connection.set_badges("pajlada", "moderator/1");
+        while let Some(message) = incoming_messages.recv().await {
+            if let ServerMessage::UserState(msg) = message
+                && msg.badges.iter().any(|b| b.name == "moderator")
+            {
+                break;
+            }
+        }
+
+        let start = Instant::now();
+        for i in 0..30 {
+            client
+                .say("pajlada".to_owned(), format!("message {i}"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_duplicate_message_bypass() {
+        let mut server = FakeTwitchServer::new();
+        let mut config = test_config(&server);
+        config.duplicate_message_bypass = true;
+        let (mut incoming_messages, client) = MockClient::new(config);
+
+        for _ in 0..3 {
+            client
+                .say("pajlada".to_owned(), "hi".to_owned())
+                .await
+                .unwrap();
+        }
+
+        let mut connection = server.accept().await;
+        for expected_text in [". hi", ". hi \u{E0000}", ". hi"] {
+            let privmsg = connection.recv_command("PRIVMSG").await.unwrap();
+            assert_eq!(privmsg.params[1], expected_text);
+        }
+
+        // all messages were accepted by the server
+        let mut confirmed_messages = 0;
+        while confirmed_messages < 3 {
+            match incoming_messages.recv().await.unwrap() {
+                ServerMessage::UserState(_) => confirmed_messages += 1,
+                ServerMessage::Notice(notice) => panic!("unexpected NOTICE: {notice:?}"),
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed() {
+        let server = FakeTwitchServer::new();
+        let mut config = test_config(&server);
+        // send the messages right away, so the second one is sent while the JOIN is in progress
+        config.privmsg_rate_limits = None;
+        let (_incoming_messages, client) = MockClient::new(config);
+
+        client
+            .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+            .await
+            .unwrap();
+
+        // the USERSTATE sent in response to the JOIN does not confirm the message
+        client.join("pajlada".to_owned()).unwrap();
+        let result = client
+            .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+            .await;
+        match result {
+            Err(DeliveryError::Rejected { reason, notice }) => {
+                assert_eq!(reason, NoticeId::Duplicate);
+                assert_eq!(notice.channel_login.as_deref(), Some("pajlada"));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed_after_say() {
+        let server = FakeTwitchServer::new();
+        let mut config = test_config(&server);
+        config.privmsg_rate_limits = None;
+        let (_incoming_messages, client) = MockClient::new(config);
+
+        // the USERSTATE answering the first message does not confirm the second one
+        client
+            .say("pajlada".to_owned(), "hi".to_owned())
+            .await
+            .unwrap();
+        let result = client
+            .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+            .await;
+        assert!(matches!(
+            result,
+            Err(DeliveryError::Rejected {
+                reason: NoticeId::Duplicate,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed_with_priorities() {
+        let server = FakeTwitchServer::new();
+        // hold the connection back until the messages are queued on it
+        let connection_rate_limiter = Arc::new(Semaphore::new(0));
+        let config = ClientConfig {
+            connection_rate_limiter: Arc::clone(&connection_rate_limiter),
+            // low priority messages expire before they can be sent
+            low_priority_message_expiry: Some(Duration::ZERO),
+            ..test_config(&server)
+        };
+        let (_incoming_messages, client) = MockClient::new(config);
+
+        // the high priority message overtakes the first one and is accepted, so the first one
+        // is refused as a duplicate
+        let confirmation = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+                    .await
+            }
+        });
+        let high_priority = tokio::spawn({
+            let client = client.clone();
+            async move {
+                client
+                    .say_with_priority("pajlada".to_owned(), "hi".to_owned(), MessagePriority::High)
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        connection_rate_limiter.add_permits(1);
+        high_priority.await.unwrap().unwrap();
+        assert!(matches!(
+            confirmation.await.unwrap(),
+            Err(DeliveryError::Rejected {
+                reason: NoticeId::Duplicate,
+                ..
+            })
+        ));
+
+        // the expired message is not answered, so it does not take the next answer
+        let result = client
+            .say_with_priority("pajlada".to_owned(), "bye".to_owned(), MessagePriority::Low)
+            .await;
+        assert!(matches!(result, Err(Error::MessageExpired)));
+        client
+            .say_confirmed("pajlada".to_owned(), "hello".to_owned())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed_ignores_other_notices() {
+        let mut server = FakeTwitchServer::new();
+        // the fake server does not answer messages sent by anonymous users
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            login_credentials: StaticLoginCredentials::anonymous(),
+            ..test_config(&server)
+        });
+
+        let confirmation = tokio::spawn(async move {
+            client
+                .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+                .await
+        });
+        let mut connection = server.accept().await;
+        connection.recv_command("PRIVMSG").await.unwrap();
+        connection.send_notice(
+            Some("pajlada"),
+            Some("msg_channel_suspended"),
+            "This channel does not exist or has been suspended.",
+        );
+        connection.send_raw(
+            "@badge-info=;badges=;color=;display-name=justinfan12345;emote-sets=0;id=3f1a7c2e-5b0d-4e8a-9c61-2d4f8b7e0a15;mod=0;subscriber=0;user-type= :tmi.twitch.tv USERSTATE #pajlada",
+        );
+        confirmation.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed_ignores_mod_userstate() {
+        let mut server = FakeTwitchServer::new();
+        // the fake server does not answer messages sent by anonymous users
+        let (_incoming_messages, client) = MockClient::new(ClientConfig {
+            login_credentials: StaticLoginCredentials::anonymous(),
+            ..test_config(&server)
+        });
+
+        let confirmation = tokio::spawn(async move {
+            client
+                .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+                .await
+        });
+        let mut connection = server.accept().await;
+        connection.recv_command("PRIVMSG").await.unwrap();
+        // the USERSTATE sent when the user is modded does not answer the message
+        connection.set_badges("pajlada", "moderator/1");
+        connection.send_notice(
+            Some("pajlada"),
+            Some("msg_duplicate"),
+            "Your message was not sent because it is identical to the previous one you sent, less than 30 seconds ago.",
+        );
+        assert!(matches!(
+            confirmation.await.unwrap(),
+            Err(DeliveryError::Rejected {
+                reason: NoticeId::Duplicate,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed_timeout() {
+        let server = FakeTwitchServer::new();
+        let config = ClientConfig {
+            server_endpoint: server.endpoint(),
+            delivery_confirmation_timeout: Duration::from_secs(5),
+            #[cfg(feature = "metrics-collection")]
+            metrics_config: crate::MetricsConfig::Disabled,
+            ..ClientConfig::default()
+        };
+        let (_incoming_messages, client) =
+            TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+
+        // Twitch does not respond to messages sent by anonymous users
+        let start = Instant::now();
+        let result = client
+            .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+            .await;
+        assert!(matches!(result, Err(DeliveryError::Timeout)));
+        assert!(start.elapsed() >= Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_confirmed_connection_closed() {
+        let mut server = FakeTwitchServer::new();
+        // the fake server does not answer messages sent by anonymous users
+        let config = ClientConfig {
+            login_credentials: StaticLoginCredentials::anonymous(),
+            ..test_config(&server)
+        };
+        let timeout = config.delivery_confirmation_timeout;
+        let (_incoming_messages, client) = MockClient::new(config);
+
+        let start = Instant::now();
+        let confirmation = tokio::spawn(async move {
+            client
+                .say_confirmed("pajlada".to_owned(), "hi".to_owned())
+                .await
+        });
+        let mut connection = server.accept().await;
+        connection.recv_command("PRIVMSG").await.unwrap();
+        connection.disconnect();
+
+        // reported as a failed connection, not as a timeout
+        assert!(matches!(
+            confirmation.await.unwrap(),
+            Err(DeliveryError::Send(_))
+        ));
+        assert!(start.elapsed() < timeout);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_split_leaves_room_for_duplicate_bypass() {
+        let mut server = FakeTwitchServer::new();
+        let mut config = test_config(&server);
+        config.privmsg_rate_limits = None;
+        config.duplicate_message_bypass = true;
+        config.message_splitting.max_message_length = 14;
+        let (_incoming_messages, client) = MockClient::new(config);
+
+        // without room for the suffix, this would be split into two identical messages of
+        // the maximum length, and the suffix would be appended to the second one
+        client
+            .say_split("pajlada".to_owned(), "aaaaaaaa ".repeat(3))
+            .await
+            .unwrap();
+
+        let mut connection = server.accept().await;
+        for _ in 0..6 {
+            let privmsg = connection.recv_command("PRIVMSG").await.unwrap();
+            assert!(privmsg.params[1].chars().count() <= 14);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_say_split() {
+        let mut server = FakeTwitchServer::new();
+        let mut config = test_config(&server);
+        config.message_splitting.max_message_length = 12;
+        let (_incoming_messages, client) = MockClient::new(config);
+
+        let words = (0..8).map(|i| format!("word{i}")).collect::<Vec<_>>();
+        tokio::spawn(async move {
+            client
+                .say_in_reply_to_split(&("pajlada", "abc"), words.join(" "))
+                .await
+                .unwrap();
+        });
+
+        // more messages than `max_waiting_messages_per_connection`, but all of them are
+        // sent over the same connection
+        let mut connection = server.accept().await;
+        for i in 0..8 {
+            let privmsg = connection.recv_command("PRIVMSG").await.unwrap();
+            let expected_text = if i < 7 {
+                format!(". word{i} ...")
+            } else {
+                format!(". word{i}")
+            };
+            assert_eq!(privmsg.params, vec!["#pajlada".to_owned(), expected_text]);
+            assert_eq!(
+                privmsg
+                    .tags
+                    .0
+                    .get("reply-parent-msg-id")
+                    .map(String::as_str),
+                Some("abc")
+            );
+        }
+    }
 }
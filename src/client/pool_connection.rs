@@ -1,21 +1,85 @@
+use crate::client::MessagePriority;
+use crate::client::duplicate_bypass::SentMessageRecord;
+use crate::client::pool_snapshot::PoolConnectionState;
 use crate::config::ClientConfig;
 use crate::connection::Connection;
+use crate::connection::event_loop::{ConnectionLoopCommand, OutgoingMessage};
+use crate::error::{DeliveryError, Error};
+use crate::irc;
 use crate::login::LoginCredentials;
 use crate::transport::Transport;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::oneshot;
 
-/// The actual state of the connection loop is held only by the connection loop.
-/// However the connection sends out messages indicating that it has changed its state.
-/// This enum tracks that "reported state" as received via messages from the connection.
+/// Maximum length of the comma-separated channel list of a batched `JOIN` or `PART`, so the
+/// line stays within the 512 bytes IRC allows.
+const MAX_CHANNEL_LIST_LENGTH: usize = 490;
+
+/// Maximum number of `PRIVMSG`s recorded in the pending confirmations of a connection. This
+/// is more than Twitch lets a connection send within the delivery confirmation timeout, but
+/// bounds the queue if Twitch does not answer the messages at all (e.g. for anonymous users).
+const MAX_PENDING_CONFIRMATIONS: usize = 200;
+
+/// A command that can be batched for multiple channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChannelCommand {
+    Join,
+    Part,
+}
+
+impl ChannelCommand {
+    fn as_str(self) -> &'static str {
+        match self {
+            ChannelCommand::Join => "JOIN",
+            ChannelCommand::Part => "PART",
+        }
+    }
+}
+
+/// Used to report back whether Twitch accepted a message sent with delivery confirmation.
+pub(crate) type ConfirmationSender<T, L> = oneshot::Sender<Result<(), DeliveryError<T, L>>>;
+
+/// A `PRIVMSG` sent over a pool connection that is waiting for Twitch to confirm or reject it.
+///
+/// Twitch answers every `PRIVMSG` with a `USERSTATE` or `NOTICE` in the channel, in the order
+/// the messages were sent. So that the answers are matched to the right messages, every
+/// `PRIVMSG` is recorded, not only the ones somebody waits for a confirmation of.
 ///
-/// (The only use of this is to be able to provide metrics counting channels on a per-state basis)
-#[cfg(feature = "metrics-collection")]
-pub(crate) enum ReportedConnectionState {
-    Initializing,
-    Open,
+/// The connection may send its queued messages in a different order than they were handed
+/// to it (see [`MessagePriority`]), or drop them. So this travels with the message, and the
+/// connection hands it back once the message is written (see
+/// [`ConnectionIncomingMessage::SendingPrivmsg`](crate::connection::ConnectionIncomingMessage::SendingPrivmsg)).
+#[derive(Debug)]
+pub(crate) struct PendingConfirmation<T: Transport, L: LoginCredentials> {
+    pub channel_login: String,
+    /// `None` if nobody waits for the confirmation of this message.
+    pub confirmation_sender: Option<ConfirmationSender<T, L>>,
+    pub sent_at: tokio::time::Instant,
+    /// Set if the duplicate message bypass recorded this message, to undo that if the
+    /// message is not accepted.
+    pub sent_message_record: Option<SentMessageRecord>,
+}
+
+impl<T: Transport, L: LoginCredentials> PendingConfirmation<T, L> {
+    /// Whether somebody still waits for the confirmation of this message.
+    fn is_awaited(&self) -> bool {
+        self.confirmation_sender
+            .as_ref()
+            .is_some_and(|sender| !sender.is_closed())
+    }
+
+    /// Lets whoever waits for the confirmation know that the message can't be confirmed,
+    /// returning the record of the duplicate message bypass, if any.
+    pub fn fail(self, error: Error<T, L>) -> Option<SentMessageRecord> {
+        if let Some(confirmation_sender) = self.confirmation_sender {
+            confirmation_sender
+                .send(Err(DeliveryError::Send(error)))
+                .ok();
+        }
+        self.sent_message_record
+    }
 }
 
 pub(crate) struct PoolConnection<T: Transport, L: LoginCredentials> {
@@ -36,13 +100,31 @@ pub(crate) struct PoolConnection<T: Transport, L: LoginCredentials> {
     /// this has a list of times when messages were sent out on this pool connection,
     /// at the front there will be the oldest, and at the back the newest entries
     pub message_send_times: VecDeque<Instant>,
+    /// When the connection was made, or a message was last sent over it.
+    pub last_used: tokio::time::Instant,
     /// The actual state of the connection loop is held only by the connection loop.
     /// However the connection sends out messages indicating that it has changed its state.
-    /// This enum tracks that "reported state" as received via messages from the connection.
+    /// This tracks that "reported state" as received via messages from the connection.
     ///
-    /// (The only use of this is to be able to provide metrics counting channels on a per-state basis)
-    #[cfg(feature = "metrics-collection")]
-    pub reported_state: ReportedConnectionState,
+    /// (This is used for metrics counting connections on a per-state basis and for pool snapshots)
+    pub reported_state: PoolConnectionState,
+    /// When the connection reported that it is open.
+    pub opened_at: Option<tokio::time::Instant>,
+    /// `PRIVMSG`s sent over this connection that wait for a `USERSTATE` (accepted) or
+    /// `NOTICE` (rejected) in response, oldest first.
+    pub pending_confirmations: VecDeque<PendingConfirmation<T, L>>,
+    /// Number of `PRIVMSG`s handed to the connection that it did not report as written or
+    /// dropped yet.
+    pub unreported_privmsgs: usize,
+    /// Channels a `JOIN` was sent for, but not confirmed yet, with the time by which the
+    /// confirmation is expected.
+    pub pending_joins: HashMap<String, tokio::time::Instant>,
+    /// When this connection successfully logged in, `None` if it did not (yet).
+    pub healthy_since: Option<tokio::time::Instant>,
+    /// Channels waiting to be joined with the next batched `JOIN`, in order.
+    pub join_batch: Vec<String>,
+    /// Channels waiting to be parted with the next batched `PART`, in order.
+    pub part_batch: Vec<String>,
 
     // this is option-wrapped so it can be .take()n in the Drop implementation
     tx_kill_incoming: Option<oneshot::Sender<()>>,
@@ -64,8 +146,15 @@ impl<T: Transport, L: LoginCredentials> PoolConnection<T, L> {
             wanted_channels: HashSet::new(),
             server_channels: HashSet::new(),
             message_send_times: VecDeque::with_capacity(message_send_times_max_entries),
-            #[cfg(feature = "metrics-collection")]
-            reported_state: ReportedConnectionState::Initializing,
+            last_used: tokio::time::Instant::now(),
+            reported_state: PoolConnectionState::Initializing,
+            opened_at: None,
+            pending_confirmations: VecDeque::new(),
+            unreported_privmsgs: 0,
+            pending_joins: HashMap::new(),
+            healthy_since: None,
+            join_batch: vec![],
+            part_batch: vec![],
             tx_kill_incoming: Some(tx_kill_incoming),
         }
     }
@@ -74,12 +163,144 @@ impl<T: Transport, L: LoginCredentials> PoolConnection<T, L> {
         let max_entries = self.config.max_waiting_messages_per_connection * 2;
 
         self.message_send_times.push_back(Instant::now());
+        self.last_used = tokio::time::Instant::now();
 
         if self.message_send_times.len() > max_entries {
             self.message_send_times.pop_front();
         }
     }
 
+    /// Sends a `JOIN` or `PART` for the channel, or adds it to the batch sent once the
+    /// configured batch window has passed. Returns `true` if this started a new batch, in
+    /// which case the caller has to make sure [`flush_channel_batches`](Self::flush_channel_batches)
+    /// is called after the batch window.
+    pub fn send_channel_command(&mut self, command: ChannelCommand, channel_login: String) -> bool {
+        if self.config.channel_batch_window.is_none() {
+            self.send_channel_list(command, &[channel_login], MessagePriority::High);
+            return false;
+        }
+
+        // a JOIN and PART for the same channel must not overtake each other
+        let opposite_batch = match command {
+            ChannelCommand::Join => &self.part_batch,
+            ChannelCommand::Part => &self.join_batch,
+        };
+        if opposite_batch.contains(&channel_login) {
+            self.flush_channel_batches();
+        }
+
+        let new_batch = self.join_batch.is_empty() && self.part_batch.is_empty();
+        let batch = match command {
+            ChannelCommand::Join => &mut self.join_batch,
+            ChannelCommand::Part => &mut self.part_batch,
+        };
+        if !batch.contains(&channel_login) {
+            batch.push(channel_login);
+        }
+        new_batch
+    }
+
+    /// Sends the batched `JOIN`s and `PART`s.
+    pub fn flush_channel_batches(&mut self) {
+        let join_batch = std::mem::take(&mut self.join_batch);
+        self.send_channel_list(ChannelCommand::Join, &join_batch, MessagePriority::High);
+        let part_batch = std::mem::take(&mut self.part_batch);
+        self.send_channel_list(ChannelCommand::Part, &part_batch, MessagePriority::High);
+    }
+
+    /// Parts all given channels after all messages that are still queued on this connection,
+    /// used when shutting down.
+    pub fn part_after_queued_messages(&mut self, channel_logins: &[String]) {
+        self.send_channel_list(ChannelCommand::Part, channel_logins, MessagePriority::Low);
+    }
+
+    /// Sends the command for all given channels, combining as many channels per line as fit.
+    ///
+    /// `JOIN`s and `PART`s are usually sent with high priority, so a message sent to a channel
+    /// right after joining it can't overtake the `JOIN`.
+    fn send_channel_list(
+        &mut self,
+        command: ChannelCommand,
+        channel_logins: &[String],
+        priority: MessagePriority,
+    ) {
+        let mut channel_list = String::new();
+        for (i, channel_login) in channel_logins.iter().enumerate() {
+            if !channel_list.is_empty() {
+                channel_list.push(',');
+            }
+            channel_list.push('#');
+            channel_list.push_str(channel_login);
+
+            let next_length = channel_logins
+                .get(i + 1)
+                .map(|next| next.len() + 2)
+                .unwrap_or(usize::MAX);
+            if channel_list.len().saturating_add(next_length) > MAX_CHANNEL_LIST_LENGTH {
+                self.send_line(
+                    irc![command.as_str(), std::mem::take(&mut channel_list)],
+                    priority,
+                );
+            }
+        }
+    }
+
+    fn send_line(&mut self, message: crate::message::IRCMessage, priority: MessagePriority) {
+        self.connection
+            .connection_loop_tx
+            .send(ConnectionLoopCommand::SendMessage(OutgoingMessage::new(
+                message, priority,
+            )))
+            .unwrap();
+        self.register_sent_message();
+    }
+
+    /// Discards the messages that were not answered within the
+    /// [delivery confirmation timeout](ClientConfig::delivery_confirmation_timeout) and that
+    /// nobody waits for anymore, so a message Twitch never answers does not take the answer
+    /// meant for a later message.
+    fn prune_pending_confirmations(&mut self) {
+        let now = tokio::time::Instant::now();
+        let timeout = self.config.delivery_confirmation_timeout;
+        self.pending_confirmations
+            .retain(|c| c.is_awaited() || now < c.sent_at + timeout);
+    }
+
+    /// Records a `PRIVMSG` sent over this connection. If more than
+    /// [`MAX_PENDING_CONFIRMATIONS`] messages are recorded, the oldest one nobody waits for is
+    /// discarded.
+    pub fn push_pending_confirmation(&mut self, pending_confirmation: PendingConfirmation<T, L>) {
+        self.prune_pending_confirmations();
+        self.pending_confirmations.push_back(pending_confirmation);
+        if self.pending_confirmations.len() > MAX_PENDING_CONFIRMATIONS
+            && let Some(pos) = self
+                .pending_confirmations
+                .iter()
+                .position(|c| !c.is_awaited())
+        {
+            self.pending_confirmations.remove(pos);
+        }
+    }
+
+    /// Removes and returns the oldest pending confirmation for the given channel, after
+    /// discarding the expired ones.
+    pub fn take_pending_confirmation(
+        &mut self,
+        channel_login: &str,
+    ) -> Option<PendingConfirmation<T, L>> {
+        self.prune_pending_confirmations();
+        let pos = self
+            .pending_confirmations
+            .iter()
+            .position(|c| c.channel_login == channel_login)?;
+        self.pending_confirmations.remove(pos)
+    }
+
+    /// Whether somebody waits for the confirmation of a message sent over this connection.
+    pub fn awaits_confirmations(&self) -> bool {
+        self.pending_confirmations.iter().any(|c| c.is_awaited())
+    }
+
     pub fn channels_limit_not_reached(&self) -> bool {
         let configured_limit = self.config.max_channels_per_connection;
         self.wanted_channels.len() < configured_limit
@@ -122,3 +343,63 @@ impl<T: Transport, L: LoginCredentials> Drop for PoolConnection<T, L> {
         self.tx_kill_incoming.take().unwrap().send(()).ok();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::pool_connection::{
+        MAX_PENDING_CONFIRMATIONS, PendingConfirmation, PoolConnection,
+    };
+    use crate::client::tests::test_config;
+    use crate::connection::Connection;
+    use crate::login::StaticLoginCredentials;
+    use crate::transport::mock::{FakeTwitchServer, MockTransport};
+    use std::sync::Arc;
+    use tokio::sync::oneshot;
+    use tokio::time::{Duration, Instant};
+
+    fn unawaited(
+        channel_login: &str,
+    ) -> PendingConfirmation<MockTransport, StaticLoginCredentials> {
+        PendingConfirmation {
+            channel_login: channel_login.to_owned(),
+            confirmation_sender: None,
+            sent_at: Instant::now(),
+            sent_message_record: None,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unconfirmed_messages_are_discarded() {
+        let server = FakeTwitchServer::new();
+        let config = Arc::new(test_config(&server));
+        let (_incoming_messages, connection) = Connection::new(
+            Arc::clone(&config),
+            0,
+            Duration::ZERO,
+            None,
+            #[cfg(feature = "metrics-collection")]
+            None,
+        );
+        let (tx_kill_incoming, _rx_kill_incoming) = oneshot::channel();
+        let timeout = config.delivery_confirmation_timeout;
+        let mut pool_connection = PoolConnection::new(config, 0, connection, tx_kill_incoming);
+
+        // e.g. messages sent by an anonymous user, which Twitch never answers
+        for _ in 0..1000 {
+            pool_connection.push_pending_confirmation(unawaited("pajlada"));
+        }
+        assert_eq!(
+            pool_connection.pending_confirmations.len(),
+            MAX_PENDING_CONFIRMATIONS
+        );
+
+        tokio::time::advance(timeout).await;
+        pool_connection.push_pending_confirmation(unawaited("forsen"));
+        assert_eq!(pool_connection.pending_confirmations.len(), 1);
+        assert!(
+            pool_connection
+                .take_pending_confirmation("pajlada")
+                .is_none()
+        );
+    }
+}
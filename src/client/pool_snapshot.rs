@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+/// The state of a single pool connection, as returned by
+/// [`TwitchIRCClient::pool_snapshot`](crate::TwitchIRCClient::pool_snapshot).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionSnapshot {
+    /// ID of the connection, the same ID [`ClientEvent`](crate::ClientEvent)s and
+    /// [`ChannelStatus`](crate::ChannelStatus) refer to.
+    pub connection_id: usize,
+    /// Whether the connection is still being opened or is open.
+    pub state: PoolConnectionState,
+    /// How long the connection has been open, `None` while it is initializing.
+    pub uptime: Option<Duration>,
+    /// The channels this connection is supposed to be joined to, ordered by channel login.
+    pub wanted_channels: Vec<String>,
+    /// The channels the server confirmed this connection to be joined to, ordered by
+    /// channel login.
+    pub server_channels: Vec<String>,
+    /// How many messages were sent on this connection recently. This is what the client
+    /// uses to decide whether the connection is busy, so it counts at most twice
+    /// [`max_waiting_messages_per_connection`](crate::ClientConfig::max_waiting_messages_per_connection)
+    /// messages.
+    pub recent_messages_sent: usize,
+    /// Whether whispers are currently received from this connection. Whispers are sent to
+    /// every connection, but only forwarded from one of them.
+    pub is_whisper_connection: bool,
+}
+
+/// The state of a pool connection, see [`ConnectionSnapshot::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PoolConnectionState {
+    /// The connection is waiting to connect, connecting, or has not been fully set up yet.
+    Initializing,
+    /// The connection is open. Logging in and joining channels takes place in this state.
+    Open,
+}
@@ -0,0 +1,30 @@
+/// The priority of an outgoing message, see
+/// [`TwitchIRCClient::send_message_with_priority`](crate::TwitchIRCClient::send_message_with_priority).
+///
+/// Messages waiting to be sent out over a connection are sent in the order of their priority,
+/// and in the order they were sent within the same priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MessagePriority {
+    /// For messages that should not be held up by other messages, e.g. moderation actions
+    /// or replies. The `PONG`s the client sends to stay connected and its `JOIN`s and `PART`s
+    /// have this priority too.
+    High,
+    /// The priority of messages sent without specifying a priority.
+    #[default]
+    Normal,
+    /// For messages that can wait, e.g. announcements. Low priority messages can
+    /// [expire](crate::ClientConfig::low_priority_message_expiry) if they have to wait
+    /// for too long.
+    Low,
+}
+
+impl MessagePriority {
+    /// Position of this priority in the order messages are sent in, highest priority first.
+    pub(crate) fn index(self) -> usize {
+        match self {
+            MessagePriority::High => 0,
+            MessagePriority::Normal => 1,
+            MessagePriority::Low => 2,
+        }
+    }
+}
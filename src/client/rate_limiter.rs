@@ -0,0 +1,434 @@
+use crate::config::PrivmsgRateLimits;
+use crate::message::{RoomStateMessage, UserStateMessage};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+/// A token bucket where each token that is taken only returns to the bucket once `window`
+/// has passed. This is equivalent to allowing at most `capacity` messages within any
+/// sliding window of length `window`, which is how Twitch counts messages.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: usize,
+    window: Duration,
+    /// Times at which the taken tokens return to the bucket. Oldest at the front.
+    token_returns: VecDeque<Instant>,
+}
+
+impl TokenBucket {
+    /// `capacity` must not be 0, the callers validate it.
+    fn new(capacity: usize, window: Duration) -> TokenBucket {
+        TokenBucket {
+            capacity,
+            window,
+            token_returns: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Earliest time at which a token can be taken from this bucket.
+    fn ready_at(&mut self, now: Instant) -> Instant {
+        while self.token_returns.front().is_some_and(|t| *t <= now) {
+            self.token_returns.pop_front();
+        }
+
+        if self.token_returns.len() < self.capacity {
+            now
+        } else {
+            // the bucket may be overdrawn (see `take`), in which case more than one token
+            // has to be returned first.
+            self.token_returns[self.token_returns.len() - self.capacity]
+        }
+    }
+
+    /// Take a token. This does not check whether a token is available, so this can overdraw
+    /// the bucket.
+    fn take(&mut self, now: Instant) {
+        self.token_returns.push_back(now + self.window);
+    }
+}
+
+/// Rate-limits the `JOIN`s sent by one or more clients, so the limits Twitch imposes on
+/// joining channels are not exceeded. `JOIN`s that would exceed the limit are queued by the
+/// client and sent once the limit permits.
+///
+/// Twitch counts `JOIN`s per account, so if multiple `TwitchIRCClient`s log in with the same
+/// account, they should share one limiter. For this reason,
+/// [`ClientConfig::join_rate_limiter`](crate::ClientConfig::join_rate_limiter) wraps it in an
+/// `Arc`.
+#[derive(Debug)]
+pub struct JoinRateLimiter {
+    bucket: Mutex<TokenBucket>,
+}
+
+impl JoinRateLimiter {
+    /// Allow at most `joins_per_window` `JOIN`s within any period of `window`.
+    ///
+    /// # Panics
+    /// Panics if `joins_per_window` is 0.
+    pub fn new(joins_per_window: usize, window: Duration) -> JoinRateLimiter {
+        assert!(
+            joins_per_window > 0,
+            "joins_per_window must be greater than 0"
+        );
+        JoinRateLimiter {
+            bucket: Mutex::new(TokenBucket::new(joins_per_window, window)),
+        }
+    }
+
+    /// The limit for regular accounts: 20 `JOIN`s per 10 seconds.
+    pub fn normal() -> JoinRateLimiter {
+        JoinRateLimiter::new(20, Duration::from_secs(10))
+    }
+
+    /// The limit for verified bots: 2000 `JOIN`s per 10 seconds.
+    pub fn verified() -> JoinRateLimiter {
+        JoinRateLimiter::new(2000, Duration::from_secs(10))
+    }
+
+    /// Take a token if one is available. Otherwise, returns the earliest time at which a
+    /// token might be available (other clients sharing this limiter might take it first).
+    pub(crate) fn try_acquire(&self, now: Instant) -> Result<(), Instant> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let ready_at = bucket.ready_at(now);
+        if ready_at <= now {
+            bucket.take(now);
+            Ok(())
+        } else {
+            Err(ready_at)
+        }
+    }
+}
+
+impl Default for JoinRateLimiter {
+    fn default() -> Self {
+        JoinRateLimiter::normal()
+    }
+}
+
+/// A limit of 0 messages would hold back all messages forever, so it is raised to 1.
+fn at_least_one(messages_per_window: usize, name: &str) -> usize {
+    if messages_per_window == 0 {
+        tracing::warn!("privmsg_rate_limits.{} is 0, using 1 instead", name);
+        1
+    } else {
+        messages_per_window
+    }
+}
+
+/// What the rate limiter knows about the logged in user in a channel.
+#[derive(Default)]
+struct ChannelState {
+    /// whether the logged in user is the broadcaster, a moderator or a VIP in this channel.
+    privileged: bool,
+    /// slow mode duration as reported by the last `ROOMSTATE` (zero if disabled).
+    slow_mode: Duration,
+    last_message_sent: Option<Instant>,
+}
+
+/// Decides when a `PRIVMSG` can be sent without exceeding the limits Twitch imposes on
+/// chat messages. The limiter only makes decisions, the messages waiting to be sent are held
+/// by the client loop.
+pub(crate) struct PrivmsgRateLimiter {
+    limits: PrivmsgRateLimits,
+    /// every sent message takes a token from this bucket. Messages to channels where
+    /// the user is not privileged can only be sent if this bucket has a token.
+    regular_bucket: TokenBucket,
+    /// every sent message takes a token from this bucket, and it must have a token for
+    /// every message.
+    privileged_bucket: TokenBucket,
+    channels: HashMap<String, ChannelState>,
+}
+
+impl PrivmsgRateLimiter {
+    pub fn new(limits: PrivmsgRateLimits) -> PrivmsgRateLimiter {
+        PrivmsgRateLimiter {
+            regular_bucket: TokenBucket::new(
+                at_least_one(limits.messages_per_window, "messages_per_window"),
+                limits.window,
+            ),
+            privileged_bucket: TokenBucket::new(
+                at_least_one(
+                    limits.privileged_messages_per_window,
+                    "privileged_messages_per_window",
+                ),
+                limits.window,
+            ),
+            channels: HashMap::new(),
+            limits,
+        }
+    }
+
+    /// Learn whether the user is privileged in a channel, based on the badges in the `USERSTATE`.
+    pub fn update_user_state(&mut self, message: &UserStateMessage) {
+        let privileged = message
+            .badges
+            .iter()
+            .any(|b| matches!(b.name.as_str(), "broadcaster" | "moderator" | "vip"));
+        self.channels
+            .entry(message.channel_login.clone())
+            .or_default()
+            .privileged = privileged;
+    }
+
+    /// Learn about the slow mode setting of a channel.
+    pub fn update_room_state(&mut self, message: &RoomStateMessage) {
+        // partial ROOMSTATE updates only carry the settings that changed.
+        if let Some(slow_mode) = message.slow_mode {
+            self.channels
+                .entry(message.channel_login.clone())
+                .or_default()
+                .slow_mode = slow_mode;
+        }
+    }
+
+    /// Earliest time at which a message to the given channel can be sent. If this is not
+    /// after `now`, the message can be sent immediately.
+    pub fn ready_at(&mut self, channel_login: &str, now: Instant) -> Instant {
+        let ready_at = self.privileged_bucket.ready_at(now);
+
+        let channel = self.channels.get(channel_login);
+        if channel.is_some_and(|c| c.privileged) {
+            return ready_at;
+        }
+
+        let ready_at = ready_at.max(self.regular_bucket.ready_at(now));
+        match channel {
+            Some(ChannelState {
+                slow_mode,
+                last_message_sent: Some(last_message_sent),
+                ..
+            }) => {
+                let interval = self.limits.channel_message_interval.max(*slow_mode);
+                ready_at.max(*last_message_sent + interval)
+            }
+            _ => ready_at,
+        }
+    }
+
+    /// Record that a message was sent to the given channel.
+    ///
+    /// Channels the limiter knows nothing relevant about anymore are forgotten.
+    pub fn register_sent_message(&mut self, channel_login: &str, now: Instant) {
+        let channel_message_interval = self.limits.channel_message_interval;
+        self.channels.retain(|_, channel| {
+            channel.privileged
+                || !channel.slow_mode.is_zero()
+                || channel
+                    .last_message_sent
+                    .is_some_and(|sent_at| now < sent_at + channel_message_interval)
+        });
+
+        self.privileged_bucket.take(now);
+        self.regular_bucket.take(now);
+        self.channels
+            .entry(channel_login.to_owned())
+            .or_default()
+            .last_message_sent = Some(now);
+    }
+
+    /// Forget what is known about a channel that was parted. When it is joined again, Twitch
+    /// sends the `USERSTATE` and `ROOMSTATE` again.
+    pub fn forget_channel(&mut self, channel_login: &str) {
+        self.channels.remove(channel_login);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::rate_limiter::{JoinRateLimiter, PrivmsgRateLimiter};
+    use crate::config::PrivmsgRateLimits;
+    use crate::message::{IRCMessage, RoomStateMessage, UserStateMessage};
+    use std::convert::TryFrom;
+    use tokio::time::{Duration, Instant};
+
+    fn limiter() -> PrivmsgRateLimiter {
+        PrivmsgRateLimiter::new(PrivmsgRateLimits::default())
+    }
+
+    fn user_state(channel_login: &str, badges: &str) -> UserStateMessage {
+        UserStateMessage::try_from(
+            IRCMessage::parse(&format!(
+                "@badge-info=;badges={badges};color=;display-name=Bot;emote-sets=0;mod=0;subscriber=0;user-type= :tmi.twitch.tv USERSTATE #{channel_login}"
+            ))
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// sends as many messages as possible to `channels` in turn, returns how many were sent.
+    fn send_all_possible(
+        limiter: &mut PrivmsgRateLimiter,
+        channels: &[&str],
+        now: Instant,
+    ) -> usize {
+        let mut sent = 0;
+        for channel in channels.iter().cycle() {
+            if limiter.ready_at(channel, now) > now {
+                break;
+            }
+            limiter.register_sent_message(channel, now);
+            sent += 1;
+        }
+        sent
+    }
+
+    #[test]
+    fn test_join_limit() {
+        let limiter = JoinRateLimiter::new(3, Duration::from_secs(10));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.try_acquire(start), Ok(()));
+        }
+        let ready_at = start + Duration::from_secs(10);
+        assert_eq!(limiter.try_acquire(start), Err(ready_at));
+        assert_eq!(
+            limiter.try_acquire(start + Duration::from_secs(5)),
+            Err(ready_at)
+        );
+        assert_eq!(limiter.try_acquire(ready_at), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "joins_per_window must be greater than 0")]
+    fn test_join_limit_zero() {
+        let _ = JoinRateLimiter::new(0, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_zero_limit() {
+        let mut limiter = PrivmsgRateLimiter::new(PrivmsgRateLimits {
+            messages_per_window: 0,
+            privileged_messages_per_window: 0,
+            ..PrivmsgRateLimits::default()
+        });
+        let start = Instant::now();
+        assert_eq!(
+            send_all_possible(&mut limiter, &["pajlada", "forsen"], start),
+            1
+        );
+    }
+
+    #[test]
+    fn test_regular_limit() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+        let channels = (0..30).map(|i| format!("channel{i}")).collect::<Vec<_>>();
+        let channels = channels.iter().map(String::as_str).collect::<Vec<_>>();
+
+        assert_eq!(send_all_possible(&mut limiter, &channels, start), 20);
+        assert_eq!(
+            limiter.ready_at("channel20", start),
+            start + Duration::from_secs(30)
+        );
+        assert_eq!(
+            send_all_possible(&mut limiter, &channels, start + Duration::from_secs(30)),
+            20
+        );
+    }
+
+    #[test]
+    fn test_privileged_limit() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+        limiter.update_user_state(&user_state("pajlada", "moderator/1"));
+        limiter.update_user_state(&user_state("randers", "broadcaster/1"));
+
+        assert_eq!(
+            send_all_possible(&mut limiter, &["pajlada", "randers"], start),
+            100
+        );
+        // regular channels are limited too, since all messages count towards the limit
+        assert_eq!(
+            limiter.ready_at("forsen", start + Duration::from_secs(29)),
+            start + Duration::from_secs(30)
+        );
+
+        // losing moderator status
+        limiter.update_user_state(&user_state("pajlada", ""));
+        let later = start + Duration::from_secs(30);
+        assert_eq!(send_all_possible(&mut limiter, &["pajlada"], later), 1);
+    }
+
+    #[test]
+    fn test_regular_messages_count_towards_privileged_limit() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+        limiter.update_user_state(&user_state("pajlada", "vip/1"));
+        let channels = (0..30).map(|i| format!("channel{i}")).collect::<Vec<_>>();
+        let channels = channels.iter().map(String::as_str).collect::<Vec<_>>();
+
+        assert_eq!(send_all_possible(&mut limiter, &channels, start), 20);
+        assert_eq!(send_all_possible(&mut limiter, &["pajlada"], start), 80);
+    }
+
+    #[test]
+    fn test_channel_interval() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+
+        limiter.register_sent_message("pajlada", start);
+        assert_eq!(
+            limiter.ready_at("pajlada", start),
+            start + Duration::from_secs(1)
+        );
+        // other channels are unaffected
+        assert_eq!(limiter.ready_at("forsen", start), start);
+
+        // moderators are not affected
+        limiter.update_user_state(&user_state("pajlada", "moderator/1"));
+        assert_eq!(limiter.ready_at("pajlada", start), start);
+    }
+
+    #[test]
+    fn test_slow_mode() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+        let room_state = |src: &str| RoomStateMessage::try_from(IRCMessage::parse(src).unwrap());
+
+        limiter.update_room_state(
+            &room_state("@emote-only=0;followers-only=-1;r9k=0;room-id=11148817;slow=10;subs-only=0 :tmi.twitch.tv ROOMSTATE #pajlada").unwrap(),
+        );
+        limiter.register_sent_message("pajlada", start);
+        assert_eq!(
+            limiter.ready_at("pajlada", start),
+            start + Duration::from_secs(10)
+        );
+
+        // partial update not concerning slow mode
+        limiter.update_room_state(
+            &room_state("@emote-only=1;room-id=11148817 :tmi.twitch.tv ROOMSTATE #pajlada")
+                .unwrap(),
+        );
+        assert_eq!(
+            limiter.ready_at("pajlada", start),
+            start + Duration::from_secs(10)
+        );
+
+        limiter.update_room_state(
+            &room_state("@room-id=11148817;slow=0 :tmi.twitch.tv ROOMSTATE #pajlada").unwrap(),
+        );
+        assert_eq!(
+            limiter.ready_at("pajlada", start),
+            start + Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_channels_are_forgotten() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+        limiter.update_user_state(&user_state("pajlada", "moderator/1"));
+        limiter.update_user_state(&user_state("forsen", ""));
+        limiter.register_sent_message("randers", start);
+
+        let later = start + Duration::from_secs(1);
+        limiter.register_sent_message("zneix", later);
+        // "forsen" and "randers" hold nothing that affects the limits anymore
+        assert_eq!(limiter.channels.len(), 2);
+        assert!(limiter.channels.contains_key("pajlada"));
+        assert!(limiter.channels.contains_key("zneix"));
+
+        limiter.forget_channel("pajlada");
+        assert!(!limiter.channels.contains_key("pajlada"));
+    }
+}
@@ -0,0 +1,196 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Split `text` into chunks of at most `max_length` characters (Unicode scalar values) each.
+///
+/// Chunks are split between words if possible. Words that do not fit into a single chunk
+/// are split between grapheme clusters, so e.g. emoji or characters with combining marks are
+/// not torn apart. Only grapheme clusters that do not fit into a single chunk themselves are
+/// split between characters. All chunks except for the last one are suffixed with `continuation_marker`,
+/// which counts towards the `max_length` of the chunk.
+///
+/// Whitespace between the words is normalized to a single space.
+///
+/// Returns `None` if `text` has to be split but `max_length` leaves no room for any text next
+/// to the continuation marker, or if `text` consists only of whitespace.
+pub(crate) fn split_message(
+    text: &str,
+    max_length: usize,
+    continuation_marker: &str,
+) -> Option<Vec<String>> {
+    if text.chars().count() <= max_length {
+        return Some(vec![text.to_owned()]);
+    }
+
+    // the space available for the text of chunks followed by the continuation marker.
+    let budget = max_length.saturating_sub(continuation_marker.chars().count());
+    if budget == 0 {
+        return None;
+    }
+
+    let mut chunks = vec![];
+    let mut current_chunk = String::new();
+    let mut current_length = 0;
+
+    for word in text.split_whitespace() {
+        let word_length = word.chars().count();
+        let separator_length = usize::from(!current_chunk.is_empty());
+
+        if current_length + separator_length + word_length <= budget {
+            if separator_length > 0 {
+                current_chunk.push(' ');
+            }
+            current_chunk.push_str(word);
+            current_length += separator_length + word_length;
+            continue;
+        }
+
+        if !current_chunk.is_empty() {
+            chunks.push(std::mem::take(&mut current_chunk));
+            current_length = 0;
+        }
+
+        if word_length <= budget {
+            current_chunk.push_str(word);
+            current_length = word_length;
+            continue;
+        }
+
+        // the word is too long for a single chunk
+        for grapheme in word.graphemes(true) {
+            let grapheme_length = grapheme.chars().count();
+            if current_length + grapheme_length > budget && !current_chunk.is_empty() {
+                chunks.push(std::mem::take(&mut current_chunk));
+                current_length = 0;
+            }
+            if grapheme_length <= budget {
+                current_chunk.push_str(grapheme);
+                current_length += grapheme_length;
+                continue;
+            }
+
+            // the grapheme cluster is too long for a single chunk
+            for c in grapheme.chars() {
+                if current_length == budget {
+                    chunks.push(std::mem::take(&mut current_chunk));
+                    current_length = 0;
+                }
+                current_chunk.push(c);
+                current_length += 1;
+            }
+        }
+    }
+
+    if !current_chunk.is_empty() {
+        chunks.push(current_chunk);
+    }
+    if chunks.is_empty() {
+        return None;
+    }
+
+    let last_chunk = chunks.len().saturating_sub(1);
+    for chunk in &mut chunks[..last_chunk] {
+        chunk.push_str(continuation_marker);
+    }
+
+    Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::split::split_message;
+
+    #[test]
+    fn test_short_message() {
+        assert_eq!(
+            split_message("hello  world", 20, "...").unwrap(),
+            vec!["hello  world"]
+        );
+        assert_eq!(split_message("", 20, "...").unwrap(), vec![""]);
+    }
+
+    #[test]
+    fn test_split_on_words() {
+        assert_eq!(
+            split_message("the quick brown fox jumps over the lazy dog", 15, "...").unwrap(),
+            vec![
+                "the quick...",
+                "brown fox...",
+                "jumps over...",
+                "the lazy dog"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_long_word() {
+        assert_eq!(
+            split_message("a abcdefghijklmnop b", 10, "-").unwrap(),
+            vec!["a-", "abcdefghi-", "jklmnop b"]
+        );
+    }
+
+    #[test]
+    fn test_split_keeps_graphemes() {
+        // family emoji: 7 chars, one grapheme cluster
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{200D}\u{1F466}";
+        let text = format!("{family}{family}{family}");
+        assert_eq!(
+            split_message(&text, 16, "").unwrap(),
+            vec![format!("{family}{family}"), family.to_owned()]
+        );
+
+        // combining marks
+        assert_eq!(
+            split_message("e\u{301}e\u{301}e\u{301}", 5, "").unwrap(),
+            vec!["e\u{301}e\u{301}", "e\u{301}"]
+        );
+    }
+
+    #[test]
+    fn test_split_overlong_grapheme() {
+        // a single grapheme cluster of 13 chars
+        let text = format!("a{}", "\u{301}".repeat(12));
+        let chunks = split_message(&format!("x {text} y"), 6, "-").unwrap();
+        assert_eq!(
+            chunks,
+            vec![
+                "x-".to_owned(),
+                format!("a{}-", "\u{301}".repeat(4)),
+                format!("{}-", "\u{301}".repeat(5)),
+                format!("{} y", "\u{301}".repeat(3)),
+            ]
+        );
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 6);
+        }
+    }
+
+    #[test]
+    fn test_split_impossible() {
+        // no room for any text next to the continuation marker
+        assert_eq!(split_message("abc def", 3, "..."), None);
+        assert_eq!(split_message("abc def", 0, ""), None);
+        // fits without splitting
+        assert_eq!(split_message("abc", 3, "..."), Some(vec!["abc".to_owned()]));
+        // nothing to split
+        assert_eq!(split_message(&" ".repeat(20), 10, "..."), None);
+    }
+
+    #[test]
+    fn test_chunk_lengths() {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ".repeat(30);
+        let chunks = split_message(&text, 500, " (cont.)").unwrap();
+        assert_eq!(chunks.len(), 4);
+        for chunk in &chunks {
+            assert!(chunk.chars().count() <= 500);
+        }
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|c| c.trim_end_matches(" (cont.)"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            text.trim_end()
+        );
+    }
+}
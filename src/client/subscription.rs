@@ -0,0 +1,142 @@
+use crate::client::incoming::{BoundedReceiver, IncomingMessagesSender};
+use crate::message::ServerMessage;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+
+/// Selects the incoming messages that are delivered to a subscription, see
+/// [`TwitchIRCClient::subscribe`](crate::TwitchIRCClient::subscribe).
+#[derive(Clone)]
+pub enum SubscriptionFilter {
+    /// Messages sent to the channel with the given login name, e.g. the `PRIVMSG`, `USERNOTICE`
+    /// and `ROOMSTATE` messages of that channel. Like for
+    /// [`TwitchIRCClient::join`](crate::TwitchIRCClient::join), the login name has to be valid,
+    /// see [`validate_login`](crate::validate::validate_login).
+    Channel(String),
+    /// Messages with the given command, e.g. `PRIVMSG` or `WHISPER`.
+    Command(String),
+    /// Messages for which the function returns `true`. The function is called by the
+    /// background task of the client for every incoming message, so it should return quickly.
+    Predicate(Arc<dyn Fn(&ServerMessage) -> bool + Send + Sync>),
+}
+
+impl SubscriptionFilter {
+    /// Create a [`SubscriptionFilter::Predicate`] from the given function.
+    pub fn predicate(
+        predicate: impl Fn(&ServerMessage) -> bool + Send + Sync + 'static,
+    ) -> SubscriptionFilter {
+        SubscriptionFilter::Predicate(Arc::new(predicate))
+    }
+
+    /// Whether the message is selected by this filter.
+    #[must_use]
+    pub fn matches(&self, message: &ServerMessage) -> bool {
+        match self {
+            SubscriptionFilter::Channel(channel_login) => {
+                message.channel_login() == Some(channel_login.as_str())
+            }
+            SubscriptionFilter::Command(command) => {
+                message.source().command.eq_ignore_ascii_case(command)
+            }
+            SubscriptionFilter::Predicate(predicate) => predicate(message),
+        }
+    }
+}
+
+impl fmt::Debug for SubscriptionFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriptionFilter::Channel(channel_login) => {
+                f.debug_tuple("Channel").field(channel_login).finish()
+            }
+            SubscriptionFilter::Command(command) => {
+                f.debug_tuple("Command").field(command).finish()
+            }
+            SubscriptionFilter::Predicate(_) => f.write_str("Predicate(..)"),
+        }
+    }
+}
+
+/// A receiver of the incoming messages selected by a filter.
+#[derive(Debug)]
+pub(crate) struct Subscription {
+    pub filter: SubscriptionFilter,
+    pub sender: IncomingMessagesSender,
+}
+
+impl Subscription {
+    /// Sends the message to the subscriber if the filter selects it. Returns how many messages
+    /// were dropped because the receiver was full.
+    pub fn deliver(&self, message: &ServerMessage) -> usize {
+        if self.filter.matches(message) {
+            self.sender.send(message.clone())
+        } else {
+            0
+        }
+    }
+
+    /// Whether the receiver was dropped, so the subscription can be removed.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+#[derive(Debug)]
+enum SubscriptionReceiverInner {
+    Unbounded(mpsc::UnboundedReceiver<ServerMessage>),
+    Bounded(BoundedReceiver),
+}
+
+/// Receives the incoming messages of a subscription, see
+/// [`TwitchIRCClient::subscribe`](crate::TwitchIRCClient::subscribe).
+///
+/// For a client created with [`TwitchIRCClient::new`](crate::TwitchIRCClient::new), this works
+/// like an [`mpsc::UnboundedReceiver`]. For a client created with
+/// [`TwitchIRCClient::new_bounded`](crate::TwitchIRCClient::new_bounded), it works like the
+/// [`BoundedReceiver`] of the client, with the same capacity and
+/// [`OverflowPolicy`](crate::OverflowPolicy).
+#[derive(Debug)]
+pub struct SubscriptionReceiver(SubscriptionReceiverInner);
+
+impl SubscriptionReceiver {
+    pub(crate) fn unbounded(receiver: mpsc::UnboundedReceiver<ServerMessage>) -> Self {
+        SubscriptionReceiver(SubscriptionReceiverInner::Unbounded(receiver))
+    }
+
+    pub(crate) fn bounded(receiver: BoundedReceiver) -> Self {
+        SubscriptionReceiver(SubscriptionReceiverInner::Bounded(receiver))
+    }
+
+    /// Receives the next message, waiting for one to arrive if necessary. Returns `None` once
+    /// the client has shut down and all remaining messages have been received.
+    pub async fn recv(&mut self) -> Option<ServerMessage> {
+        match &mut self.0 {
+            SubscriptionReceiverInner::Unbounded(receiver) => receiver.recv().await,
+            SubscriptionReceiverInner::Bounded(receiver) => receiver.recv().await,
+        }
+    }
+
+    /// Receives the next message if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Result<ServerMessage, TryRecvError> {
+        match &mut self.0 {
+            SubscriptionReceiverInner::Unbounded(receiver) => receiver.try_recv(),
+            SubscriptionReceiverInner::Bounded(receiver) => receiver.try_recv(),
+        }
+    }
+
+    /// The number of messages waiting to be received.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            SubscriptionReceiverInner::Unbounded(receiver) => receiver.len(),
+            SubscriptionReceiverInner::Bounded(receiver) => receiver.len(),
+        }
+    }
+
+    /// Whether there are no messages waiting to be received.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
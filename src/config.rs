@@ -1,7 +1,10 @@
+use crate::client::JoinRateLimiter;
 use crate::login::{LoginCredentials, StaticLoginCredentials};
+use crate::transport::ServerEndpoint;
 use std::borrow::Cow;
 #[cfg(feature = "metrics-collection")]
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Semaphore;
@@ -49,6 +52,135 @@ pub struct ClientConfig<L: LoginCredentials> {
     /// handshake. Default value: 20 seconds.
     pub connect_timeout: Duration,
 
+    /// If set, delays new connections after connections of this client failed, so Twitch is
+    /// not flooded with reconnect attempts e.g. during an outage. The delay grows with every
+    /// further consecutive failure. A `RECONNECT` requested by Twitch does not count as a
+    /// failure. Default value: `None` (reconnect right away, limited only by
+    /// `connection_rate_limiter` and `new_connection_every`). Use
+    /// `Some(ReconnectBackoff::default())` for a backoff from 1 to 60 seconds.
+    ///
+    /// Regardless of this option, new connections are delayed while Twitch rejects the login
+    /// credentials, starting at 1 second and doubling up to 60 seconds with every further
+    /// failed login.
+    ///
+    /// See [`ReconnectBackoff`] for the individual options and their defaults.
+    pub reconnect_backoff: Option<ReconnectBackoff>,
+
+    /// If the server does not confirm a `JOIN` within this time, a
+    /// [`ClientEvent::ChannelJoinTimedOut`](crate::ClientEvent::ChannelJoinTimedOut) is emitted.
+    /// This usually means that the channel does not exist or is suspended.
+    /// Default value: 10 seconds.
+    pub join_timeout: Duration,
+
+    /// How long to wait before sending another `JOIN` for a channel whose `JOIN` was not
+    /// confirmed within `join_timeout`. The `JOIN` is retried periodically for as long as the
+    /// channel is wanted and not confirmed. `None` disables retrying.
+    /// Default value: `None` (no retrying). 60 seconds is a reasonable interval.
+    pub join_retry_interval: Option<Duration>,
+
+    /// Rate-limits the `JOIN`s sent by this client, e.g. when joining thousands of channels
+    /// with [`set_wanted_channels`](crate::TwitchIRCClient::set_wanted_channels). `JOIN`s that
+    /// would exceed the limit are queued and sent as soon as the limit permits. Use
+    /// [`JoinRateLimiter::normal`] for the limit of regular accounts (20 `JOIN`s per 10 seconds)
+    /// and [`JoinRateLimiter::verified`] for verified bots. Default value: `None` (no rate
+    /// limiting).
+    ///
+    /// This is wrapped in an Arc to allow it to be shared between multiple `TwitchIRCClient`
+    /// instances that log in with the same account.
+    pub join_rate_limiter: Option<Arc<JoinRateLimiter>>,
+
+    /// If set, `JOIN`s and `PART`s for the channels of a connection are collected for this
+    /// long, and then sent as combined commands like `JOIN #a,#b,#c`, so joining many channels
+    /// takes fewer lines. A window of e.g. 50 milliseconds works well. Default value: `None`
+    /// (a separate `JOIN` or `PART` is sent for every channel right away).
+    pub channel_batch_window: Option<Duration>,
+
+    /// If set, the client rebalances its connections in this interval, the same as
+    /// [`TwitchIRCClient::rebalance`](crate::TwitchIRCClient::rebalance) does: channels are
+    /// moved off under-filled connections so they can be closed. Default value: `None`
+    /// (connections are only rebalanced when `rebalance()` is called).
+    pub rebalance_interval: Option<Duration>,
+
+    /// If set, connections that have no channels and were not used to send a message for this
+    /// long are closed, e.g. the additional connections made during a burst of messages. One
+    /// connection is always kept open to receive whispers. Default value: `None` (connections
+    /// are kept open).
+    pub idle_connection_timeout: Option<Duration>,
+
+    /// How long [`TwitchIRCClient::shutdown`](crate::TwitchIRCClient::shutdown) waits for
+    /// queued messages to be sent and for the connections to be closed cleanly, before the
+    /// connections are closed forcibly. Default value: 10 seconds.
+    pub shutdown_timeout: Duration,
+
+    /// Specifies which server new connections are made to. By default, all connections are
+    /// made to the official Twitch servers.
+    ///
+    /// This can be used to point the client at e.g. a local mock IRC server for integration
+    /// tests, or a proxy/bouncer. See [`ServerEndpoint`] for details.
+    pub server_endpoint: ServerEndpoint,
+
+    /// How long [`say_confirmed()`](crate::TwitchIRCClient::say_confirmed) waits for Twitch
+    /// to confirm or reject a message after it was sent, before giving up with
+    /// [`DeliveryError::Timeout`](crate::DeliveryError::Timeout). Default value: 10 seconds.
+    pub delivery_confirmation_timeout: Duration,
+
+    /// Rate-limits outgoing `PRIVMSG`s (this includes all messages sent via `say()`, `me()`,
+    /// `privmsg()` and their variants) so the limits Twitch imposes on chat messages are never
+    /// exceeded. Messages that would exceed the limits are queued and sent out as soon as
+    /// the limits permit.
+    ///
+    /// The client learns whether the logged in user is the broadcaster, a moderator or a VIP
+    /// in a channel from the `USERSTATE` messages received for that channel, and learns about
+    /// slow mode being active in a channel from the `ROOMSTATE` messages. Until then, it
+    /// conservatively assumes the user is a regular user in that channel, so messages of
+    /// moderators and VIPs can be held back until the first `USERSTATE` arrives.
+    ///
+    /// See [`PrivmsgRateLimits`] for the individual limits and their defaults.
+    /// Default value: `None` (no rate limiting). Use `Some(PrivmsgRateLimits::default())` to
+    /// apply the limits of regular accounts.
+    pub privmsg_rate_limits: Option<PrivmsgRateLimits>,
+
+    /// [Low priority](crate::MessagePriority::Low) messages that could not be sent within this
+    /// time (e.g. because they were held back by the rate limiting, or because higher priority
+    /// messages were sent first) are dropped, and sending them fails with
+    /// [`Error::MessageExpired`](crate::Error::MessageExpired). Set to `None` to send all
+    /// messages no matter how long they waited. Default value: `None`
+    pub low_priority_message_expiry: Option<Duration>,
+
+    /// Twitch refuses to deliver a message if it is identical to the previous message sent
+    /// to the same channel less than 30 seconds ago (and sends a `NOTICE` with the `msg-id`
+    /// `msg_duplicate` instead). If this option is enabled, the client remembers the last message
+    /// sent to each channel, and makes such messages differ from the previous one by appending
+    /// a space and an invisible character (`U+E0000`). Repeating the same message
+    /// alternates between the original text and the text with the suffix. Messages that
+    /// Twitch refused or that could not be sent are not remembered.
+    ///
+    /// This applies to all outgoing `PRIVMSG`s. Default: `false`
+    pub duplicate_message_bypass: bool,
+
+    /// Configures how overlong messages are split into multiple messages by
+    /// [`say_split()`](crate::TwitchIRCClient::say_split) and its variants. See
+    /// [`MessageSplitting`] for the options and their defaults.
+    pub message_splitting: MessageSplitting,
+
+    /// Only incoming messages with these commands (e.g. `PRIVMSG`) are fully parsed into their
+    /// specific [`ServerMessage`](crate::message::ServerMessage) variant. Messages with other
+    /// commands are emitted without being parsed, as the generic variant of `ServerMessage`.
+    /// Their [`IRCMessage`](crate::message::IRCMessage) can be obtained with
+    /// [`ServerMessage::source()`](crate::message::ServerMessage::source) or `IRCMessage::from`,
+    /// and parsed later with `ServerMessage::try_from` if needed.
+    ///
+    /// This saves the time spent on parsing the emotes, badges and other details of messages
+    /// that the application does not look at, which adds up for clients listening to a large
+    /// volume of messages.
+    ///
+    /// The commands are not case-sensitive. `JOIN`, `NOTICE`, `PART`, `PING`, `PONG`,
+    /// `RECONNECT`, `ROOMSTATE`, `USERSTATE` and `WHISPER` messages are always parsed, since the
+    /// client itself needs them.
+    ///
+    /// Default: `None`, which parses all messages.
+    pub parsed_commands: Option<HashSet<String>>,
+
     /// Disable or enable and configure the collection of metrics on this `TwitchIRCClient`
     /// using the `prometheus` crate. See more information about the possible options on the
     /// [`MetricsConfig`] enum.
@@ -77,6 +209,23 @@ pub struct ClientConfig<L: LoginCredentials> {
     /// * `twitchirc_connections_created` on the other hand tracks how many times, since
     ///   the creation of the client, a new connection has been made.
     ///
+    /// * `twitchirc_privmsgs_queued` counts how many `PRIVMSG`s are currently held back by the
+    ///   [rate limiter](ClientConfig::privmsg_rate_limits) (Gauge).
+    ///
+    /// * `twitchirc_privmsgs_delayed` counts every `PRIVMSG` that could not be sent immediately
+    ///   because of the [rate limiter](ClientConfig::privmsg_rate_limits) (Counter).
+    ///
+    /// * `twitchirc_joins_queued` counts how many `JOIN`s are currently held back by the
+    ///   [join rate limiter](ClientConfig::join_rate_limiter) (Gauge).
+    ///
+    /// * `twitchirc_reconnect_backoff_seconds` is the delay new connections currently wait for
+    ///   because of the [reconnect backoff](ClientConfig::reconnect_backoff) or failed logins,
+    ///   before jitter is applied, or 0 if new connections are not delayed (Gauge).
+    ///
+    /// * `twitchirc_incoming_messages_dropped` counts the incoming messages dropped because the
+    ///   receiver of a client created with [`new_bounded`](crate::TwitchIRCClient::new_bounded)
+    ///   was full (Counter).
+    ///
     /// Also see the `metrics` example in the `examples` directory of the repository, which contains
     /// further help for getting up to speed with this feature.
     #[cfg(feature = "metrics-collection")]
@@ -122,6 +271,107 @@ pub struct ClientConfig<L: LoginCredentials> {
     pub tracing_identifier: Option<Cow<'static, str>>,
 }
 
+/// The limits used to rate-limit outgoing `PRIVMSG`s.
+///
+/// For more details, see [`ClientConfig::privmsg_rate_limits`]. The defaults are the
+/// [limits documented by Twitch](https://dev.twitch.tv/docs/chat/#rate-limits) for regular
+/// accounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivmsgRateLimits {
+    /// How many messages can be sent within `window`, across all channels. A value of 0
+    /// is treated as 1. Default: 20
+    pub messages_per_window: usize,
+
+    /// How many messages can be sent within `window` if the message is sent to a channel where
+    /// the logged in user is the broadcaster, a moderator or a VIP. A value of 0 is treated as 1.
+    /// Default: 100
+    ///
+    /// Messages sent to other channels count towards this limit as well, and the other way round.
+    pub privileged_messages_per_window: usize,
+
+    /// The period of time the above limits apply to. Default: 30 seconds
+    pub window: Duration,
+
+    /// Minimum time between two messages sent to the same channel, unless the logged in user
+    /// is the broadcaster, a moderator or a VIP in that channel. If slow mode is active in
+    /// the channel, the slow mode duration is used instead if it is longer. Default: 1 second
+    pub channel_message_interval: Duration,
+}
+
+impl Default for PrivmsgRateLimits {
+    fn default() -> Self {
+        PrivmsgRateLimits {
+            messages_per_window: 20,
+            privileged_messages_per_window: 100,
+            window: Duration::from_secs(30),
+            channel_message_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Options for the backoff applied to new connections after connections failed.
+///
+/// After the `n`-th consecutive failure, new connections wait for
+/// `initial_delay * multiplier^(n - 1)` (at most `max_delay`), randomly varied by `jitter`,
+/// before connecting. For more details, see [`ClientConfig::reconnect_backoff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectBackoff {
+    /// Delay after the first failure. Default: 1 second
+    pub initial_delay: Duration,
+
+    /// Factor the delay is multiplied with for every further consecutive failure. Default: 2
+    pub multiplier: f64,
+
+    /// Upper bound for the delay (before `jitter` is applied). Default: 60 seconds
+    pub max_delay: Duration,
+
+    /// Fraction by which the delay is randomly varied in both directions, e.g. with `0.2`
+    /// a delay of 10 seconds becomes a random delay between 8 and 12 seconds. This prevents
+    /// many clients from reconnecting at the same time. Default: 0.2
+    pub jitter: f64,
+
+    /// The consecutive failures are forgotten once a connection has stayed open for this
+    /// long. Default: 60 seconds
+    pub reset_after: Duration,
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        ReconnectBackoff {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(60),
+            jitter: 0.2,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Options for splitting overlong chat messages into multiple messages.
+///
+/// For more details, see [`ClientConfig::message_splitting`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSplitting {
+    /// Maximum length of a single message, in characters (Unicode scalar values). This
+    /// includes the prefix added by e.g. `say()` to prevent commands from being executed, and
+    /// the suffix added by the [duplicate message bypass](ClientConfig::duplicate_message_bypass)
+    /// if it is enabled. Default: 500, which is the limit Twitch imposes on chat messages.
+    pub max_message_length: usize,
+
+    /// Appended to every message except the last one to indicate that the message continues.
+    /// Counts towards the `max_message_length`. Default: `" ..."`
+    pub continuation_marker: Cow<'static, str>,
+}
+
+impl Default for MessageSplitting {
+    fn default() -> Self {
+        MessageSplitting {
+            max_message_length: 500,
+            continuation_marker: Cow::Borrowed(" ..."),
+        }
+    }
+}
+
 /// Used to configure the options around metrics collection using the `prometheus` crate.
 ///
 /// For more details, see [`ClientConfig::metrics_config`].
@@ -177,6 +427,21 @@ impl<L: LoginCredentials> ClientConfig<L> {
             connection_rate_limiter: Arc::new(Semaphore::new(1)),
             new_connection_every: Duration::from_secs(2),
             connect_timeout: Duration::from_secs(20),
+            reconnect_backoff: None,
+            join_timeout: Duration::from_secs(10),
+            join_retry_interval: None,
+            join_rate_limiter: None,
+            channel_batch_window: None,
+            rebalance_interval: None,
+            idle_connection_timeout: None,
+            shutdown_timeout: Duration::from_secs(10),
+            server_endpoint: ServerEndpoint::default(),
+            delivery_confirmation_timeout: Duration::from_secs(10),
+            privmsg_rate_limits: None,
+            low_priority_message_expiry: None,
+            duplicate_message_bypass: false,
+            message_splitting: MessageSplitting::default(),
+            parsed_commands: None,
 
             #[cfg(feature = "metrics-collection")]
             metrics_config: MetricsConfig::default(),
@@ -185,6 +450,33 @@ impl<L: LoginCredentials> ClientConfig<L> {
     }
 }
 
+/// Commands the client reacts to itself, see [`ClientConfig::parsed_commands`].
+const ALWAYS_PARSED_COMMANDS: [&str; 9] = [
+    "JOIN",
+    "NOTICE",
+    "PART",
+    "PING",
+    "PONG",
+    "RECONNECT",
+    "ROOMSTATE",
+    "USERSTATE",
+    "WHISPER",
+];
+
+impl<L: LoginCredentials> ClientConfig<L> {
+    /// Whether incoming messages with this command should be parsed into their specific
+    /// `ServerMessage` variant. Expects `parsed_commands` to be uppercase, which the client
+    /// ensures when it is created.
+    pub(crate) fn parses_command(&self, command: &str) -> bool {
+        match &self.parsed_commands {
+            None => true,
+            Some(parsed_commands) => {
+                parsed_commands.contains(command) || ALWAYS_PARSED_COMMANDS.contains(&command)
+            }
+        }
+    }
+}
+
 impl Default for ClientConfig<StaticLoginCredentials> {
     fn default() -> ClientConfig<StaticLoginCredentials> {
         ClientConfig::new_simple(StaticLoginCredentials::anonymous())
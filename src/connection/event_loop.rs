@@ -1,11 +1,16 @@
+use crate::client::MessagePriority;
+use crate::client::incoming::{IncomingPermit, IncomingQueue};
+use crate::client::pool_connection::PendingConfirmation;
 use crate::config::ClientConfig;
 use crate::connection::ConnectionIncomingMessage;
+use crate::connection::outgoing_queue::OutgoingQueue;
 use crate::error::Error;
 use crate::irc;
 use crate::login::{CredentialsPair, LoginCredentials};
 use crate::message::AsRawIRC;
 use crate::message::IRCMessage;
 use crate::message::commands::ServerMessage;
+use crate::message::{NoticeId, NoticeMessage};
 #[cfg(feature = "metrics-collection")]
 use crate::metrics::MetricsBundle;
 use crate::transport::Transport;
@@ -16,13 +21,64 @@ use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::sync::{Arc, Weak};
 use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 use tokio::time::{Duration, Instant, interval_at};
 use tracing::{Instrument, debug_span, info_span};
 
+/// A message to be sent out by a connection.
+#[derive(Debug)]
+pub(crate) struct OutgoingMessage<T: Transport, L: LoginCredentials> {
+    pub message: IRCMessage,
+    pub priority: MessagePriority,
+    /// The message is dropped if it could not be sent by this time.
+    pub expires_at: Option<Instant>,
+    /// Notified once the message was sent, or failed to be sent.
+    pub reply_sender: Option<oneshot::Sender<Result<(), Error<T, L>>>>,
+    /// Set for `PRIVMSG`s sent by the client, handed back to the client once the message
+    /// is written or dropped.
+    pub pending_confirmation: Option<PendingConfirmation<T, L>>,
+}
+
+impl<T: Transport, L: LoginCredentials> OutgoingMessage<T, L> {
+    pub fn new(message: IRCMessage, priority: MessagePriority) -> OutgoingMessage<T, L> {
+        OutgoingMessage {
+            message,
+            priority,
+            expires_at: None,
+            reply_sender: None,
+            pending_confirmation: None,
+        }
+    }
+
+    pub fn fail(self, error: Error<T, L>) {
+        if let Some(reply_sender) = self.reply_sender {
+            reply_sender.send(Err(error)).ok();
+        }
+    }
+
+    /// Like [`fail`](Self::fail), but also lets the client know that the `PRIVMSG` was
+    /// not sent, so it does not wait for Twitch to answer it.
+    pub fn fail_and_report(
+        mut self,
+        error: Error<T, L>,
+        connection_incoming_tx: &mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
+    ) {
+        if let Some(pending_confirmation) = self.pending_confirmation.take() {
+            connection_incoming_tx
+                .send(ConnectionIncomingMessage::PrivmsgNotSent(
+                    pending_confirmation,
+                    error.clone(),
+                ))
+                .ok();
+        }
+        self.fail(error);
+    }
+}
+
 #[derive(Debug)]
 pub(crate) enum ConnectionLoopCommand<T: Transport, L: LoginCredentials> {
     // commands that come from Connection methods
-    SendMessage(IRCMessage, Option<oneshot::Sender<Result<(), Error<T, L>>>>),
+    SendMessage(OutgoingMessage<T, L>),
 
     // comes from the init task
     TransportInitFinished(Result<(T, CredentialsPair), Error<T, L>>),
@@ -31,21 +87,25 @@ pub(crate) enum ConnectionLoopCommand<T: Transport, L: LoginCredentials> {
     SendError(Arc<T::OutgoingError>),
 
     // commands that come from the incoming loop
-    // Some(Ok(_)) is an ordinary message, Some(Err(_)) an error, and None an EOF (end of stream)
-    IncomingMessage(Option<Result<IRCMessage, Error<T, L>>>),
+    // Some(Ok(_)) is an ordinary message, Some(Err(_)) an error, and None an EOF (end of stream).
+    // The permit holds room for the message in the bounded receiver of the client, if any.
+    IncomingMessage(
+        Option<Result<IRCMessage, Error<T, L>>>,
+        Option<IncomingPermit>,
+    ),
 
     // commands that come from the ping loop
     SendPing(),
     CheckPong(),
+
+    // comes from the client loop when the client is shut down. The sender is notified once
+    // the connection is closed and its background tasks have ended.
+    Close(oneshot::Sender<()>),
 }
 
 #[enum_dispatch]
 trait ConnectionLoopStateMethods<T: Transport, L: LoginCredentials> {
-    fn send_message(
-        &mut self,
-        message: IRCMessage,
-        reply_sender: Option<oneshot::Sender<Result<(), Error<T, L>>>>,
-    );
+    fn send_message(&mut self, message: OutgoingMessage<T, L>);
     fn on_transport_init_finished(
         self,
         init_result: Result<(T, CredentialsPair), Error<T, L>>,
@@ -54,9 +114,11 @@ trait ConnectionLoopStateMethods<T: Transport, L: LoginCredentials> {
     fn on_incoming_message(
         self,
         maybe_message: Option<Result<IRCMessage, Error<T, L>>>,
+        permit: Option<IncomingPermit>,
     ) -> ConnectionLoopState<T, L>;
     fn send_ping(&mut self);
     fn check_pong(self) -> ConnectionLoopState<T, L>;
+    fn close(self, done_sender: oneshot::Sender<()>) -> ConnectionLoopState<T, L>;
 }
 
 #[enum_dispatch(ConnectionLoopStateMethods < T, L >)]
@@ -74,41 +136,61 @@ pub(crate) struct ConnectionLoopWorker<T: Transport, L: LoginCredentials> {
 }
 
 impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
+    /// Spawns the worker, returning the sender for its commands. The worker itself only
+    /// holds a weak reference to the sender.
     pub fn spawn(
         config: Arc<ClientConfig<L>>,
         connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
-        connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
-        connection_loop_rx: mpsc::UnboundedReceiver<ConnectionLoopCommand<T, L>>,
         connection_id: usize,
+        connect_delay: Duration,
+        incoming_backpressure: Option<Arc<IncomingQueue>>,
         #[cfg(feature = "metrics-collection")] metrics: Option<MetricsBundle>,
-    ) {
+    ) -> Arc<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>> {
+        let (connection_loop_tx_strong, connection_loop_rx) = mpsc::unbounded_channel();
+        let connection_loop_tx_strong = Arc::new(connection_loop_tx_strong);
+        let connection_loop_tx = Arc::downgrade(&connection_loop_tx_strong);
+
+        let main_connection_span = info_span!("connection", id = connection_id);
+        let _enter = main_connection_span.enter();
+        let init_task = tokio::spawn(
+            ConnectionLoopWorker::run_init_task(
+                Arc::clone(&config),
+                connect_delay,
+                Weak::clone(&connection_loop_tx),
+            )
+            .instrument(info_span!("init_task")),
+        );
+
         let worker = ConnectionLoopWorker {
             connection_loop_rx,
             state: ConnectionLoopState::Initializing(ConnectionLoopInitializingState {
+                config,
                 commands_queue: VecDeque::new(),
-                connection_loop_tx: Weak::clone(&connection_loop_tx),
+                connection_loop_tx,
                 connection_incoming_tx,
+                init_task,
+                incoming_backpressure,
                 #[cfg(feature = "metrics-collection")]
                 metrics: metrics.clone(),
             }),
             #[cfg(feature = "metrics-collection")]
             metrics,
         };
-
-        let main_connection_span = info_span!("connection", id = connection_id);
-        let _enter = main_connection_span.enter();
-        tokio::spawn(
-            ConnectionLoopWorker::run_init_task(config, connection_loop_tx)
-                .instrument(info_span!("init_task")),
-        );
         tokio::spawn(worker.run().instrument(info_span!("main_loop")));
+
+        connection_loop_tx_strong
     }
 
     async fn run_init_task(
         config: Arc<ClientConfig<L>>,
+        connect_delay: Duration,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
     ) {
         tracing::debug!("Spawned connection init task");
+        if !connect_delay.is_zero() {
+            tracing::info!("Waiting {:?} before connecting", connect_delay);
+            tokio::time::sleep(connect_delay).await;
+        }
         // async{}.await is used in place of a try block since they are not stabilized yet
         // TODO revise this once try blocks are stabilized
         let res = async {
@@ -126,7 +208,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
                 .await;
             tracing::trace!("Successfully got permit to open transport.");
 
-            let connect_attempt = T::new();
+            let connect_attempt = T::new(&config.server_endpoint);
             let timeout = tokio::time::sleep(config.connect_timeout);
 
             let transport = tokio::select! {
@@ -175,8 +257,8 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
     /// Process a command, consuming the current state and returning a new state
     fn process_command(mut self, command: ConnectionLoopCommand<T, L>) -> Self {
         match command {
-            ConnectionLoopCommand::SendMessage(message, reply_sender) => {
-                self.state.send_message(message, reply_sender);
+            ConnectionLoopCommand::SendMessage(message) => {
+                self.state.send_message(message);
             }
             ConnectionLoopCommand::TransportInitFinished(init_result) => {
                 self.state = self.state.on_transport_init_finished(init_result);
@@ -184,7 +266,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
             ConnectionLoopCommand::SendError(error) => {
                 self.state = self.state.on_send_error(error);
             }
-            ConnectionLoopCommand::IncomingMessage(maybe_msg) => {
+            ConnectionLoopCommand::IncomingMessage(maybe_msg, permit) => {
                 match &maybe_msg {
                     Some(Ok(msg)) => {
                         tracing::trace!("< {}", msg.as_raw_irc());
@@ -200,31 +282,34 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopWorker<T, L> {
                     None => tracing::trace!("EOF from transport"),
                 }
 
-                self.state = self.state.on_incoming_message(maybe_msg);
+                self.state = self.state.on_incoming_message(maybe_msg, permit);
             }
             ConnectionLoopCommand::SendPing() => self.state.send_ping(),
             ConnectionLoopCommand::CheckPong() => {
                 self.state = self.state.check_pong();
             }
+            ConnectionLoopCommand::Close(done_sender) => {
+                self.state = self.state.close(done_sender);
+            }
         }
         self
     }
 }
 
-type CommandQueue<T, L> = VecDeque<(IRCMessage, Option<oneshot::Sender<Result<(), Error<T, L>>>>)>;
-type MessageReceiver<T, L> =
-    mpsc::UnboundedReceiver<(IRCMessage, Option<oneshot::Sender<Result<(), Error<T, L>>>>)>;
-type MessageSender<T, L> =
-    mpsc::UnboundedSender<(IRCMessage, Option<oneshot::Sender<Result<(), Error<T, L>>>>)>;
+type CommandQueue<T, L> = VecDeque<OutgoingMessage<T, L>>;
 
 //
 // INITIALIZING STATE
 //
 struct ConnectionLoopInitializingState<T: Transport, L: LoginCredentials> {
+    config: Arc<ClientConfig<L>>,
     // a list of queued up ConnectionLoopCommand::SendMessage messages
     commands_queue: CommandQueue<T, L>,
     connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
     connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
+    init_task: JoinHandle<()>,
+    /// passed on to the incoming forwarder, see `Connection::new`.
+    incoming_backpressure: Option<Arc<IncomingQueue>>,
     #[cfg(feature = "metrics-collection")]
     metrics: Option<MetricsBundle>,
 }
@@ -233,10 +318,8 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
     fn transition_to_closed(self, err: Error<T, L>) -> ConnectionLoopState<T, L> {
         tracing::info!("Closing connection, reason: {}", err);
 
-        for (_message, return_sender) in self.commands_queue {
-            if let Some(return_sender) = return_sender {
-                return_sender.send(Err(err.clone())).ok();
-            }
+        for message in self.commands_queue {
+            message.fail_and_report(err.clone(), &self.connection_incoming_tx);
         }
 
         self.connection_incoming_tx
@@ -246,6 +329,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
         // return the new state the connection should take on
         ConnectionLoopState::Closed(ConnectionLoopClosedState {
             reason_for_closure: err,
+            connection_incoming_tx: self.connection_incoming_tx,
         })
     }
 
@@ -253,9 +337,18 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
         mut transport_incoming: T::Incoming,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
         mut shutdown_notify: oneshot::Receiver<()>,
+        incoming_backpressure: Option<Arc<IncomingQueue>>,
     ) {
         tracing::debug!("Spawned incoming messages forwarder");
         loop {
+            let permit = match &incoming_backpressure {
+                Some(incoming_backpressure) => tokio::select! {
+                    _ = &mut shutdown_notify => break,
+                    permit = incoming_backpressure.reserve() => Some(permit),
+                },
+                None => None,
+            };
+
             tokio::select! {
                 _ = &mut shutdown_notify => {
                     // got kill signal
@@ -269,7 +362,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
                     }));
 
                     if let Some(connection_loop_tx) = connection_loop_tx.upgrade() {
-                        connection_loop_tx.send(ConnectionLoopCommand::IncomingMessage(incoming_message)).ok();
+                        connection_loop_tx.send(ConnectionLoopCommand::IncomingMessage(incoming_message, permit)).ok();
                     } else {
                         break;
                     }
@@ -285,11 +378,16 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
 
     async fn run_outgoing_forward_task(
         mut transport_outgoing: T::Outgoing,
-        mut messages_rx: MessageReceiver<T, L>,
+        outgoing_queue: Arc<OutgoingQueue<T, L>>,
         connection_loop_tx: Weak<mpsc::UnboundedSender<ConnectionLoopCommand<T, L>>>,
     ) {
         tracing::debug!("Spawned outgoing messages forwarder");
-        while let Some((message, reply_sender)) = messages_rx.recv().await {
+        while let Some(OutgoingMessage {
+            message,
+            reply_sender,
+            ..
+        }) = outgoing_queue.pop().await
+        {
             let res = transport_outgoing.send(message).await.map_err(Arc::new);
 
             // The error is cloned and sent both to the calling method as well as
@@ -306,6 +404,10 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
                 reply_sender.send(res.map_err(Error::OutgoingError)).ok();
             }
         }
+
+        // the queue was closed, the connection is being closed
+        transport_outgoing.close().await.ok();
+        tracing::debug!("Outgoing messages forwarder ended");
     }
 
     async fn run_ping_task(
@@ -353,12 +455,8 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopInitializingState<T, L> {
 impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     for ConnectionLoopInitializingState<T, L>
 {
-    fn send_message(
-        &mut self,
-        message: IRCMessage,
-        reply_sender: Option<oneshot::Sender<Result<(), Error<T, L>>>>,
-    ) {
-        self.commands_queue.push_back((message, reply_sender));
+    fn send_message(&mut self, message: OutgoingMessage<T, L>) {
+        self.commands_queue.push_back(message);
     }
 
     fn on_transport_init_finished(
@@ -372,27 +470,29 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                 let (transport_incoming, transport_outgoing) = transport.split();
 
                 let (kill_incoming_loop_tx, kill_incoming_loop_rx) = oneshot::channel();
-                tokio::spawn(
+                let incoming_task = tokio::spawn(
                     ConnectionLoopInitializingState::run_incoming_forward_task(
                         transport_incoming,
                         Weak::clone(&self.connection_loop_tx),
                         kill_incoming_loop_rx,
+                        self.incoming_backpressure,
                     )
                     .instrument(info_span!("incoming_forward_task")),
                 );
 
-                let (outgoing_messages_tx, outgoing_messages_rx) = mpsc::unbounded_channel();
-                tokio::spawn(
+                let outgoing_queue =
+                    Arc::new(OutgoingQueue::new(self.connection_incoming_tx.clone()));
+                let outgoing_task = tokio::spawn(
                     ConnectionLoopInitializingState::run_outgoing_forward_task(
                         transport_outgoing,
-                        outgoing_messages_rx,
+                        Arc::clone(&outgoing_queue),
                         Weak::clone(&self.connection_loop_tx),
                     )
                     .instrument(info_span!("outgoing_forward_task")),
                 );
 
                 let (kill_pinger_tx, kill_pinger_rx) = oneshot::channel();
-                tokio::spawn(
+                let ping_task = tokio::spawn(
                     ConnectionLoopInitializingState::run_ping_task(
                         Weak::clone(&self.connection_loop_tx),
                         kill_pinger_rx,
@@ -401,32 +501,43 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                 );
 
                 // transition our own state from Initializing to Open
-                #[cfg(feature = "metrics-collection")]
                 self.connection_incoming_tx
                     .send(ConnectionIncomingMessage::StateOpen)
                     .ok();
 
+                let login = credentials.login.clone();
+                let token = credentials.token.clone();
                 let mut new_state = ConnectionLoopState::Open(ConnectionLoopOpenState {
+                    config: self.config,
+                    credentials,
                     connection_incoming_tx: self.connection_incoming_tx,
-                    outgoing_messages_tx,
+                    outgoing_queue,
                     pong_received: false,
                     kill_incoming_loop_tx: Some(kill_incoming_loop_tx),
                     kill_pinger_tx: Some(kill_pinger_tx),
+                    background_tasks: vec![incoming_task, outgoing_task, ping_task],
                     #[cfg(feature = "metrics-collection")]
                     metrics: self.metrics,
                 });
 
-                new_state.send_message(
+                // the login has the highest priority, so it precedes all queued messages
+                new_state.send_message(OutgoingMessage::new(
                     irc!["CAP", "REQ", "twitch.tv/tags twitch.tv/commands"],
-                    None,
-                );
-                if let Some(token) = credentials.token {
-                    new_state.send_message(irc!["PASS", format!("oauth:{}", token)], None);
+                    MessagePriority::High,
+                ));
+                if let Some(token) = token {
+                    new_state.send_message(OutgoingMessage::new(
+                        irc!["PASS", format!("oauth:{}", token)],
+                        MessagePriority::High,
+                    ));
                 }
-                new_state.send_message(irc!["NICK", credentials.login], None);
+                new_state.send_message(OutgoingMessage::new(
+                    irc!["NICK", login],
+                    MessagePriority::High,
+                ));
 
-                for (message, return_sender) in self.commands_queue {
-                    new_state.send_message(message, return_sender);
+                for message in self.commands_queue {
+                    new_state.send_message(message);
                 }
 
                 new_state
@@ -446,6 +557,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     fn on_incoming_message(
         self,
         _maybe_message: Option<Result<IRCMessage, Error<T, L>>>,
+        _permit: Option<IncomingPermit>,
     ) -> ConnectionLoopState<T, L> {
         unreachable!("messages cannot come in while initializing")
     }
@@ -457,19 +569,46 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     fn check_pong(self) -> ConnectionLoopState<T, L> {
         unreachable!("pinger should not run while initializing")
     }
+
+    fn close(self, done_sender: oneshot::Sender<()>) -> ConnectionLoopState<T, L> {
+        tracing::info!("Closing connection before it was opened");
+        self.init_task.abort();
+
+        for message in self.commands_queue {
+            message.fail_and_report(Error::ClientShutDown, &self.connection_incoming_tx);
+        }
+
+        let init_task = self.init_task;
+        tokio::spawn(async move {
+            init_task.await.ok();
+            done_sender.send(()).ok();
+        });
+
+        ConnectionLoopState::Closed(ConnectionLoopClosedState {
+            reason_for_closure: Error::ClientShutDown,
+            connection_incoming_tx: self.connection_incoming_tx,
+        })
+    }
 }
 
 //
 // OPEN STATE
 //
 struct ConnectionLoopOpenState<T: Transport, L: LoginCredentials> {
+    config: Arc<ClientConfig<L>>,
+    /// the credentials this connection logged in with.
+    credentials: CredentialsPair,
     connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
-    outgoing_messages_tx: MessageSender<T, L>,
+    /// Messages waiting to be sent out by the outgoing forwarder.
+    outgoing_queue: Arc<OutgoingQueue<T, L>>,
     pong_received: bool,
     /// To kill the background pinger and forward tasks when this gets dropped.
     /// These fields are wrapped in `Option` so we can use `take()` in the Drop implementation.
     kill_incoming_loop_tx: Option<oneshot::Sender<()>>,
     kill_pinger_tx: Option<oneshot::Sender<()>>,
+    /// The incoming and outgoing forwarders and the pinger, awaited when the connection
+    /// is closed cleanly.
+    background_tasks: Vec<JoinHandle<()>>,
     #[cfg(feature = "metrics-collection")]
     metrics: Option<MetricsBundle>,
 }
@@ -489,35 +628,48 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopOpenState<T, L> {
         // return the new state the connection should take on
         ConnectionLoopState::Closed(ConnectionLoopClosedState {
             reason_for_closure: cause,
+            connection_incoming_tx: self.connection_incoming_tx.clone(),
         })
     }
 }
 
+impl<T: Transport, L: LoginCredentials> ConnectionLoopOpenState<T, L> {
+    /// Let the `LoginCredentials` know that Twitch rejected the credentials.
+    fn notify_login_failed(&self) {
+        let config = Arc::clone(&self.config);
+        let credentials = self.credentials.clone();
+        tokio::spawn(
+            async move {
+                config.login_credentials.on_login_failed(&credentials).await;
+            }
+            .instrument(debug_span!("notify_login_failed_task")),
+        );
+    }
+}
+
 impl<T: Transport, L: LoginCredentials> Drop for ConnectionLoopOpenState<T, L> {
     fn drop(&mut self) {
         self.kill_incoming_loop_tx.take().unwrap().send(()).ok();
         self.kill_pinger_tx.take().unwrap().send(()).ok();
+        // the outgoing forwarder ends once it has sent out the remaining messages
+        self.outgoing_queue.close();
     }
 }
 
 impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     for ConnectionLoopOpenState<T, L>
 {
-    fn send_message(
-        &mut self,
-        message: IRCMessage,
-        reply_sender: Option<oneshot::Sender<Result<(), Error<T, L>>>>,
-    ) {
-        tracing::trace!("> {}", message.as_raw_irc());
+    fn send_message(&mut self, message: OutgoingMessage<T, L>) {
+        tracing::trace!("> {}", message.message.as_raw_irc());
         #[cfg(feature = "metrics-collection")]
         if let Some(ref metrics) = self.metrics {
             metrics
                 .messages_sent
-                .with_label_values(&[&message.command])
+                .with_label_values(&[&message.message.command])
                 .inc();
         }
 
-        self.outgoing_messages_tx.send((message, reply_sender)).ok();
+        self.outgoing_queue.push(message);
     }
 
     fn on_transport_init_finished(
@@ -534,6 +686,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     fn on_incoming_message(
         mut self,
         maybe_message: Option<Result<IRCMessage, Error<T, L>>>,
+        permit: Option<IncomingPermit>,
     ) -> ConnectionLoopState<T, L> {
         match maybe_message {
             None => {
@@ -549,21 +702,29 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                 // in a connection abort. This is by design. See for example
                 // https://github.com/robotty/dank-twitch-irc/issues/22.
                 // The message will just be ignored instead
-                let server_message = ServerMessage::try_from(irc_message);
+                let server_message = if self.config.parses_command(&irc_message.command) {
+                    ServerMessage::try_from(irc_message)
+                } else {
+                    Ok(ServerMessage::new_generic(irc_message))
+                };
 
                 match server_message {
                     Ok(server_message) => {
                         self.connection_incoming_tx
-                            .send(ConnectionIncomingMessage::IncomingMessage(Box::new(
-                                server_message.clone(),
-                            )))
+                            .send(ConnectionIncomingMessage::IncomingMessage(
+                                Box::new(server_message.clone()),
+                                permit,
+                            ))
                             .ok();
 
                         // handle message
                         // react to PING, PONG and RECONNECT
                         match &server_message {
                             ServerMessage::Ping(_) => {
-                                self.send_message(irc!["PONG", "tmi.twitch.tv"], None);
+                                self.send_message(OutgoingMessage::new(
+                                    irc!["PONG", "tmi.twitch.tv"],
+                                    MessagePriority::High,
+                                ));
                             }
                             ServerMessage::Pong(_) => {
                                 tracing::trace!("Received pong");
@@ -573,6 +734,22 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                                 // disconnect
                                 return self.transition_to_closed(Error::ReconnectCmd);
                             }
+                            ServerMessage::Notice(NoticeMessage {
+                                channel_login: None,
+                                notice_id:
+                                    Some(
+                                        NoticeId::LoginAuthenticationFailed
+                                        | NoticeId::ImproperlyFormattedAuth,
+                                    ),
+                                message_text,
+                                ..
+                            }) => {
+                                // Twitch closes the connection after this notice
+                                self.notify_login_failed();
+                                return self.transition_to_closed(Error::LoginFailed(
+                                    message_text.clone(),
+                                ));
+                            }
                             _ => {}
                         }
                     }
@@ -582,9 +759,10 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
                             parse_error
                         );
                         self.connection_incoming_tx
-                            .send(ConnectionIncomingMessage::IncomingMessage(Box::new(
-                                ServerMessage::new_generic(IRCMessage::from(parse_error)),
-                            )))
+                            .send(ConnectionIncomingMessage::IncomingMessage(
+                                Box::new(ServerMessage::new_generic(IRCMessage::from(parse_error))),
+                                permit,
+                            ))
                             .ok();
                     }
                 }
@@ -597,7 +775,11 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
 
     fn send_ping(&mut self) {
         self.pong_received = false;
-        self.send_message(irc!["PING", "tmi.twitch.tv"], None);
+        // a PING held up by other messages could make the connection time out
+        self.send_message(OutgoingMessage::new(
+            irc!["PING", "tmi.twitch.tv"],
+            MessagePriority::High,
+        ));
     }
 
     fn check_pong(self) -> ConnectionLoopState<T, L> {
@@ -609,6 +791,30 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
             self.transition_to_closed(Error::PingTimeout)
         }
     }
+
+    fn close(mut self, done_sender: oneshot::Sender<()>) -> ConnectionLoopState<T, L> {
+        tracing::info!("Closing connection");
+        // the QUIT goes out after all other messages
+        self.send_message(OutgoingMessage::new(irc!["QUIT"], MessagePriority::Low));
+
+        // dropping the state kills the incoming forwarder and the pinger, and the outgoing
+        // forwarder ends after it has sent out the remaining messages (including the QUIT)
+        // and closed the transport.
+        let background_tasks = std::mem::take(&mut self.background_tasks);
+        let connection_incoming_tx = self.connection_incoming_tx.clone();
+        drop(self);
+        tokio::spawn(async move {
+            for task in background_tasks {
+                task.await.ok();
+            }
+            done_sender.send(()).ok();
+        });
+
+        ConnectionLoopState::Closed(ConnectionLoopClosedState {
+            reason_for_closure: Error::ClientShutDown,
+            connection_incoming_tx,
+        })
+    }
 }
 
 //
@@ -616,19 +822,18 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
 //
 struct ConnectionLoopClosedState<T: Transport, L: LoginCredentials> {
     reason_for_closure: Error<T, L>,
+    /// to report the `PRIVMSG`s that are sent to the closed connection.
+    connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
 }
 
 impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     for ConnectionLoopClosedState<T, L>
 {
-    fn send_message(
-        &mut self,
-        _message: IRCMessage,
-        reply_sender: Option<oneshot::Sender<Result<(), Error<T, L>>>>,
-    ) {
-        if let Some(reply_sender) = reply_sender {
-            reply_sender.send(Err(self.reason_for_closure.clone())).ok();
-        }
+    fn send_message(&mut self, message: OutgoingMessage<T, L>) {
+        message.fail_and_report(
+            self.reason_for_closure.clone(),
+            &self.connection_incoming_tx,
+        );
     }
 
     fn on_transport_init_finished(
@@ -647,6 +852,7 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
     fn on_incoming_message(
         self,
         _maybe_message: Option<Result<IRCMessage, Error<T, L>>>,
+        _permit: Option<IncomingPermit>,
     ) -> ConnectionLoopState<T, L> {
         // do nothing, stay closed
         ConnectionLoopState::Closed(self)
@@ -658,4 +864,9 @@ impl<T: Transport, L: LoginCredentials> ConnectionLoopStateMethods<T, L>
         // do nothing, stay closed
         ConnectionLoopState::Closed(self)
     }
+
+    fn close(self, done_sender: oneshot::Sender<()>) -> ConnectionLoopState<T, L> {
+        done_sender.send(()).ok();
+        ConnectionLoopState::Closed(self)
+    }
 }
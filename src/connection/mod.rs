@@ -1,5 +1,8 @@
 pub mod event_loop;
+mod outgoing_queue;
 
+use crate::client::incoming::{IncomingPermit, IncomingQueue};
+use crate::client::pool_connection::PendingConfirmation;
 use crate::config::ClientConfig;
 use crate::connection::event_loop::{ConnectionLoopCommand, ConnectionLoopWorker};
 use crate::error::Error;
@@ -9,12 +12,17 @@ use crate::message::commands::ServerMessage;
 use crate::metrics::MetricsBundle;
 use crate::transport::Transport;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
 
 #[derive(Debug)]
 pub enum ConnectionIncomingMessage<T: Transport, L: LoginCredentials> {
-    IncomingMessage(Box<ServerMessage>),
-    #[cfg(feature = "metrics-collection")]
+    IncomingMessage(Box<ServerMessage>, Option<IncomingPermit>),
+    /// A `PRIVMSG` is about to be written to the transport. This is reported before the message
+    /// is written, so it always precedes Twitch's answer to the message.
+    SendingPrivmsg(PendingConfirmation<T, L>),
+    /// A `PRIVMSG` was dropped without being written, e.g. because it expired.
+    PrivmsgNotSent(PendingConfirmation<T, L>, Error<T, L>),
     StateOpen,
     StateClosed {
         cause: Error<T, L>,
@@ -28,25 +36,27 @@ pub(crate) struct Connection<T: Transport, L: LoginCredentials> {
 
 impl<T: Transport, L: LoginCredentials> Connection<T, L> {
     /// makes a tuple with the incoming messages and the `Connection` handle for outgoing
-    /// messages.
+    /// messages. The connection waits for `connect_delay` before it starts connecting.
+    /// If `incoming_backpressure` is given, the connection reserves room in that queue
+    /// before it reads each message.
     pub fn new(
         config: Arc<ClientConfig<L>>,
         connection_id: usize,
+        connect_delay: Duration,
+        incoming_backpressure: Option<Arc<IncomingQueue>>,
         #[cfg(feature = "metrics-collection")] metrics: Option<MetricsBundle>,
     ) -> (
         mpsc::UnboundedReceiver<ConnectionIncomingMessage<T, L>>,
         Connection<T, L>,
     ) {
-        let (connection_loop_tx, connection_loop_rx) = mpsc::unbounded_channel();
         let (connection_incoming_tx, connection_incoming_rx) = mpsc::unbounded_channel();
-        let connection_loop_tx = Arc::new(connection_loop_tx);
 
-        ConnectionLoopWorker::spawn(
+        let connection_loop_tx = ConnectionLoopWorker::spawn(
             config,
             connection_incoming_tx,
-            Arc::downgrade(&connection_loop_tx),
-            connection_loop_rx,
             connection_id,
+            connect_delay,
+            incoming_backpressure,
             #[cfg(feature = "metrics-collection")]
             metrics,
         );
@@ -54,3 +64,61 @@ impl<T: Transport, L: LoginCredentials> Connection<T, L> {
         (connection_incoming_rx, Connection { connection_loop_tx })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::client::MessagePriority;
+    use crate::client::pool_connection::PendingConfirmation;
+    use crate::client::tests::test_config;
+    use crate::connection::event_loop::{ConnectionLoopCommand, OutgoingMessage};
+    use crate::connection::{Connection, ConnectionIncomingMessage};
+    use crate::irc;
+    use crate::transport::mock::{FakeTwitchServer, MockTransport};
+    use std::sync::Arc;
+    use tokio::time::{Duration, Instant};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_privmsg_sent_to_closed_connection_is_reported() {
+        let mut server = FakeTwitchServer::new();
+        let (mut incoming_messages, connection) = Connection::<MockTransport, _>::new(
+            Arc::new(test_config(&server)),
+            0,
+            Duration::ZERO,
+            None,
+            #[cfg(feature = "metrics-collection")]
+            None,
+        );
+
+        let mut fake_connection = server.accept().await;
+        fake_connection.disconnect();
+        loop {
+            if let ConnectionIncomingMessage::StateClosed { .. } =
+                incoming_messages.recv().await.unwrap()
+            {
+                break;
+            }
+        }
+
+        let mut message =
+            OutgoingMessage::new(irc!["PRIVMSG", "#pajlada", "hi"], MessagePriority::Normal);
+        message.pending_confirmation = Some(PendingConfirmation {
+            channel_login: "pajlada".to_owned(),
+            confirmation_sender: None,
+            sent_at: Instant::now(),
+            sent_message_record: None,
+        });
+        connection
+            .connection_loop_tx
+            .send(ConnectionLoopCommand::SendMessage(message))
+            .unwrap();
+
+        // the client must learn that the message was not sent, e.g. to undo its record
+        // in the duplicate message bypass
+        match incoming_messages.recv().await.unwrap() {
+            ConnectionIncomingMessage::PrivmsgNotSent(pending_confirmation, _) => {
+                assert_eq!(pending_confirmation.channel_login, "pajlada");
+            }
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+}
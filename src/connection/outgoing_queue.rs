@@ -0,0 +1,198 @@
+use crate::connection::ConnectionIncomingMessage;
+use crate::connection::event_loop::OutgoingMessage;
+use crate::error::Error;
+use crate::login::LoginCredentials;
+use crate::transport::Transport;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::{Notify, mpsc};
+use tokio::time::Instant;
+
+struct QueueState<T: Transport, L: LoginCredentials> {
+    /// one queue per priority, highest priority first. Within a priority, messages are sent
+    /// in the order they were queued.
+    queues: [VecDeque<OutgoingMessage<T, L>>; 3],
+    /// once closed, no more messages are queued, and `pop` returns `None` when the queue
+    /// is empty.
+    closed: bool,
+}
+
+/// The messages waiting to be sent out by a connection. The connection loop pushes the
+/// messages, and the outgoing forwarder pops them in the order of their priority.
+///
+/// Since this can change the order of the messages, the client is told which `PRIVMSG`s
+/// are actually written, and which are dropped.
+pub(crate) struct OutgoingQueue<T: Transport, L: LoginCredentials> {
+    state: Mutex<QueueState<T, L>>,
+    notify: Notify,
+    connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
+}
+
+impl<T: Transport, L: LoginCredentials> OutgoingQueue<T, L> {
+    pub fn new(
+        connection_incoming_tx: mpsc::UnboundedSender<ConnectionIncomingMessage<T, L>>,
+    ) -> OutgoingQueue<T, L> {
+        OutgoingQueue {
+            state: Mutex::new(QueueState {
+                queues: Default::default(),
+                closed: false,
+            }),
+            notify: Notify::new(),
+            connection_incoming_tx,
+        }
+    }
+
+    pub fn push(&self, message: OutgoingMessage<T, L>) {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            // the forwarder is gone or about to finish, the message can't be sent anymore
+            message.fail_and_report(
+                Error::RemoteUnexpectedlyClosedConnection,
+                &self.connection_incoming_tx,
+            );
+            return;
+        }
+        state.queues[message.priority.index()].push_back(message);
+        drop(state);
+        self.notify.notify_one();
+    }
+
+    /// No more messages are accepted. The messages already queued are still handed out.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// Takes the next message that is to be sent, waiting for one to be queued if necessary.
+    /// Messages that expired while waiting are answered with [`Error::MessageExpired`].
+    /// Returns `None` once the queue is closed and empty.
+    ///
+    /// The message must be written right away, since its pending confirmation is already
+    /// handed back to the client.
+    pub async fn pop(&self) -> Option<OutgoingMessage<T, L>> {
+        loop {
+            if let Some(message) = self.try_pop(Instant::now()) {
+                return message;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    /// `Some(_)` if `pop` can return right away.
+    fn try_pop(&self, now: Instant) -> Option<Option<OutgoingMessage<T, L>>> {
+        let mut state = self.state.lock().unwrap();
+        for queue in &mut state.queues {
+            while let Some(mut message) = queue.pop_front() {
+                if message
+                    .expires_at
+                    .is_some_and(|expires_at| expires_at <= now)
+                {
+                    tracing::debug!(
+                        "Dropping {} message that waited too long to be sent",
+                        message.message.command
+                    );
+                    message.fail_and_report(Error::MessageExpired, &self.connection_incoming_tx);
+                    continue;
+                }
+                if let Some(pending_confirmation) = message.pending_confirmation.take() {
+                    self.connection_incoming_tx
+                        .send(ConnectionIncomingMessage::SendingPrivmsg(
+                            pending_confirmation,
+                        ))
+                        .ok();
+                }
+                return Some(Some(message));
+            }
+        }
+
+        if state.closed { Some(None) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::MessagePriority;
+    use crate::connection::event_loop::OutgoingMessage;
+    use crate::connection::outgoing_queue::OutgoingQueue;
+    use crate::error::Error;
+    use crate::irc;
+    use crate::login::StaticLoginCredentials;
+    use crate::transport::mock::MockTransport;
+    use tokio::sync::{mpsc, oneshot};
+    use tokio::time::{Duration, Instant};
+
+    type Queue = OutgoingQueue<MockTransport, StaticLoginCredentials>;
+
+    fn queue() -> Queue {
+        let (connection_incoming_tx, _connection_incoming_rx) = mpsc::unbounded_channel();
+        Queue::new(connection_incoming_tx)
+    }
+
+    fn message(
+        text: &str,
+        priority: MessagePriority,
+    ) -> OutgoingMessage<MockTransport, StaticLoginCredentials> {
+        OutgoingMessage::new(irc!["PRIVMSG", "#pajlada", text], priority)
+    }
+
+    async fn pop_text(queue: &Queue) -> Option<String> {
+        queue.pop().await.map(|m| m.message.params[1].clone())
+    }
+
+    #[tokio::test]
+    async fn test_priority_order() {
+        let queue = queue();
+        queue.push(message("low 1", MessagePriority::Low));
+        queue.push(message("normal 1", MessagePriority::Normal));
+        queue.push(message("low 2", MessagePriority::Low));
+        queue.push(message("high 1", MessagePriority::High));
+        queue.push(message("normal 2", MessagePriority::Normal));
+        queue.close();
+
+        let mut popped = vec![];
+        while let Some(text) = pop_text(&queue).await {
+            popped.push(text);
+        }
+        assert_eq!(
+            popped,
+            vec!["high 1", "normal 1", "normal 2", "low 1", "low 2"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_expiry() {
+        let queue = queue();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        queue.push(OutgoingMessage {
+            expires_at: Some(Instant::now() + Duration::from_secs(5)),
+            reply_sender: Some(reply_tx),
+            ..message("expires", MessagePriority::Low)
+        });
+        queue.push(OutgoingMessage {
+            expires_at: Some(Instant::now() + Duration::from_secs(20)),
+            ..message("stays", MessagePriority::Low)
+        });
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(pop_text(&queue).await.unwrap(), "stays");
+        assert!(matches!(
+            reply_rx.await.unwrap(),
+            Err(Error::MessageExpired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pop_waits_for_message() {
+        let queue = std::sync::Arc::new(queue());
+        let pop_task = tokio::spawn({
+            let queue = std::sync::Arc::clone(&queue);
+            async move { pop_text(&queue).await }
+        });
+        tokio::task::yield_now().await;
+        queue.push(message("hello", MessagePriority::Normal));
+        assert_eq!(pop_task.await.unwrap().unwrap(), "hello");
+
+        queue.close();
+        assert_eq!(pop_text(&queue).await, None);
+    }
+}
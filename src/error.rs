@@ -1,6 +1,7 @@
 use crate::login::LoginCredentials;
-use crate::message::IRCParseError;
+use crate::message::{IRCParseError, NoticeId, NoticeMessage};
 use crate::transport::Transport;
+use crate::validate;
 use std::sync::Arc;
 use thiserror::Error;
 
@@ -25,6 +26,9 @@ pub enum Error<T: Transport, L: LoginCredentials> {
     /// Failed to get login credentials to log in with
     #[error("Failed to get login credentials to log in with: {0}")]
     LoginError(Arc<L::Error>),
+    /// Twitch rejected the login credentials. Contains the text of the `NOTICE` sent by Twitch.
+    #[error("Twitch rejected the login credentials: {0}")]
+    LoginFailed(String),
     /// Received RECONNECT command by IRC server
     #[error("Received RECONNECT command by IRC server")]
     ReconnectCmd,
@@ -34,6 +38,21 @@ pub enum Error<T: Transport, L: LoginCredentials> {
     /// Remote server unexpectedly closed connection
     #[error("Remote server unexpectedly closed connection")]
     RemoteUnexpectedlyClosedConnection,
+    /// The client was shut down using
+    /// [`TwitchIRCClient::shutdown`](crate::TwitchIRCClient::shutdown)
+    #[error("The client was shut down")]
+    ClientShutDown,
+    /// A [low priority](crate::MessagePriority::Low) message waited longer than the
+    /// [configured expiry](crate::ClientConfig::low_priority_message_expiry) to be sent,
+    /// and was dropped
+    #[error("The message waited too long to be sent and was dropped")]
+    MessageExpired,
+    /// A message passed to [`say_split()`](crate::TwitchIRCClient::say_split) or one of its
+    /// variants is too long, but could not be split into messages within the
+    /// [configured maximum length](crate::MessageSplitting::max_message_length), because that
+    /// leaves no room for the text, or because the message consists only of whitespace
+    #[error("The message is too long and could not be split")]
+    MessageNotSplittable,
 }
 
 impl<T: Transport, L: LoginCredentials> Clone for Error<T, L> {
@@ -45,9 +64,67 @@ impl<T: Transport, L: LoginCredentials> Clone for Error<T, L> {
             Error::OutgoingError(e) => Error::OutgoingError(Arc::clone(e)),
             Error::IRCParseError(e) => Error::IRCParseError(*e),
             Error::LoginError(e) => Error::LoginError(Arc::clone(e)),
+            Error::LoginFailed(text) => Error::LoginFailed(text.clone()),
             Error::ReconnectCmd => Error::ReconnectCmd,
             Error::PingTimeout => Error::PingTimeout,
             Error::RemoteUnexpectedlyClosedConnection => Error::RemoteUnexpectedlyClosedConnection,
+            Error::ClientShutDown => Error::ClientShutDown,
+            Error::MessageExpired => Error::MessageExpired,
+            Error::MessageNotSplittable => Error::MessageNotSplittable,
         }
     }
 }
+
+/// Errors that can occur while joining a channel using
+/// [`TwitchIRCClient::join_and_wait`](crate::TwitchIRCClient::join_and_wait).
+#[derive(Error, Debug, Clone)]
+pub enum JoinError {
+    /// The channel login is of invalid format.
+    #[error("{0}")]
+    InvalidChannelLogin(#[from] validate::Error),
+    /// Twitch refused the `JOIN` with a `NOTICE`, e.g. because the channel is suspended.
+    #[error("Join was rejected by Twitch ({reason:?}): {}", notice.message_text)]
+    Rejected {
+        /// Why the `JOIN` was refused, parsed from the `msg-id` of the `NOTICE`.
+        reason: NoticeId,
+        /// The `NOTICE` Twitch sent in response to the `JOIN`.
+        notice: Box<NoticeMessage>,
+    },
+    /// Twitch did not confirm the `JOIN` within the given timeout.
+    #[error("Twitch did not confirm the join in time")]
+    Timeout,
+    /// The client was shut down using
+    /// [`TwitchIRCClient::shutdown`](crate::TwitchIRCClient::shutdown).
+    #[error("The client was shut down")]
+    ClientShutDown,
+}
+
+/// Returned by [`TwitchIRCClient::shutdown`](crate::TwitchIRCClient::shutdown) if the
+/// connections could not be closed cleanly within the
+/// [configured timeout](crate::ClientConfig::shutdown_timeout). The connections are closed
+/// forcibly in that case.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Client did not shut down cleanly within the configured timeout")]
+pub struct ShutdownTimeout;
+
+/// Errors that can occur while sending a message with delivery confirmation, e.g. using
+/// [`TwitchIRCClient::say_confirmed`](crate::TwitchIRCClient::say_confirmed).
+#[derive(Error, Debug)]
+pub enum DeliveryError<T: Transport, L: LoginCredentials> {
+    /// The message could not be sent, or the connection it was sent over failed before
+    /// Twitch confirmed or rejected the message.
+    #[error("{0}")]
+    Send(Error<T, L>),
+    /// Twitch rejected the message with a `NOTICE`.
+    #[error("Message was rejected by Twitch ({reason:?}): {}", notice.message_text)]
+    Rejected {
+        /// Why the message was rejected, parsed from the `msg-id` of the `NOTICE`.
+        reason: NoticeId,
+        /// The `NOTICE` Twitch sent in response to the message.
+        notice: Box<NoticeMessage>,
+    },
+    /// Twitch neither confirmed nor rejected the message within the
+    /// [configured timeout](crate::ClientConfig::delivery_confirmation_timeout).
+    #[error("Twitch did not confirm the message in time")]
+    Timeout,
+}
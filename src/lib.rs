@@ -50,6 +50,7 @@
 //!   channels joined as well as number of outgoing messages)
 //! * Automatic reconnect of failed connections, automatically re-joins channels
 //! * Rate limiting of new connections
+//! * Optional rate limiting of outgoing chat messages according to the Twitch rate limits
 //! * Support for refreshing login tokens, see below
 //! * Fully parses all message types (see [`ServerMessage`](message/enum.ServerMessage.html) for all supported types)
 //! * Can connect using all protocol types supported by Twitch
@@ -214,6 +215,8 @@
 //!     * `transport-ws-rustls-webpki-roots` enables `SecureWSTransport` using [Rustls][rustls] as the TLS
 //!       implementation, and will statically embed the current [Mozilla root certificates][mozilla-roots] as the
 //!       trusted root certificates.
+//! * **`transport-mock`** enables the [`transport::mock`] module, containing the in-memory `MockTransport` and a
+//!   scriptable `FakeTwitchServer` to test your application without connecting to Twitch.
 //! * Three different feature flags are provided to enable the
 //!   [`RefreshingLoginCredentials`](crate::login::RefreshingLoginCredentials):
 //!     * `refreshing-token-native-tls` enables this feature using the OS-native TLS functionality to make secure
@@ -246,11 +249,15 @@ mod metrics;
 pub mod transport;
 pub mod validate;
 
-pub use client::TwitchIRCClient;
-pub use config::ClientConfig;
+pub use client::{
+    BoundedReceiver, ChannelStatus, ClientEvent, ConnectionSnapshot, JoinFailure, JoinRateLimiter,
+    MessagePriority, OverflowPolicy, PoolConnectionState, RoomState, SubscriptionFilter,
+    SubscriptionReceiver, TwitchIRCClient,
+};
 #[cfg(feature = "metrics-collection")]
 pub use config::MetricsConfig;
-pub use error::Error;
+pub use config::{ClientConfig, MessageSplitting, PrivmsgRateLimits, ReconnectBackoff};
+pub use error::{DeliveryError, Error, JoinError, ShutdownTimeout};
 
 #[cfg(feature = "transport-tcp")]
 pub use transport::tcp::PlainTCPTransport;
@@ -27,7 +27,11 @@ use {
     chrono::DateTime,
     chrono::Utc,
     reqwest::ClientBuilder,
-    std::{sync::Arc, time::Duration},
+    std::{
+        sync::Arc,
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    },
     thiserror::Error,
     tokio::sync::Mutex,
 };
@@ -66,6 +70,13 @@ pub trait LoginCredentials: Debug + Send + Sync + 'static {
 
     /// Get a fresh set of credentials to be used right-away.
     async fn get_credentials(&self) -> Result<CredentialsPair, Self::Error>;
+
+    /// Called when Twitch rejected the given `credentials` (as previously returned by
+    /// `get_credentials()`) while logging in, e.g. because the token was revoked or expired.
+    ///
+    /// Implementations can use this to e.g. refresh the token the next time
+    /// `get_credentials()` is called. The default implementation does nothing.
+    async fn on_login_failed(&self, _credentials: &CredentialsPair) {}
 }
 
 /// Simple `LoginCredentials` implementation that always returns the same `CredentialsPair`
@@ -210,6 +221,9 @@ pub struct RefreshingLoginCredentials<S: TokenStorage> {
     client_id: String,
     client_secret: String,
     token_storage: Arc<Mutex<S>>,
+    /// set when Twitch rejected the current token, forcing a refresh on the next
+    /// `get_credentials()` call.
+    force_refresh: Arc<AtomicBool>,
 }
 
 // Custom implementation to display [redacted] in place of the client secret
@@ -222,6 +236,7 @@ impl<S: TokenStorage> Debug for RefreshingLoginCredentials<S> {
             .field("client_id", &self.client_id)
             .field("client_secret", &"[redacted]")
             .field("token_storage", &self.token_storage)
+            .field("force_refresh", &self.force_refresh)
             .finish()
     }
 }
@@ -278,6 +293,7 @@ impl<S: TokenStorage> RefreshingLoginCredentials<S> {
             client_id,
             client_secret,
             token_storage: Arc::new(Mutex::new(token_storage)),
+            force_refresh: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -323,8 +339,9 @@ impl<S: TokenStorage> LoginCredentials for RefreshingLoginCredentials<S> {
         let token_age = (Utc::now() - current_token.created_at).to_std().unwrap();
         let max_token_age = token_expires_after.mul_f64(SHOULD_REFRESH_AFTER_FACTOR);
         let is_token_expired = token_age >= max_token_age;
+        let force_refresh = self.force_refresh.swap(false, Ordering::SeqCst);
 
-        if is_token_expired {
+        if is_token_expired || force_refresh {
             let response = self
                 .http_client
                 .post("https://id.twitch.tv/oauth2/token")
@@ -390,6 +407,24 @@ impl<S: TokenStorage> LoginCredentials for RefreshingLoginCredentials<S> {
             token: Some(current_token.access_token.clone()),
         })
     }
+
+    async fn on_login_failed(&self, credentials: &CredentialsPair) {
+        let mut token_storage = self.token_storage.lock().await;
+
+        // several connections can be rejected with the same token. Only force a refresh
+        // if the rejected token was not already replaced.
+        match token_storage.load_token().await {
+            Ok(current_token)
+                if credentials.token.as_deref() != Some(current_token.access_token.as_str()) =>
+            {
+                tracing::debug!("Rejected token was already refreshed, not refreshing again");
+            }
+            _ => {
+                tracing::info!("Twitch rejected the token, refreshing it before the next login");
+                self.force_refresh.store(true, Ordering::SeqCst);
+            }
+        }
+    }
 }
 
 /// Represents the Twitch API response to `/helix/users` API requests.
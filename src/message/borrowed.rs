@@ -0,0 +1,458 @@
+use super::tags::decode_tag_value;
+use super::{
+    AsRawIRC, IRCMessage, IRCParseError, IRCPrefix, IRCTagMap, IRCTags, format_params_as_raw_irc,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::fmt::Write;
+
+/// A protocol-level IRC message that borrows its command, parameters, tags and prefix from
+/// the line it was parsed from.
+///
+/// This is the zero-copy counterpart of [`IRCMessage`]: Parsing does not copy any part of the
+/// source line, and tag values are only unescaped when they are looked up. This makes it a good
+/// fit for applications that process large amounts of messages and only look at a few parts
+/// of each of them. Use [`IRCMessageRef::into_owned`] to get an [`IRCMessage`] that can outlive
+/// the source line.
+///
+/// # Example
+///
+/// ```
+/// use twitch_irc::message::{IRCMessage, IRCMessageRef};
+///
+/// let source = "@display-name=randers;id=123 :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Hello\\sthere";
+/// let message = IRCMessageRef::parse(source).unwrap();
+///
+/// assert_eq!(message.command, "PRIVMSG");
+/// assert_eq!(message.params, vec!["#pajlada", "Hello\\sthere"]);
+/// assert_eq!(message.tags.get("display-name").unwrap(), "randers");
+/// assert_eq!(message.into_owned(), IRCMessage::parse(source).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IRCMessageRef<'a> {
+    /// The key-value tags on this message.
+    pub tags: IRCTagsRef<'a>,
+    /// The "prefix" of this message, as defined by RFC 2812.
+    pub prefix: Option<IRCPrefixRef<'a>>,
+    /// The command of this message, converted to upper case. Only allocates if the command
+    /// was not sent in upper case already.
+    pub command: Cow<'a, str>,
+    /// The parameters of this message. See [`IRCMessage::params`].
+    pub params: Vec<&'a str>,
+}
+
+impl<'a> IRCMessageRef<'a> {
+    /// Parse a raw IRC wire-format message into an `IRCMessageRef` borrowing from `source`.
+    /// `source` should be specified without trailing newline character(s).
+    ///
+    /// This accepts and rejects exactly the same messages as [`IRCMessage::parse`].
+    pub fn parse(mut source: &'a str) -> Result<IRCMessageRef<'a>, IRCParseError> {
+        if source.bytes().any(|b| b == b'\r' || b == b'\n') {
+            return Err(IRCParseError::NewlinesInMessage);
+        }
+
+        let tags = if let Some(tags_and_remainder) = source.strip_prefix('@') {
+            let (tags_part, remainder) = tags_and_remainder
+                .split_once(' ')
+                .ok_or(IRCParseError::NoSpaceAfterTags)?;
+            source = remainder;
+
+            if tags_part.is_empty() {
+                return Err(IRCParseError::EmptyTagsDeclaration);
+            }
+
+            IRCTagsRef::parse(tags_part)
+        } else {
+            IRCTagsRef::new()
+        };
+
+        let prefix = if let Some(prefix_and_remainder) = source.strip_prefix(':') {
+            let (prefix_part, remainder) = prefix_and_remainder
+                .split_once(' ')
+                .ok_or(IRCParseError::NoSpaceAfterPrefix)?;
+            source = remainder;
+
+            if prefix_part.is_empty() {
+                return Err(IRCParseError::EmptyPrefixDeclaration);
+            }
+
+            Some(IRCPrefixRef::parse(prefix_part))
+        } else {
+            None
+        };
+
+        let (command, params_part) = match source.split_once(' ') {
+            Some((command, params_part)) => (command, Some(params_part)),
+            None => (source, None),
+        };
+
+        if command.is_empty()
+            || !command.chars().all(|c| c.is_ascii_alphabetic())
+                && !command.chars().all(|c| c.is_ascii() && c.is_numeric())
+        {
+            return Err(IRCParseError::MalformedCommand);
+        }
+
+        let command = if command.bytes().any(|b| b.is_ascii_lowercase()) {
+            Cow::Owned(command.to_ascii_uppercase())
+        } else {
+            Cow::Borrowed(command)
+        };
+
+        let mut params = vec![];
+        let mut rest = params_part;
+        while let Some(rest_str) = rest {
+            if let Some(sub_str) = rest_str.strip_prefix(':') {
+                // trailing param, remove : and consume the rest of the input
+                params.push(sub_str);
+                rest = None;
+            } else {
+                let (param, remainder) = match rest_str.split_once(' ') {
+                    Some((param, remainder)) => (param, Some(remainder)),
+                    None => (rest_str, None),
+                };
+                rest = remainder;
+
+                if param.is_empty() {
+                    return Err(IRCParseError::TooManySpacesInMiddleParams);
+                }
+                params.push(param);
+            }
+        }
+
+        Ok(IRCMessageRef {
+            tags,
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// Copies all parts of this message into a new [`IRCMessage`], unescaping all tag values.
+    #[must_use]
+    pub fn into_owned(self) -> IRCMessage {
+        IRCMessage {
+            tags: IRCTags::from(&self.tags),
+            prefix: self.prefix.map(IRCPrefix::from),
+            command: self.command.into_owned(),
+            params: self.params.into_iter().map(str::to_owned).collect(),
+        }
+    }
+}
+
+impl From<IRCMessageRef<'_>> for IRCMessage {
+    fn from(message: IRCMessageRef<'_>) -> IRCMessage {
+        message.into_owned()
+    }
+}
+
+impl AsRawIRC for IRCMessageRef<'_> {
+    fn format_as_raw_irc(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.tags.is_empty() {
+            f.write_char('@')?;
+            self.tags.format_as_raw_irc(f)?;
+            f.write_char(' ')?;
+        }
+
+        if let Some(prefix) = &self.prefix {
+            f.write_char(':')?;
+            prefix.format_as_raw_irc(f)?;
+            f.write_char(' ')?;
+        }
+
+        f.write_str(&self.command)?;
+        format_params_as_raw_irc(&self.params, f)
+    }
+}
+
+/// The [IRCv3 tags](https://ircv3.net/specs/extensions/message-tags.html) of an
+/// [`IRCMessageRef`], borrowed from the line they were parsed from.
+///
+/// The tags are kept in the order they were received in, and the values are kept in their
+/// escaped wire format until they are looked up. If a key appears more than once, the last
+/// value wins, like in [`IRCTags`].
+///
+/// # Examples
+///
+/// ```
+/// use twitch_irc::message::IRCTagsRef;
+///
+/// let tags = IRCTagsRef::parse("key=The\\sLazy\\sDog;key2");
+/// assert_eq!(tags.get("key").unwrap(), "The Lazy Dog");
+/// assert_eq!(tags.get_raw("key"), Some("The\\sLazy\\sDog"));
+/// assert_eq!(tags.get("key2").unwrap(), "");
+/// assert_eq!(tags.get("key3"), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IRCTagsRef<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> IRCTagsRef<'a> {
+    /// Creates a new empty set of tags.
+    #[must_use]
+    pub fn new() -> IRCTagsRef<'a> {
+        IRCTagsRef(Vec::new())
+    }
+
+    /// Parses the tags from their wire-format representation, without unescaping the values.
+    /// `source` should be specified without the leading `@` present in the full IRC tags.
+    #[must_use]
+    pub fn parse(source: &'a str) -> IRCTagsRef<'a> {
+        let mut tags = Vec::with_capacity(source.bytes().filter(|b| *b == b';').count() + 1);
+        for raw_tag in source.split(';') {
+            // a tag without = has an empty value
+            tags.push(raw_tag.split_once('=').unwrap_or((raw_tag, "")));
+        }
+        IRCTagsRef(tags)
+    }
+
+    /// Returns the escaped wire-format value of the tag with the given key.
+    #[must_use]
+    pub fn get_raw(&self, key: &str) -> Option<&'a str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(tag_key, _)| *tag_key == key)
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns the unescaped value of the tag with the given key. Only allocates if the
+    /// value contains escape sequences.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<Cow<'a, str>> {
+        self.get_raw(key).map(unescape_tag_value)
+    }
+
+    /// Whether a tag with the given key is present.
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|(tag_key, _)| *tag_key == key)
+    }
+
+    /// Iterates over the keys and unescaped values of the tags, in the order they were
+    /// received in. Keys that appear more than once are returned more than once.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, Cow<'a, str>)> + '_ {
+        self.0
+            .iter()
+            .map(|(key, value)| (*key, unescape_tag_value(value)))
+    }
+
+    /// The number of tags, including duplicate keys.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no tags.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn unescape_tag_value(raw: &str) -> Cow<'_, str> {
+    if raw.contains('\\') {
+        Cow::Owned(decode_tag_value(raw))
+    } else {
+        Cow::Borrowed(raw)
+    }
+}
+
+impl From<&IRCTagsRef<'_>> for IRCTags {
+    fn from(tags: &IRCTagsRef<'_>) -> IRCTags {
+        let mut owned_tags = IRCTags(IRCTagMap::with_capacity(tags.len()));
+        for (key, value) in tags.iter() {
+            owned_tags.0.insert(key.to_owned(), value.into_owned());
+        }
+        owned_tags
+    }
+}
+
+impl AsRawIRC for IRCTagsRef<'_> {
+    fn format_as_raw_irc(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(';')?;
+            }
+            // the value is still escaped
+            f.write_str(key)?;
+            f.write_char('=')?;
+            f.write_str(value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The "prefix" part of an [`IRCMessageRef`], borrowed from the line it was parsed from.
+/// See [`IRCPrefix`] for the format.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum IRCPrefixRef<'a> {
+    /// The prefix specifies only a sending server/hostname. See [`IRCPrefix::HostOnly`].
+    HostOnly {
+        /// `host` part of the prefix
+        host: &'a str,
+    },
+    /// The prefix specifies a nickname, and optionally also a username and optionally a
+    /// hostname. See [`IRCPrefix::Full`].
+    Full {
+        /// `nick` part of the prefix
+        nick: &'a str,
+        /// `user` part of the prefix
+        user: Option<&'a str>,
+        /// `host` part of the prefix
+        host: Option<&'a str>,
+    },
+}
+
+impl<'a> IRCPrefixRef<'a> {
+    /// Parse the `IRCPrefixRef` from the given string slice. `source` should be specified
+    /// without the leading `:` that precedes in full IRC messages.
+    #[must_use]
+    pub fn parse(source: &'a str) -> IRCPrefixRef<'a> {
+        match source.split_once('@') {
+            // just a hostname
+            None => IRCPrefixRef::HostOnly { host: source },
+            // full prefix (nick[!user]@host)
+            Some((nick_and_user, host)) => {
+                let (nick, user) = match nick_and_user.split_once('!') {
+                    Some((nick, user)) => (nick, Some(user)),
+                    None => (nick_and_user, None),
+                };
+                IRCPrefixRef::Full {
+                    nick,
+                    user,
+                    host: Some(host),
+                }
+            }
+        }
+    }
+}
+
+impl From<IRCPrefixRef<'_>> for IRCPrefix {
+    fn from(prefix: IRCPrefixRef<'_>) -> IRCPrefix {
+        match prefix {
+            IRCPrefixRef::HostOnly { host } => IRCPrefix::HostOnly {
+                host: host.to_owned(),
+            },
+            IRCPrefixRef::Full { nick, user, host } => IRCPrefix::Full {
+                nick: nick.to_owned(),
+                user: user.map(str::to_owned),
+                host: host.map(str::to_owned),
+            },
+        }
+    }
+}
+
+impl AsRawIRC for IRCPrefixRef<'_> {
+    fn format_as_raw_irc(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::HostOnly { host } => f.write_str(host)?,
+            Self::Full { nick, user, host } => {
+                f.write_str(nick)?;
+                if let Some(host) = host {
+                    if let Some(user) = user {
+                        write!(f, "!{user}")?;
+                    }
+                    write!(f, "@{host}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::hashmap;
+
+    const SOURCES: &[&str] = &[
+        "@badge-info=subscriber/16;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=6e2ccb1f-01ed-44d0-85b6-edf762524475;mod=1;room-id=11148817;subscriber=1;tmi-sent-ts=1577040814959;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Pajapains",
+        "@msg-id=sub;system-msg=randers\\ssubscribed\\sat\\sTier\\s1.;key=a;key=b :tmi.twitch.tv USERNOTICE #pajlada",
+        ":coolguy foo bar baz :  asdf quux ",
+        "foo bar baz ::asdf",
+        ":coolguy foo bar baz :",
+        ":nick@host PRIVMSG #channel :x",
+        "@k;rt=ql7 ping",
+        "500 :Internal Server Error",
+    ];
+
+    #[test]
+    fn test_same_result_as_owned_parsing() {
+        for source in SOURCES {
+            let message = IRCMessageRef::parse(source).unwrap();
+            assert_eq!(message.into_owned(), IRCMessage::parse(source).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_same_errors_as_owned_parsing() {
+        for source in [
+            "@ :test TEST",
+            "@key=value",
+            ": TEST",
+            ":test",
+            " PING",
+            "",
+            "PING:",
+            "PING  asd",
+            "PING asd ",
+            "abc\ndef",
+        ] {
+            assert_eq!(
+                IRCMessageRef::parse(source).unwrap_err(),
+                IRCMessage::parse(source).unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_borrows_from_source() {
+        let source = "@system-msg=hi\\sthere;id=123 :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :xD";
+        let message = IRCMessageRef::parse(source).unwrap();
+
+        assert!(matches!(message.command, Cow::Borrowed("PRIVMSG")));
+        assert!(matches!(message.tags.get("id"), Some(Cow::Borrowed("123"))));
+        assert!(matches!(
+            message.tags.get("system-msg"),
+            Some(Cow::Owned(ref value)) if value == "hi there"
+        ));
+        assert_eq!(
+            message.prefix,
+            Some(IRCPrefixRef::Full {
+                nick: "randers",
+                user: Some("randers"),
+                host: Some("randers.tmi.twitch.tv"),
+            })
+        );
+        assert_eq!(message.params, vec!["#pajlada", "xD"]);
+
+        let message = IRCMessageRef::parse("ping").unwrap();
+        assert!(matches!(message.command, Cow::Owned(ref command) if command == "PING"));
+    }
+
+    #[test]
+    fn test_duplicate_tags() {
+        let tags = IRCTagsRef::parse("key=value;asd;key=;key=other");
+        assert_eq!(tags.get("key").unwrap(), "other");
+        assert_eq!(tags.get("asd").unwrap(), "");
+        assert_eq!(tags.len(), 4);
+        assert_eq!(
+            IRCTags::from(&tags),
+            hashmap! {
+                "key".to_owned() => "other".to_owned(),
+                "asd".to_owned() => String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_as_raw_irc_is_identical_to_source() {
+        for source in [
+            "@badges=moderator/1;display-name=randers;system-msg=a\\sb;flags= :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Pajapains xD",
+            ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags",
+            ":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!",
+        ] {
+            assert_eq!(IRCMessageRef::parse(source).unwrap().as_raw_irc(), source);
+        }
+    }
+}
@@ -490,6 +490,9 @@ pub struct HiddenIRCMessage(pub(self) IRCMessage);
 /// added parsing for the command you were trying to catch by matching against the `Unparsed`
 /// variant, your code would be broken without any compiler error.
 ///
+/// Clients configured with [`ClientConfig::parsed_commands`](crate::ClientConfig::parsed_commands)
+/// also emit the messages with commands that are not in that set without parsing them.
+///
 /// # Examples
 ///
 /// ```
@@ -622,6 +625,14 @@ impl ServerMessage {
         }
     }
 
+    /// The login name of the channel this message was sent to, if it was sent to a channel.
+    pub(crate) fn channel_login(&self) -> Option<&str> {
+        self.source()
+            .params
+            .first()
+            .and_then(|param| param.strip_prefix('#'))
+    }
+
     pub(crate) fn new_generic(message: IRCMessage) -> ServerMessage {
         ServerMessage::Generic(HiddenIRCMessage(message))
     }
@@ -19,11 +19,200 @@ pub struct NoticeMessage {
     /// If present, a computer-readable string identifying the class/type of notice.
     /// For example `msg_banned`. These message IDs are [documented by Twitch here](https://dev.twitch.tv/docs/irc/msg-id).
     pub message_id: Option<String>,
+    /// The type of notice, parsed from `message_id`. The notices Twitch sends in response to a
+    /// failed login do not carry a `msg-id`, these are recognized by their `message_text` instead.
+    ///
+    /// This is `None` if the notice has neither a `msg-id` nor is one of those login notices.
+    pub notice_id: Option<NoticeId>,
 
     /// The message that this `NoticeMessage` was parsed from.
     pub source: IRCMessage,
 }
 
+/// Identifies the type of a `NOTICE`, based on its `msg-id` tag. See the
+/// [Twitch documentation](https://dev.twitch.tv/docs/irc/msg-id) for a full list of message IDs.
+///
+/// Message IDs not covered by this enum are parsed as [`NoticeId::Unknown`]. This enum is also
+/// marked as `#[non_exhaustive]` so that more message IDs may be added to it in the future,
+/// without the need for a breaking release.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
+pub enum NoticeId {
+    /// `NOTICE * :Login authentication failed`: The login name or token is invalid.
+    /// (Sent without a `msg-id`.)
+    LoginAuthenticationFailed,
+    /// `NOTICE * :Improperly formatted auth`: The token is malformed, e.g. it is missing the
+    /// `oauth:` prefix. (Sent without a `msg-id`.)
+    ImproperlyFormattedAuth,
+    /// `msg_banned`: You are permanently banned from talking in the channel.
+    Banned,
+    /// `msg_timedout`: You are timed out in the channel.
+    TimedOut,
+    /// `msg_bad_characters`: The message contained characters Twitch does not accept.
+    BadCharacters,
+    /// `msg_channel_blocked`: Your account is not allowed to talk in the channel.
+    ChannelBlocked,
+    /// `msg_channel_suspended`: The channel is suspended.
+    ChannelSuspended,
+    /// `msg_suspended`: Your account is suspended.
+    Suspended,
+    /// `msg_duplicate`: The message is identical to the previous message you sent to this
+    /// channel less than 30 seconds ago.
+    Duplicate,
+    /// `msg_ratelimit`: You are sending messages too quickly.
+    RateLimited,
+    /// `msg_slowmode`: Slow mode is enabled and you sent a message too recently.
+    SlowMode,
+    /// `msg_emoteonly`: Emote-only mode is enabled and the message contained non-emote text.
+    EmoteOnly,
+    /// `msg_followersonly`: Followers-only mode is enabled and you do not follow the channel.
+    FollowersOnly,
+    /// `msg_followersonly_followed`: Followers-only mode is enabled and you have not followed
+    /// the channel for long enough.
+    FollowersOnlyFollowed,
+    /// `msg_followersonly_zero`: Followers-only mode is enabled and you do not follow the
+    /// channel.
+    FollowersOnlyZero,
+    /// `msg_subsonly`: Subscribers-only mode is enabled and you are not subscribed.
+    SubscribersOnly,
+    /// `msg_r9k`: Unique-chat mode is enabled and the message is not unique.
+    UniqueChat,
+    /// `msg_rejected`: The message was held back for review by AutoMod.
+    Rejected,
+    /// `msg_rejected_mandatory`: The message was rejected by the moderation settings of
+    /// the channel.
+    RejectedMandatory,
+    /// `msg_verified_email`: The channel requires a verified email address to chat.
+    VerifiedEmailRequired,
+    /// `msg_requires_verified_phone_number`: The channel requires a verified phone number
+    /// to chat.
+    VerifiedPhoneNumberRequired,
+    /// `unrecognized_cmd`: The chat command is not recognized.
+    UnrecognizedCommand,
+    /// `no_permission`: You do not have permission to perform the chat command.
+    NoPermission,
+    /// `slow_on`: Slow mode was enabled.
+    SlowModeOn,
+    /// `slow_off`: Slow mode was disabled.
+    SlowModeOff,
+    /// `emote_only_on`: Emote-only mode was enabled.
+    EmoteOnlyOn,
+    /// `emote_only_off`: Emote-only mode was disabled.
+    EmoteOnlyOff,
+    /// `followers_on` or `followers_on_zero`: Followers-only mode was enabled.
+    FollowersOnlyOn,
+    /// `followers_off`: Followers-only mode was disabled.
+    FollowersOnlyOff,
+    /// `subs_on`: Subscribers-only mode was enabled.
+    SubscribersOnlyOn,
+    /// `subs_off`: Subscribers-only mode was disabled.
+    SubscribersOnlyOff,
+    /// `r9k_on`: Unique-chat mode was enabled.
+    UniqueChatOn,
+    /// `r9k_off`: Unique-chat mode was disabled.
+    UniqueChatOff,
+    /// Any other `msg-id`, which is contained in this variant.
+    Unknown(String),
+}
+
+impl NoticeId {
+    /// Parses the `msg-id` of a `NOTICE`.
+    #[must_use]
+    pub fn from_message_id(message_id: &str) -> NoticeId {
+        match message_id {
+            "msg_banned" => NoticeId::Banned,
+            "msg_timedout" => NoticeId::TimedOut,
+            "msg_bad_characters" => NoticeId::BadCharacters,
+            "msg_channel_blocked" => NoticeId::ChannelBlocked,
+            "msg_channel_suspended" => NoticeId::ChannelSuspended,
+            "msg_suspended" => NoticeId::Suspended,
+            "msg_duplicate" => NoticeId::Duplicate,
+            "msg_ratelimit" => NoticeId::RateLimited,
+            "msg_slowmode" => NoticeId::SlowMode,
+            "msg_emoteonly" => NoticeId::EmoteOnly,
+            "msg_followersonly" => NoticeId::FollowersOnly,
+            "msg_followersonly_followed" => NoticeId::FollowersOnlyFollowed,
+            "msg_followersonly_zero" => NoticeId::FollowersOnlyZero,
+            "msg_subsonly" => NoticeId::SubscribersOnly,
+            "msg_r9k" => NoticeId::UniqueChat,
+            "msg_rejected" => NoticeId::Rejected,
+            "msg_rejected_mandatory" => NoticeId::RejectedMandatory,
+            "msg_verified_email" => NoticeId::VerifiedEmailRequired,
+            "msg_requires_verified_phone_number" => NoticeId::VerifiedPhoneNumberRequired,
+            "unrecognized_cmd" => NoticeId::UnrecognizedCommand,
+            "no_permission" => NoticeId::NoPermission,
+            "slow_on" => NoticeId::SlowModeOn,
+            "slow_off" => NoticeId::SlowModeOff,
+            "emote_only_on" => NoticeId::EmoteOnlyOn,
+            "emote_only_off" => NoticeId::EmoteOnlyOff,
+            "followers_on" | "followers_on_zero" => NoticeId::FollowersOnlyOn,
+            "followers_off" => NoticeId::FollowersOnlyOff,
+            "subs_on" => NoticeId::SubscribersOnlyOn,
+            "subs_off" => NoticeId::SubscribersOnlyOff,
+            "r9k_on" => NoticeId::UniqueChatOn,
+            "r9k_off" => NoticeId::UniqueChatOff,
+            other => NoticeId::Unknown(other.to_owned()),
+        }
+    }
+
+    /// Returns `true` if this notice reports that something failed, e.g. the login was
+    /// unsuccessful, a chat message was not accepted, or a chat command could not be executed.
+    ///
+    /// For [`NoticeId::Unknown`], this is based on the Twitch convention of prefixing the IDs of
+    /// notices about refused chat messages with `msg_`.
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        match self {
+            NoticeId::SlowModeOn
+            | NoticeId::SlowModeOff
+            | NoticeId::EmoteOnlyOn
+            | NoticeId::EmoteOnlyOff
+            | NoticeId::FollowersOnlyOn
+            | NoticeId::FollowersOnlyOff
+            | NoticeId::SubscribersOnlyOn
+            | NoticeId::SubscribersOnlyOff
+            | NoticeId::UniqueChatOn
+            | NoticeId::UniqueChatOff => false,
+            NoticeId::Unknown(message_id) => message_id.starts_with("msg_"),
+            _ => true,
+        }
+    }
+
+    /// Returns `true` if this notice is the answer to a chat message that Twitch refused to
+    /// send, e.g. because the user is timed out or slow mode is enabled.
+    pub(crate) fn is_message_rejection(&self) -> bool {
+        matches!(
+            self,
+            NoticeId::Banned
+                | NoticeId::TimedOut
+                | NoticeId::BadCharacters
+                | NoticeId::ChannelBlocked
+                | NoticeId::Suspended
+                | NoticeId::Duplicate
+                | NoticeId::RateLimited
+                | NoticeId::SlowMode
+                | NoticeId::EmoteOnly
+                | NoticeId::FollowersOnly
+                | NoticeId::FollowersOnlyFollowed
+                | NoticeId::FollowersOnlyZero
+                | NoticeId::SubscribersOnly
+                | NoticeId::UniqueChat
+                | NoticeId::Rejected
+                | NoticeId::RejectedMandatory
+                | NoticeId::VerifiedEmailRequired
+                | NoticeId::VerifiedPhoneNumberRequired
+        )
+    }
+
+    /// Returns `true` if this notice is purely informational, e.g. it announces that a chat
+    /// setting was changed. This is the opposite of [`is_error()`](NoticeId::is_error).
+    #[must_use]
+    pub fn is_informational(&self) -> bool {
+        !self.is_error()
+    }
+}
+
 impl TryFrom<IRCMessage> for NoticeMessage {
     type Error = ServerMessageParseError;
 
@@ -32,14 +221,22 @@ impl TryFrom<IRCMessage> for NoticeMessage {
             return Err(ServerMessageParseError::MismatchedCommand(Box::new(source)));
         }
 
+        let message_text = source.try_get_param(1)?;
+        let message_id = source.try_get_optional_nonempty_tag_value("msg-id")?;
+        let notice_id = match (message_id, message_text) {
+            (Some(message_id), _) => Some(NoticeId::from_message_id(message_id)),
+            (None, "Login authentication failed") => Some(NoticeId::LoginAuthenticationFailed),
+            (None, "Improperly formatted auth") => Some(NoticeId::ImproperlyFormattedAuth),
+            (None, _) => None,
+        };
+
         Ok(NoticeMessage {
             channel_login: source
                 .try_get_optional_channel_login()?
                 .map(|s| s.to_owned()),
-            message_text: source.try_get_param(1)?.to_owned(),
-            message_id: source
-                .try_get_optional_nonempty_tag_value("msg-id")?
-                .map(|s| s.to_owned()),
+            message_text: message_text.to_owned(),
+            message_id: message_id.map(|s| s.to_owned()),
+            notice_id,
             source,
         })
     }
@@ -53,7 +250,7 @@ impl From<NoticeMessage> for IRCMessage {
 
 #[cfg(test)]
 mod tests {
-    use crate::message::{IRCMessage, NoticeMessage};
+    use crate::message::{IRCMessage, NoticeId, NoticeMessage};
     use std::convert::TryFrom;
 
     #[test]
@@ -68,6 +265,7 @@ mod tests {
                 channel_login: Some("forsen".to_owned()),
                 message_text: "You are permanently banned from talking in forsen.".to_owned(),
                 message_id: Some("msg_banned".to_owned()),
+                notice_id: Some(NoticeId::Banned),
                 source: irc_message
             }
         );
@@ -86,8 +284,40 @@ mod tests {
                 channel_login: None,
                 message_text: "Improperly formatted auth".to_owned(),
                 message_id: None,
+                notice_id: Some(NoticeId::ImproperlyFormattedAuth),
                 source: irc_message
             }
         );
     }
+
+    #[test]
+    pub fn test_notice_ids() {
+        let parse = |src: &str| NoticeMessage::try_from(IRCMessage::parse(src).unwrap()).unwrap();
+
+        let msg = parse(
+            "@msg-id=slow_on :tmi.twitch.tv NOTICE #pajlada :This room is now in slow mode. You may send messages every 10 seconds.",
+        );
+        assert_eq!(msg.notice_id, Some(NoticeId::SlowModeOn));
+        assert!(msg.notice_id.unwrap().is_informational());
+
+        let msg = parse(
+            "@msg-id=msg_duplicate :tmi.twitch.tv NOTICE #pajlada :Your message was not sent because it is identical to the previous one you sent, less than 30 seconds ago.",
+        );
+        assert_eq!(msg.notice_id, Some(NoticeId::Duplicate));
+        assert!(msg.notice_id.unwrap().is_error());
+
+        let msg = parse(":tmi.twitch.tv NOTICE * :Login authentication failed");
+        assert_eq!(msg.notice_id, Some(NoticeId::LoginAuthenticationFailed));
+        assert!(msg.notice_id.unwrap().is_error());
+
+        let msg = parse(":tmi.twitch.tv NOTICE * :Something else");
+        assert_eq!(msg.notice_id, None);
+
+        assert_eq!(
+            NoticeId::from_message_id("msg_some_new_id"),
+            NoticeId::Unknown("msg_some_new_id".to_owned())
+        );
+        assert!(NoticeId::from_message_id("msg_some_new_id").is_error());
+        assert!(NoticeId::from_message_id("some_new_id").is_informational());
+    }
 }
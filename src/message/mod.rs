@@ -1,15 +1,17 @@
 //! Generic and Twitch-specific IRC messages.
 
+pub(crate) mod borrowed;
 pub(crate) mod commands;
 pub(crate) mod prefix;
 pub(crate) mod tags;
 pub(crate) mod twitch;
 
+pub use borrowed::{IRCMessageRef, IRCPrefixRef, IRCTagsRef};
 pub use commands::clearchat::{ClearChatAction, ClearChatMessage};
 pub use commands::clearmsg::ClearMsgMessage;
 pub use commands::globaluserstate::GlobalUserStateMessage;
 pub use commands::join::JoinMessage;
-pub use commands::notice::NoticeMessage;
+pub use commands::notice::{NoticeId, NoticeMessage};
 pub use commands::part::PartMessage;
 pub use commands::ping::PingMessage;
 pub use commands::pong::PongMessage;
@@ -21,7 +23,7 @@ pub use commands::userstate::UserStateMessage;
 pub use commands::whisper::WhisperMessage;
 pub use commands::{ServerMessage, ServerMessageParseError};
 pub use prefix::IRCPrefix;
-pub use tags::IRCTags;
+pub use tags::{IRCTagMap, IRCTags};
 pub use twitch::*;
 
 use std::fmt;
@@ -75,8 +77,8 @@ pub trait AsRawIRC {
     /// but due to protocol ambiguity it is not guaranteed to be identical to the input
     /// the value was parsed from (if it was parsed at all).
     ///
-    /// For example, the order of tags might differ, or the use of trailing parameters
-    /// might be different.
+    /// For example, the use of trailing parameters might be different, or tags without a value
+    /// might be written as `key=` instead of `key`. The order of tags is kept.
     fn as_raw_irc(&self) -> String
     where
         Self: Sized,
@@ -179,85 +181,11 @@ impl IRCMessage {
 
     /// Parse a raw IRC wire-format message into an `IRCMessage`. `source` should be specified
     /// without trailing newline character(s).
-    pub fn parse(mut source: &str) -> Result<IRCMessage, IRCParseError> {
-        if source.chars().any(|c| c == '\r' || c == '\n') {
-            return Err(IRCParseError::NewlinesInMessage);
-        }
-
-        let tags = if source.starts_with('@') {
-            // str[1..] removes the leading @ sign
-            let (tags_part, remainder) = source[1..]
-                .split_once(' ')
-                .ok_or(IRCParseError::NoSpaceAfterTags)?;
-            source = remainder;
-
-            if tags_part.is_empty() {
-                return Err(IRCParseError::EmptyTagsDeclaration);
-            }
-
-            IRCTags::parse(tags_part)
-        } else {
-            IRCTags::new()
-        };
-
-        let prefix = if source.starts_with(':') {
-            // str[1..] removes the leading : sign
-            let (prefix_part, remainder) = source[1..]
-                .split_once(' ')
-                .ok_or(IRCParseError::NoSpaceAfterPrefix)?;
-            source = remainder;
-
-            if prefix_part.is_empty() {
-                return Err(IRCParseError::EmptyPrefixDeclaration);
-            }
-
-            Some(IRCPrefix::parse(prefix_part))
-        } else {
-            None
-        };
-
-        let mut command_split = source.splitn(2, ' ');
-        let mut command = command_split.next().unwrap().to_owned();
-        command.make_ascii_uppercase();
-
-        if command.is_empty()
-            || !command.chars().all(|c| c.is_ascii_alphabetic())
-                && !command.chars().all(|c| c.is_ascii() && c.is_numeric())
-        {
-            return Err(IRCParseError::MalformedCommand);
-        }
-
-        let mut params;
-        if let Some(params_part) = command_split.next() {
-            params = vec![];
-
-            let mut rest = Some(params_part);
-            while let Some(rest_str) = rest {
-                if let Some(sub_str) = rest_str.strip_prefix(':') {
-                    // trailing param, remove : and consume the rest of the input
-                    params.push(sub_str.to_owned());
-                    rest = None;
-                } else {
-                    let mut split = rest_str.splitn(2, ' ');
-                    let param = split.next().unwrap();
-                    rest = split.next();
-
-                    if param.is_empty() {
-                        return Err(IRCParseError::TooManySpacesInMiddleParams);
-                    }
-                    params.push(param.to_owned());
-                }
-            }
-        } else {
-            params = vec![];
-        }
-
-        Ok(IRCMessage {
-            tags,
-            prefix,
-            command,
-            params,
-        })
+    ///
+    /// If the message does not need to outlive `source`, [`IRCMessageRef::parse`] avoids
+    /// copying the parts of the message.
+    pub fn parse(source: &str) -> Result<IRCMessage, IRCParseError> {
+        IRCMessageRef::parse(source).map(IRCMessageRef::into_owned)
     }
 }
 
@@ -276,21 +204,28 @@ impl AsRawIRC for IRCMessage {
         }
 
         f.write_str(&self.command)?;
+        format_params_as_raw_irc(&self.params, f)
+    }
+}
 
-        for param in &self.params {
-            if !param.contains(' ') && !param.is_empty() && !param.starts_with(':') {
-                // middle parameter
-                write!(f, " {param}")?;
-            } else {
-                // trailing parameter
-                write!(f, " :{param}")?;
-                // TODO should there be a panic if this is not the last parameter?
-                break;
-            }
+fn format_params_as_raw_irc<S: AsRef<str>>(
+    params: &[S],
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    for param in params {
+        let param = param.as_ref();
+        if !param.contains(' ') && !param.is_empty() && !param.starts_with(':') {
+            // middle parameter
+            write!(f, " {param}")?;
+        } else {
+            // trailing parameter
+            write!(f, " :{param}")?;
+            // TODO should there be a panic if this is not the last parameter?
+            break;
         }
-
-        Ok(())
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -834,6 +769,12 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_round_trip_is_identical() {
+        let source = "@badge-info=subscriber/16;badges=moderator/1,subscriber/12;color=#19E6E6;display-name=randers;emotes=;flags=;id=6e2ccb1f-01ed-44d0-85b6-edf762524475;mod=1;room-id=11148817;subscriber=1;system-msg=hello\\sthere;tmi-sent-ts=1577040814959;turbo=0;user-id=40286300;user-type=mod :randers!randers@randers.tmi.twitch.tv PRIVMSG #pajlada :Pajapains xD";
+        assert_eq!(IRCMessage::parse(source).unwrap().as_raw_irc(), source);
+    }
+
     #[test]
     fn test_lowercase_command() {
         assert_eq!(IRCMessage::parse("ping").unwrap().command, "PING");
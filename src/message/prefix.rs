@@ -1,4 +1,4 @@
-use super::AsRawIRC;
+use super::{AsRawIRC, IRCPrefixRef};
 use std::fmt;
 
 #[cfg(feature = "with-serde")]
@@ -108,34 +108,7 @@ impl IRCPrefix {
     /// ```
     #[must_use]
     pub fn parse(source: &str) -> IRCPrefix {
-        if !source.contains('@') {
-            // just a hostname
-            IRCPrefix::HostOnly {
-                host: source.to_owned(),
-            }
-        } else {
-            // full prefix (nick[[!user]@host])
-            // valid forms:
-            // nick
-            // nick@host
-            // nick!user@host
-
-            // split on @ first, then on !
-            let mut at_split = source.splitn(2, '@');
-            let nick_and_user = at_split.next().unwrap();
-            let host = at_split.next();
-
-            // now nick_and_user is either "nick" or "nick!user"
-            let mut exc_split = nick_and_user.splitn(2, '!');
-            let nick = exc_split.next();
-            let user = exc_split.next();
-
-            IRCPrefix::Full {
-                nick: nick.unwrap().to_owned(),
-                user: user.map(|s| s.to_owned()),
-                host: host.map(|s| s.to_owned()),
-            }
-        }
+        IRCPrefix::from(IRCPrefixRef::parse(source))
     }
 }
 
@@ -7,7 +7,7 @@ use std::fmt::Write;
 #[cfg(feature = "with-serde")]
 use {serde::Deserialize, serde::Serialize};
 
-fn decode_tag_value(raw: &str) -> String {
+pub(super) fn decode_tag_value(raw: &str) -> String {
     let mut output = String::with_capacity(raw.len());
 
     let mut iter = raw.chars();
@@ -48,8 +48,204 @@ fn encode_tag_value(raw: &str) -> String {
     output
 }
 
+/// An order-preserving map of tag keys to (unescaped) tag values.
+///
+/// The tags are stored in a vector in the order they were inserted in, which for parsed
+/// messages is the order they were received in. Looking up a tag compares the keys one by one,
+/// which for the small number of tags on IRC messages is faster than hashing them. The tags
+/// are kept on the heap rather than inline, since messages with around 20 tags inline would be
+/// several times slower to move around.
+///
+/// The API mirrors the parts of [`HashMap`] that are useful for tags. Inserting a key that is
+/// already present replaces the value but keeps the position of the key. Two maps are equal
+/// if they contain the same tags, regardless of their order.
+#[derive(Clone, Default)]
+pub struct IRCTagMap(Vec<(String, String)>);
+
+type IterItem<'a> = (&'a String, &'a String);
+
+impl IRCTagMap {
+    /// Creates a new empty map of tags.
+    #[must_use]
+    pub fn new() -> IRCTagMap {
+        IRCTagMap(Vec::new())
+    }
+
+    /// Creates a new empty map of tags with room for at least `capacity` tags.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> IRCTagMap {
+        IRCTagMap(Vec::with_capacity(capacity))
+    }
+
+    /// Returns the value of the tag with the given key.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.0
+            .iter()
+            .find(|(tag_key, _)| tag_key == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns a mutable reference to the value of the tag with the given key.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut String> {
+        self.0
+            .iter_mut()
+            .find(|(tag_key, _)| tag_key == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Whether a tag with the given key is present.
+    #[must_use]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of the tag with the given key, returning the previous value if the key
+    /// was already present. New keys are added at the end.
+    pub fn insert(&mut self, key: String, value: String) -> Option<String> {
+        if let Some(existing_value) = self.get_mut(&key) {
+            Some(std::mem::replace(existing_value, value))
+        } else {
+            self.0.push((key, value));
+            None
+        }
+    }
+
+    /// Removes the tag with the given key, returning its value if it was present. The order
+    /// of the remaining tags is kept.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let index = self.0.iter().position(|(tag_key, _)| tag_key == key)?;
+        Some(self.0.remove(index).1)
+    }
+
+    /// The number of tags.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no tags.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the keys and values of the tags, in order.
+    pub fn iter(&self) -> impl Iterator<Item = IterItem<'_>> {
+        self.into_iter()
+    }
+
+    /// Iterates over the keys of the tags, in order.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(key, _)| key)
+    }
+
+    /// Iterates over the values of the tags, in order.
+    pub fn values(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(_, value)| value)
+    }
+}
+
+impl<'a> IntoIterator for &'a IRCTagMap {
+    type Item = IterItem<'a>;
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, String)>,
+        fn(&(String, String)) -> IterItem<'_>,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl Extend<(String, String)> for IRCTagMap {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl FromIterator<(String, String)> for IRCTagMap {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let mut map = IRCTagMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl From<HashMap<String, String>> for IRCTagMap {
+    fn from(map: HashMap<String, String>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl fmt::Debug for IRCTagMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for IRCTagMap {
+    fn eq(&self, other: &IRCTagMap) -> bool {
+        // keys are unique, so same length and all of our tags in the other map means equality
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl Eq for IRCTagMap {}
+
+impl PartialEq<HashMap<String, String>> for IRCTagMap {
+    fn eq(&self, other: &HashMap<String, String>) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+#[cfg(feature = "with-serde")]
+impl Serialize for IRCTagMap {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "with-serde")]
+impl<'de> Deserialize<'de> for IRCTagMap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TagMapVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for TagMapVisitor {
+            type Value = IRCTagMap;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map of tag keys to tag values")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut access: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut map = IRCTagMap::new();
+                while let Some((key, value)) = access.next_entry()? {
+                    map.insert(key, value);
+                }
+                Ok(map)
+            }
+        }
+
+        deserializer.deserialize_map(TagMapVisitor)
+    }
+}
+
 /// A map of key-value [IRCv3 tags](https://ircv3.net/specs/extensions/message-tags.html).
 ///
+/// The tags keep the order they were parsed or inserted in, see [`IRCTagMap`].
+///
 /// # Examples
 ///
 /// ```
@@ -62,17 +258,18 @@ fn encode_tag_value(raw: &str) -> String {
 ///     "key".to_owned() => "value".to_owned(),
 ///     "key2".to_owned() => "value2".to_owned(),
 ///     "key3".to_owned() => "".to_owned(),
-/// })
+/// });
+/// assert_eq!(tags.as_raw_irc(), "key=value;key2=value2;key3=");
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 #[cfg_attr(feature = "with-serde", derive(Serialize, Deserialize))]
-pub struct IRCTags(pub HashMap<String, String>);
+pub struct IRCTags(pub IRCTagMap);
 
 impl IRCTags {
     /// Creates a new empty map of tags.
     #[must_use]
     pub fn new() -> IRCTags {
-        IRCTags(HashMap::new())
+        IRCTags(IRCTagMap::new())
     }
 
     /// Parses a new set of tags from their wire-format representation.
@@ -83,7 +280,8 @@ impl IRCTags {
     pub fn parse(source: &str) -> IRCTags {
         assert!(!source.is_empty(), "invalid input");
 
-        let mut tags = IRCTags::new();
+        let tag_count = source.bytes().filter(|b| *b == b';').count() + 1;
+        let mut tags = IRCTags(IRCTagMap::with_capacity(tag_count));
 
         for raw_tag in source.split(';') {
             let mut tag_split = raw_tag.splitn(2, '=');
@@ -102,6 +300,12 @@ impl IRCTags {
 
 impl From<HashMap<String, String>> for IRCTags {
     fn from(map: HashMap<String, String, RandomState>) -> Self {
+        IRCTags(IRCTagMap::from(map))
+    }
+}
+
+impl From<IRCTagMap> for IRCTags {
+    fn from(map: IRCTagMap) -> Self {
         IRCTags(map)
     }
 }
@@ -115,11 +319,11 @@ impl AsRawIRC for IRCTags {
             } else {
                 add_separator = true;
             }
+            // tags without value are written as `key=` like Twitch does, which the spec
+            // treats the same as just `key`
             f.write_str(key)?;
-            if !value.is_empty() {
-                f.write_char('=')?;
-                f.write_str(&encode_tag_value(value))?;
-            }
+            f.write_char('=')?;
+            f.write_str(&encode_tag_value(value))?;
         }
 
         Ok(())
@@ -134,7 +338,7 @@ impl PartialEq<HashMap<String, String>> for IRCTags {
 
 impl PartialEq<IRCTags> for HashMap<String, String> {
     fn eq(&self, other: &IRCTags) -> bool {
-        self == &other.0
+        &other.0 == self
     }
 }
 
@@ -256,4 +460,48 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_keeps_order() {
+        let tags = IRCTags::parse("zeta=1;alpha=2;mid;beta=a\\sb");
+        assert_eq!(
+            tags.0.keys().collect::<Vec<_>>(),
+            vec!["zeta", "alpha", "mid", "beta"]
+        );
+        assert_eq!(tags.as_raw_irc(), "zeta=1;alpha=2;mid=;beta=a\\sb");
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut tags = IRCTagMap::new();
+        assert_eq!(tags.insert("a".to_owned(), "1".to_owned()), None);
+        assert_eq!(tags.insert("b".to_owned(), "2".to_owned()), None);
+        assert_eq!(tags.insert("c".to_owned(), "3".to_owned()), None);
+        // replacing a value keeps the position of the key
+        assert_eq!(
+            tags.insert("a".to_owned(), "4".to_owned()),
+            Some("1".to_owned())
+        );
+        assert_eq!(tags.remove("b"), Some("2".to_owned()));
+        assert_eq!(tags.remove("b"), None);
+
+        assert_eq!(
+            tags.iter().collect::<Vec<_>>(),
+            vec![
+                (&"a".to_owned(), &"4".to_owned()),
+                (&"c".to_owned(), &"3".to_owned())
+            ]
+        );
+        assert_eq!(tags.get("c"), Some(&"3".to_owned()));
+        assert!(!tags.contains_key("b"));
+        assert_eq!(tags.len(), 2);
+    }
+
+    #[test]
+    fn test_equality_ignores_order() {
+        assert_eq!(IRCTags::parse("a=1;b=2"), IRCTags::parse("b=2;a=1"));
+        assert_ne!(IRCTags::parse("a=1;b=2"), IRCTags::parse("a=1;b=3"));
+        assert_ne!(IRCTags::parse("a=1;b=2"), IRCTags::parse("a=1"));
+        assert_ne!(IRCTags::parse("a=1"), IRCTags::parse("a=1;b=2"));
+    }
 }
@@ -1,7 +1,8 @@
 use crate::MetricsConfig;
 use prometheus::{
-    Counter, CounterVec, IntGaugeVec, Opts, register_counter_vec_with_registry,
-    register_counter_with_registry, register_int_gauge_vec_with_registry,
+    Counter, CounterVec, Gauge, IntGauge, IntGaugeVec, Opts, register_counter_vec_with_registry,
+    register_counter_with_registry, register_gauge_with_registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry,
 };
 
 #[derive(Clone)]
@@ -12,6 +13,11 @@ pub struct MetricsBundle {
     pub connections: IntGaugeVec,
     pub connections_failed: Counter,
     pub connections_created: Counter,
+    pub privmsgs_queued: IntGauge,
+    pub privmsgs_delayed: Counter,
+    pub joins_queued: IntGauge,
+    pub reconnect_backoff: Gauge,
+    pub incoming_messages_dropped: Counter,
 }
 
 impl MetricsBundle {
@@ -92,6 +98,56 @@ impl MetricsBundle {
         )
         .unwrap();
 
+        let privmsgs_queued = register_int_gauge_with_registry!(
+            Opts::new(
+                "twitchirc_privmsgs_queued",
+                "Number of PRIVMSGs currently held back by the rate limiter"
+            )
+            .const_labels(const_labels.clone()),
+            metrics_registry
+        )
+        .unwrap();
+
+        let privmsgs_delayed = register_counter_with_registry!(
+            Opts::new(
+                "twitchirc_privmsgs_delayed",
+                "Number of PRIVMSGs that could not be sent immediately because of the rate limiter (since the start of this client)"
+            )
+            .const_labels(const_labels.clone()),
+            metrics_registry
+        )
+        .unwrap();
+
+        let joins_queued = register_int_gauge_with_registry!(
+            Opts::new(
+                "twitchirc_joins_queued",
+                "Number of JOINs currently held back by the join rate limiter"
+            )
+            .const_labels(const_labels.clone()),
+            metrics_registry
+        )
+        .unwrap();
+
+        let reconnect_backoff = register_gauge_with_registry!(
+            Opts::new(
+                "twitchirc_reconnect_backoff_seconds",
+                "Delay new connections currently wait for because previous connections failed (before jitter)"
+            )
+            .const_labels(const_labels.clone()),
+            metrics_registry
+        )
+        .unwrap();
+
+        let incoming_messages_dropped = register_counter_with_registry!(
+            Opts::new(
+                "twitchirc_incoming_messages_dropped",
+                "Number of incoming messages dropped because the bounded receiver of the client was full (since the start of this client)"
+            )
+            .const_labels(const_labels.clone()),
+            metrics_registry
+        )
+        .unwrap();
+
         Some(MetricsBundle {
             messages_received,
             messages_sent,
@@ -99,6 +155,11 @@ impl MetricsBundle {
             connections,
             connections_failed,
             connections_created,
+            privmsgs_queued,
+            privmsgs_delayed,
+            joins_queued,
+            reconnect_backoff,
+            incoming_messages_dropped,
         })
     }
 }
@@ -0,0 +1,896 @@
+//! Implements an in-memory transport together with a scriptable fake Twitch IRC server,
+//! to test code using the `TwitchIRCClient` without opening any real network connections.
+//!
+//! A [`FakeTwitchServer`] registers itself under a unique name when it is created. To make a
+//! client connect to it, put the server's [`endpoint()`](FakeTwitchServer::endpoint) into the
+//! [`ClientConfig::server_endpoint`](crate::ClientConfig::server_endpoint) and use
+//! [`MockTransport`] as the transport type of the client.
+//!
+//! The fake server automatically answers `CAP REQ`, `PASS`/`NICK`, `JOIN`/`PART`, `PING`,
+//! `PRIVMSG` and `QUIT` the same way the Twitch servers would (including refusing duplicate
+//! messages). Each connection that is made
+//! to the server can be accepted using [`FakeTwitchServer::accept()`], which gives you a
+//! [`FakeConnection`] handle that lets you inspect the messages the client sent, and
+//! inject messages such as `RECONNECT`, `NOTICE` or a disconnect.
+//!
+//! Since everything happens in-process, tests using this module also work as expected
+//! under [`tokio::time::pause`], making the time-based logic of the client (e.g. rate limiting
+//! the opening of new connections) deterministic.
+//!
+//! This module requires the `transport-mock` feature flag.
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(feature = "transport-mock")] {
+//! use twitch_irc::login::StaticLoginCredentials;
+//! use twitch_irc::message::ServerMessage;
+//! use twitch_irc::transport::mock::{FakeTwitchServer, MockTransport};
+//! use twitch_irc::{ClientConfig, TwitchIRCClient};
+//!
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! let mut server = FakeTwitchServer::new();
+//!
+//! let mut config = ClientConfig::default();
+//! config.server_endpoint = server.endpoint();
+//! let (mut incoming_messages, client) =
+//!     TwitchIRCClient::<MockTransport, StaticLoginCredentials>::new(config);
+//!
+//! client.join("pajlada".to_owned()).unwrap();
+//!
+//! let mut connection = server.accept().await;
+//! connection.recv_command("JOIN").await.unwrap();
+//! connection.send_notice(Some("pajlada"), Some("msg_banned"), "You are permanently banned.");
+//!
+//! while let Some(message) = incoming_messages.recv().await {
+//!     if let ServerMessage::Notice(msg) = message {
+//!         assert_eq!(msg.message_text, "You are permanently banned.");
+//!         break;
+//!     }
+//! }
+//! # }
+//! # main();
+//! # }
+//! ```
+
+use crate::irc;
+use crate::message::{IRCMessage, IRCParseError, IRCPrefix};
+use crate::transport::{ServerEndpoint, Transport};
+use async_trait::async_trait;
+use either::Either;
+use futures_util::sink::Sink;
+use futures_util::stream::{FusedStream, Stream};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Duration, Instant};
+
+type IncomingItem = Result<IRCMessage, Either<MockTransportError, IRCParseError>>;
+
+/// All fake servers currently alive, indexed by their name.
+static SERVERS: LazyLock<Mutex<HashMap<String, Arc<ServerShared>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Used to give every fake server a unique name.
+static NEXT_SERVER_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Error type used for all errors of the [`MockTransport`].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MockTransportError {
+    /// There is no [`FakeTwitchServer`] registered under the host name given in the
+    /// [`ServerEndpoint`].
+    #[error("No fake server is registered under the name `{0}`")]
+    NoSuchServer(String),
+    /// The fake server was instructed to refuse new connections.
+    #[error("Fake server refused the connection")]
+    ConnectionRefused,
+    /// The fake server closed the connection.
+    #[error("Fake server closed the connection")]
+    Disconnected,
+}
+
+/// Transport that connects to a [`FakeTwitchServer`] living in the same process.
+///
+/// The server is selected by the [`host`](ServerEndpoint::host) of the [`ServerEndpoint`],
+/// see [`FakeTwitchServer::endpoint()`].
+pub struct MockTransport {
+    incoming_messages: <Self as Transport>::Incoming,
+    outgoing_messages: <Self as Transport>::Outgoing,
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    type ConnectError = MockTransportError;
+    type IncomingError = MockTransportError;
+    type OutgoingError = MockTransportError;
+
+    type Incoming = MockIncoming;
+    type Outgoing = MockOutgoing;
+
+    async fn new(endpoint: &ServerEndpoint) -> Result<MockTransport, MockTransportError> {
+        let name = endpoint.host.as_deref().unwrap_or_default();
+        let server = SERVERS
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MockTransportError::NoSuchServer(name.to_owned()))?;
+
+        if server.refuse_connections.load(Ordering::SeqCst) {
+            return Err(MockTransportError::ConnectionRefused);
+        }
+
+        let (to_client_tx, to_client_rx) = mpsc::unbounded_channel();
+        let (to_server_tx, to_server_rx) = mpsc::unbounded_channel();
+
+        let connection = FakeConnection::spawn(
+            server.next_connection_id.fetch_add(1, Ordering::SeqCst),
+            Arc::clone(&server),
+            to_client_tx,
+            to_server_rx,
+        );
+        let connection_state = Arc::clone(&connection.state);
+        server
+            .new_connections_tx
+            .send(connection)
+            .map_err(|_| MockTransportError::ConnectionRefused)?;
+
+        Ok(MockTransport {
+            incoming_messages: MockIncoming {
+                rx: to_client_rx,
+                terminated: false,
+            },
+            outgoing_messages: MockOutgoing {
+                tx: Some(to_server_tx),
+                connection_state,
+            },
+        })
+    }
+
+    fn split(self) -> (Self::Incoming, Self::Outgoing) {
+        (self.incoming_messages, self.outgoing_messages)
+    }
+}
+
+impl std::fmt::Debug for MockTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockTransport").finish()
+    }
+}
+
+/// Incoming half of a [`MockTransport`].
+#[derive(Debug)]
+pub struct MockIncoming {
+    rx: mpsc::UnboundedReceiver<IncomingItem>,
+    terminated: bool,
+}
+
+impl Stream for MockIncoming {
+    type Item = IncomingItem;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+        let poll = self.rx.poll_recv(cx);
+        if let Poll::Ready(None) = poll {
+            self.terminated = true;
+        }
+        poll
+    }
+}
+
+impl FusedStream for MockIncoming {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// Outgoing half of a [`MockTransport`].
+#[derive(Debug)]
+pub struct MockOutgoing {
+    // `None` after the sink has been closed
+    tx: Option<mpsc::UnboundedSender<IRCMessage>>,
+    // used to notice a server-side close before the answer task has wound down
+    connection_state: Arc<Mutex<ConnectionState>>,
+}
+
+impl Sink<IRCMessage> for MockOutgoing {
+    type Error = MockTransportError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: IRCMessage) -> Result<(), Self::Error> {
+        if self.connection_state.lock().unwrap().to_client_tx.is_none() {
+            return Err(MockTransportError::Disconnected);
+        }
+        self.tx
+            .as_mut()
+            .ok_or(MockTransportError::Disconnected)?
+            .send(item)
+            .map_err(|_| MockTransportError::Disconnected)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.tx = None;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// State shared between a `FakeTwitchServer` and the transports connecting to it.
+struct ServerShared {
+    new_connections_tx: mpsc::UnboundedSender<FakeConnection>,
+    next_connection_id: AtomicUsize,
+    next_room_id: AtomicUsize,
+    /// used for the `id` tag of the `USERSTATE` confirming a `PRIVMSG`
+    next_message_id: AtomicUsize,
+    refuse_connections: AtomicBool,
+    reject_logins: AtomicBool,
+    /// value of the `badges` tag of `USERSTATE` messages, by channel
+    badges: Mutex<HashMap<String, String>>,
+    /// text and time of the last `PRIVMSG` accepted, by user and channel
+    last_privmsgs: Mutex<HashMap<(String, String), (String, Instant)>>,
+    /// `JOIN`s to these channels are not answered
+    suspended_channels: Mutex<HashSet<String>>,
+}
+
+/// A fake Twitch IRC server, living in-process. See the [module-level documentation](self)
+/// for an overview.
+///
+/// The server is unregistered when this handle is dropped, after which new connection
+/// attempts to it fail with [`MockTransportError::NoSuchServer`]. Existing connections
+/// stay alive until their [`FakeConnection`] handle is dropped.
+pub struct FakeTwitchServer {
+    name: String,
+    shared: Arc<ServerShared>,
+    new_connections_rx: mpsc::UnboundedReceiver<FakeConnection>,
+}
+
+impl FakeTwitchServer {
+    /// Create and register a new fake server under a new unique name.
+    #[must_use]
+    pub fn new() -> FakeTwitchServer {
+        let name = format!(
+            "fake-twitch-server-{}",
+            NEXT_SERVER_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        let (new_connections_tx, new_connections_rx) = mpsc::unbounded_channel();
+        let shared = Arc::new(ServerShared {
+            new_connections_tx,
+            next_connection_id: AtomicUsize::new(0),
+            next_room_id: AtomicUsize::new(1),
+            next_message_id: AtomicUsize::new(0),
+            refuse_connections: AtomicBool::new(false),
+            reject_logins: AtomicBool::new(false),
+            badges: Mutex::new(HashMap::new()),
+            last_privmsgs: Mutex::new(HashMap::new()),
+            suspended_channels: Mutex::new(HashSet::new()),
+        });
+
+        SERVERS
+            .lock()
+            .unwrap()
+            .insert(name.clone(), Arc::clone(&shared));
+
+        FakeTwitchServer {
+            name,
+            shared,
+            new_connections_rx,
+        }
+    }
+
+    /// The unique name this server is registered under.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the endpoint to place into
+    /// [`ClientConfig::server_endpoint`](crate::ClientConfig::server_endpoint) in order to make
+    /// the client connect to this server.
+    #[must_use]
+    pub fn endpoint(&self) -> ServerEndpoint {
+        ServerEndpoint {
+            host: Some(Cow::Owned(self.name.clone())),
+            ..ServerEndpoint::default()
+        }
+    }
+
+    /// Wait for the next connection made to this server, and return a handle to it.
+    ///
+    /// Connections are queued up until they are accepted, so this can also be called after
+    /// the connection was made.
+    pub async fn accept(&mut self) -> FakeConnection {
+        // unwrap: the server holds a sender to its own queue in `shared`
+        self.new_connections_rx.recv().await.unwrap()
+    }
+
+    /// Returns the next connection made to this server, if one was already made.
+    pub fn try_accept(&mut self) -> Option<FakeConnection> {
+        self.new_connections_rx.try_recv().ok()
+    }
+
+    /// If set to `true`, new connection attempts fail with
+    /// [`MockTransportError::ConnectionRefused`].
+    pub fn refuse_connections(&self, refuse: bool) {
+        self.shared
+            .refuse_connections
+            .store(refuse, Ordering::SeqCst);
+    }
+
+    /// If set to `true`, connections logging in with a password are answered with
+    /// `NOTICE * :Login authentication failed` and then closed, like Twitch does
+    /// for invalid OAuth tokens.
+    pub fn reject_logins(&self, reject: bool) {
+        self.shared.reject_logins.store(reject, Ordering::SeqCst);
+    }
+
+    /// Marks the given channel as suspended (or not). Like Twitch does for suspended or
+    /// non-existent channels, the server does not answer `JOIN`s to suspended channels.
+    pub fn set_channel_suspended(&self, channel_login: &str, suspended: bool) {
+        let mut suspended_channels = self.shared.suspended_channels.lock().unwrap();
+        if suspended {
+            suspended_channels.insert(channel_login.to_owned());
+        } else {
+            suspended_channels.remove(channel_login);
+        }
+    }
+}
+
+impl Default for FakeTwitchServer {
+    fn default() -> Self {
+        FakeTwitchServer::new()
+    }
+}
+
+impl Drop for FakeTwitchServer {
+    fn drop(&mut self) {
+        SERVERS.lock().unwrap().remove(&self.name);
+    }
+}
+
+impl std::fmt::Debug for FakeTwitchServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeTwitchServer")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// Server-side state of a single connection, shared between the `FakeConnection` handle and
+/// the background task answering the client.
+#[derive(Debug)]
+struct ConnectionState {
+    login: Option<String>,
+    token: Option<String>,
+    joined_channels: HashSet<String>,
+    /// `None` once the connection was closed by the server
+    to_client_tx: Option<mpsc::UnboundedSender<IncomingItem>>,
+}
+
+impl ConnectionState {
+    fn send(&self, item: IncomingItem) {
+        if let Some(tx) = &self.to_client_tx {
+            tx.send(item).ok();
+        }
+    }
+}
+
+/// Server-side handle to a single connection made to a [`FakeTwitchServer`].
+///
+/// A background task answers the client's messages automatically. All messages sent by the
+/// client (including the ones that were answered automatically) can additionally be received
+/// via [`recv()`](FakeConnection::recv).
+///
+/// Dropping this handle closes the connection.
+pub struct FakeConnection {
+    id: usize,
+    server: Arc<ServerShared>,
+    state: Arc<Mutex<ConnectionState>>,
+    from_client_rx: mpsc::UnboundedReceiver<IRCMessage>,
+    kill_tx: Option<oneshot::Sender<()>>,
+}
+
+impl FakeConnection {
+    fn spawn(
+        id: usize,
+        server: Arc<ServerShared>,
+        to_client_tx: mpsc::UnboundedSender<IncomingItem>,
+        to_server_rx: mpsc::UnboundedReceiver<IRCMessage>,
+    ) -> FakeConnection {
+        let state = Arc::new(Mutex::new(ConnectionState {
+            login: None,
+            token: None,
+            joined_channels: HashSet::new(),
+            to_client_tx: Some(to_client_tx),
+        }));
+        let (from_client_tx, from_client_rx) = mpsc::unbounded_channel();
+        let (kill_tx, kill_rx) = oneshot::channel();
+
+        tokio::spawn(FakeConnection::run_answer_task(
+            Arc::clone(&server),
+            Arc::clone(&state),
+            to_server_rx,
+            from_client_tx,
+            kill_rx,
+        ));
+
+        FakeConnection {
+            id,
+            server,
+            state,
+            from_client_rx,
+            kill_tx: Some(kill_tx),
+        }
+    }
+
+    /// answers messages sent by the client and forwards them to the `FakeConnection` handle.
+    async fn run_answer_task(
+        server: Arc<ServerShared>,
+        state: Arc<Mutex<ConnectionState>>,
+        mut to_server_rx: mpsc::UnboundedReceiver<IRCMessage>,
+        from_client_tx: mpsc::UnboundedSender<IRCMessage>,
+        mut kill_rx: oneshot::Receiver<()>,
+    ) {
+        loop {
+            tokio::select! {
+                _ = &mut kill_rx => break,
+                message = to_server_rx.recv() => {
+                    let Some(message) = message else {
+                        break;
+                    };
+
+                    let mut state = state.lock().unwrap();
+                    let answer = answer(&server, &mut state, &message);
+                    from_client_tx.send(message).ok();
+
+                    for reply in answer.replies {
+                        state.send(Ok(reply));
+                    }
+                    if answer.close_connection {
+                        state.to_client_tx = None;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Number of this connection. Connections are numbered in the order they were made to the
+    /// server, starting at 0.
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Login name the client sent via `NICK`, if it has done so already.
+    #[must_use]
+    pub fn login(&self) -> Option<String> {
+        self.state.lock().unwrap().login.clone()
+    }
+
+    /// OAuth token the client sent via `PASS` (without the `oauth:` prefix), if any.
+    #[must_use]
+    pub fn token(&self) -> Option<String> {
+        self.state.lock().unwrap().token.clone()
+    }
+
+    /// Set of channels this connection is currently joined to.
+    #[must_use]
+    pub fn joined_channels(&self) -> HashSet<String> {
+        self.state.lock().unwrap().joined_channels.clone()
+    }
+
+    /// Whether this connection is still open, i.e. [`disconnect()`](FakeConnection::disconnect)
+    /// was not called and the client has not closed it either.
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .to_client_tx
+            .as_ref()
+            .is_some_and(|tx| !tx.is_closed())
+    }
+
+    /// Wait for the next message sent by the client. Returns `None` once the client has closed
+    /// the connection and all messages were received.
+    pub async fn recv(&mut self) -> Option<IRCMessage> {
+        self.from_client_rx.recv().await
+    }
+
+    /// Wait for the next message sent by the client that has the given `command`, skipping all
+    /// other messages. Returns `None` once the client has closed the connection.
+    pub async fn recv_command(&mut self, command: &str) -> Option<IRCMessage> {
+        while let Some(message) = self.recv().await {
+            if message.command == command {
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    /// Returns the next message sent by the client, if there is one already.
+    pub fn try_recv(&mut self) -> Option<IRCMessage> {
+        self.from_client_rx.try_recv().ok()
+    }
+
+    /// Send a message to the client.
+    pub fn send(&self, message: IRCMessage) {
+        self.state.lock().unwrap().send(Ok(message));
+    }
+
+    /// Send a message in raw IRC format to the client. If the message is not valid IRC,
+    /// the client will receive it as a parse error.
+    pub fn send_raw(&self, message: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .send(IRCMessage::parse(message).map_err(Either::Right));
+    }
+
+    /// Set the badges of the logged in user in a channel (in the format of the `badges` tag,
+    /// e.g. `moderator/1,subscriber/12`). These are sent in all following `USERSTATE` messages
+    /// for that channel, on all connections of the server. Like Twitch does when a user is
+    /// modded or unmodded, a `USERSTATE` with the new badges is sent to the client immediately
+    /// over this connection.
+    pub fn set_badges(&self, channel_login: &str, badges: &str) {
+        self.server
+            .badges
+            .lock()
+            .unwrap()
+            .insert(channel_login.to_owned(), badges.to_owned());
+        let state = self.state.lock().unwrap();
+        let login = state.login.clone().unwrap_or_default();
+        state.send(Ok(server_message(&userstate(
+            &self.server,
+            &login,
+            channel_login,
+            false,
+        ))));
+    }
+
+    /// Instruct the client to reconnect, by sending a `RECONNECT` message.
+    pub fn send_reconnect(&self) {
+        self.send_raw(":tmi.twitch.tv RECONNECT");
+    }
+
+    /// Send a `NOTICE` to the client. `channel_login` is `None` for notices not sent to
+    /// a specific channel (sent to `*` instead).
+    pub fn send_notice(&self, channel_login: Option<&str>, message_id: Option<&str>, text: &str) {
+        let mut message = irc![
+            "NOTICE",
+            channel_login.map_or_else(|| "*".to_owned(), |c| format!("#{c}")),
+            text
+        ];
+        message.prefix = Some(IRCPrefix::HostOnly {
+            host: "tmi.twitch.tv".to_owned(),
+        });
+        if let Some(message_id) = message_id {
+            message
+                .tags
+                .0
+                .insert("msg-id".to_owned(), message_id.to_owned());
+        }
+        self.send(message);
+    }
+
+    /// Close the connection from the server side. The client sees this as the remote end
+    /// unexpectedly closing the connection.
+    pub fn disconnect(&mut self) {
+        self.state.lock().unwrap().to_client_tx = None;
+        if let Some(kill_tx) = self.kill_tx.take() {
+            kill_tx.send(()).ok();
+        }
+    }
+}
+
+impl Drop for FakeConnection {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
+impl std::fmt::Debug for FakeConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeConnection")
+            .field("id", &self.id)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+fn server_message(raw: &str) -> IRCMessage {
+    IRCMessage::parse(raw).unwrap()
+}
+
+struct Answer {
+    replies: Vec<IRCMessage>,
+    /// Whether the server closes the connection after sending the replies.
+    close_connection: bool,
+}
+
+/// Produces the messages the server answers `message` with.
+fn answer(server: &ServerShared, state: &mut ConnectionState, message: &IRCMessage) -> Answer {
+    let login = state.login.clone().unwrap_or_default();
+    let param = |index: usize| message.params.get(index).map_or("", String::as_str);
+
+    let replies = match message.command.as_str() {
+        "CAP" if param(0) == "REQ" => {
+            vec![server_message(&format!(
+                ":tmi.twitch.tv CAP * ACK :{}",
+                param(1)
+            ))]
+        }
+        "PASS" => {
+            state.token = Some(param(0).trim_start_matches("oauth:").to_owned());
+            vec![]
+        }
+        "NICK" => {
+            let login = param(0).to_owned();
+            state.login = Some(login.clone());
+
+            if state.token.is_some() && server.reject_logins.load(Ordering::SeqCst) {
+                // Twitch sends this NOTICE and then closes the connection.
+                return Answer {
+                    replies: vec![server_message(
+                        ":tmi.twitch.tv NOTICE * :Login authentication failed",
+                    )],
+                    close_connection: true,
+                };
+            }
+
+            let mut replies = vec![
+                format!(":tmi.twitch.tv 001 {login} :Welcome, GLHF!"),
+                format!(":tmi.twitch.tv 002 {login} :Your host is tmi.twitch.tv"),
+                format!(":tmi.twitch.tv 003 {login} :This server is rather new"),
+                format!(":tmi.twitch.tv 004 {login} :-"),
+                format!(":tmi.twitch.tv 375 {login} :-"),
+                format!(
+                    ":tmi.twitch.tv 372 {login} :You are in a maze of twisty passages, all alike."
+                ),
+                format!(":tmi.twitch.tv 376 {login} :>"),
+            ];
+            if state.token.is_some() {
+                replies.push(format!(
+                    "@badge-info=;badges=;color=;display-name={login};emote-sets=0;user-id=1;user-type= :tmi.twitch.tv GLOBALUSERSTATE"
+                ));
+            }
+            replies.iter().map(|r| server_message(r)).collect()
+        }
+        "JOIN" => {
+            let mut replies = vec![];
+            for channel in param(0).split(',') {
+                let channel_login = channel.trim_start_matches('#');
+                if server
+                    .suspended_channels
+                    .lock()
+                    .unwrap()
+                    .contains(channel_login)
+                {
+                    continue;
+                }
+                state.joined_channels.insert(channel_login.to_owned());
+                let room_id = server.next_room_id.fetch_add(1, Ordering::SeqCst);
+
+                replies.push(format!(
+                    ":{login}!{login}@{login}.tmi.twitch.tv JOIN #{channel_login}"
+                ));
+                replies.push(format!(
+                    ":{login}.tmi.twitch.tv 353 {login} = #{channel_login} :{login}"
+                ));
+                replies.push(format!(
+                    ":{login}.tmi.twitch.tv 366 {login} #{channel_login} :End of /NAMES list"
+                ));
+                if state.token.is_some() {
+                    replies.push(userstate(server, &login, channel_login, false));
+                }
+                replies.push(format!(
+                    "@emote-only=0;followers-only=-1;r9k=0;room-id={room_id};slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #{channel_login}"
+                ));
+            }
+            replies.iter().map(|r| server_message(r)).collect()
+        }
+        "PART" => param(0)
+            .split(',')
+            .map(|channel| {
+                let channel_login = channel.trim_start_matches('#');
+                state.joined_channels.remove(channel_login);
+                server_message(&format!(
+                    ":{login}!{login}@{login}.tmi.twitch.tv PART #{channel_login}"
+                ))
+            })
+            .collect(),
+        "PING" => {
+            let mut pong = irc!["PONG", "tmi.twitch.tv"];
+            pong.params.extend(message.params.iter().cloned());
+            pong.prefix = Some(IRCPrefix::HostOnly {
+                host: "tmi.twitch.tv".to_owned(),
+            });
+            vec![pong]
+        }
+        "PRIVMSG" if state.token.is_some() => {
+            let channel_login = param(0).trim_start_matches('#');
+            let now = Instant::now();
+            let mut last_privmsgs = server.last_privmsgs.lock().unwrap();
+            let last_privmsg = last_privmsgs.get(&(login.clone(), channel_login.to_owned()));
+
+            if last_privmsg.is_some_and(|(text, sent_at)| {
+                text == param(1) && now < *sent_at + Duration::from_secs(30)
+            }) {
+                vec![server_message(&format!(
+                    "@msg-id=msg_duplicate :tmi.twitch.tv NOTICE #{channel_login} :Your message was not sent because it is identical to the previous one you sent, less than 30 seconds ago."
+                ))]
+            } else {
+                last_privmsgs.insert(
+                    (login.clone(), channel_login.to_owned()),
+                    (param(1).to_owned(), now),
+                );
+                // Twitch confirms messages sent by logged-in users with a USERSTATE, carrying
+                // the ID of the sent message.
+                vec![server_message(&userstate(
+                    server,
+                    &login,
+                    channel_login,
+                    true,
+                ))]
+            }
+        }
+        "QUIT" => {
+            return Answer {
+                replies: vec![],
+                close_connection: true,
+            };
+        }
+        _ => vec![],
+    };
+
+    Answer {
+        replies,
+        close_connection: false,
+    }
+}
+
+/// If `answers_privmsg` is set, the `USERSTATE` carries the `id` of the sent message.
+fn userstate(
+    server: &ServerShared,
+    login: &str,
+    channel_login: &str,
+    answers_privmsg: bool,
+) -> String {
+    let badges = server.badges.lock().unwrap();
+    let badges = badges.get(channel_login).map_or("", String::as_str);
+    let is_mod = u8::from(badges.contains("moderator/"));
+    let id = if answers_privmsg {
+        let message_id = server.next_message_id.fetch_add(1, Ordering::SeqCst);
+        format!("id=00000000-0000-4000-8000-{message_id:012x};")
+    } else {
+        String::new()
+    };
+    format!(
+        "@badge-info=;badges={badges};color=;display-name={login};emote-sets=0;{id}mod={is_mod};subscriber=0;user-type= :tmi.twitch.tv USERSTATE #{channel_login}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::irc;
+    use crate::transport::mock::{FakeTwitchServer, MockTransport, MockTransportError};
+    use crate::transport::{ServerEndpoint, Transport};
+    use futures_util::{SinkExt, StreamExt};
+
+    #[tokio::test]
+    async fn test_answers_login_and_join() {
+        let mut server = FakeTwitchServer::new();
+        let transport = MockTransport::new(&server.endpoint()).await.unwrap();
+        let (mut incoming, mut outgoing) = transport.split();
+        let mut connection = server.accept().await;
+
+        outgoing.send(irc!["PASS", "oauth:abc"]).await.unwrap();
+        outgoing.send(irc!["NICK", "randers"]).await.unwrap();
+        outgoing.send(irc!["JOIN", "#pajlada"]).await.unwrap();
+
+        let commands = incoming
+            .by_ref()
+            .take(13)
+            .map(|m| m.unwrap().command)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            commands,
+            vec![
+                "001",
+                "002",
+                "003",
+                "004",
+                "375",
+                "372",
+                "376",
+                "GLOBALUSERSTATE",
+                "JOIN",
+                "353",
+                "366",
+                "USERSTATE",
+                "ROOMSTATE"
+            ]
+        );
+
+        assert_eq!(connection.recv().await.unwrap(), irc!["PASS", "oauth:abc"]);
+        assert_eq!(
+            connection.recv_command("JOIN").await.unwrap(),
+            irc!["JOIN", "#pajlada"]
+        );
+        assert_eq!(connection.login().as_deref(), Some("randers"));
+        assert_eq!(connection.token().as_deref(), Some("abc"));
+        assert!(connection.joined_channels().contains("pajlada"));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let mut server = FakeTwitchServer::new();
+        let transport = MockTransport::new(&server.endpoint()).await.unwrap();
+        let (mut incoming, mut outgoing) = transport.split();
+        let mut connection = server.accept().await;
+
+        connection.disconnect();
+        assert!(!connection.is_connected());
+        assert!(incoming.next().await.is_none());
+        assert_eq!(
+            outgoing.send(irc!["PING", "tmi.twitch.tv"]).await,
+            Err(MockTransportError::Disconnected)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refused_and_unknown_server() {
+        let server = FakeTwitchServer::new();
+        server.refuse_connections(true);
+        assert_eq!(
+            MockTransport::new(&server.endpoint()).await.err(),
+            Some(MockTransportError::ConnectionRefused)
+        );
+
+        let endpoint = server.endpoint();
+        drop(server);
+        assert!(matches!(
+            MockTransport::new(&endpoint).await,
+            Err(MockTransportError::NoSuchServer(_))
+        ));
+        assert!(matches!(
+            MockTransport::new(&ServerEndpoint::default()).await,
+            Err(MockTransportError::NoSuchServer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reject_logins() {
+        let mut server = FakeTwitchServer::new();
+        server.reject_logins(true);
+        let transport = MockTransport::new(&server.endpoint()).await.unwrap();
+        let (mut incoming, mut outgoing) = transport.split();
+        let _connection = server.accept().await;
+
+        outgoing.send(irc!["PASS", "oauth:abc"]).await.unwrap();
+        outgoing.send(irc!["NICK", "randers"]).await.unwrap();
+
+        let notice = incoming.next().await.unwrap().unwrap();
+        assert_eq!(notice.command, "NOTICE");
+        assert_eq!(notice.params[1], "Login authentication failed");
+        assert!(incoming.next().await.is_none());
+    }
+}
@@ -1,5 +1,7 @@
 //! Implements the different protocols for connecting to Twitch services.
 
+#[cfg(any(test, feature = "transport-mock"))]
+pub mod mock;
 #[cfg(feature = "transport-tcp")]
 pub mod tcp;
 #[cfg(feature = "transport-ws")]
@@ -9,8 +11,55 @@ use crate::message::{IRCMessage, IRCParseError};
 use async_trait::async_trait;
 use either::Either;
 use futures_util::{sink::Sink, stream::FusedStream};
+use std::borrow::Cow;
 use std::fmt::{Debug, Display};
 
+/// Specifies which server a [`Transport`] should connect to.
+///
+/// All fields are optional. Fields that are left as `None` fall back to the official Twitch
+/// servers, so the [`Default`] value of this struct makes all transports connect to Twitch as
+/// usual. Setting these fields is useful to point the client at a local mock server, a staging
+/// proxy or a bouncer instead.
+///
+/// Each transport only uses the fields relevant to it: The TCP transports use `host`, `port` and
+/// `tls_server_name`, while the WebSocket transports only use `uri`.
+///
+/// # Example
+///
+/// ```
+/// use std::borrow::Cow;
+/// use twitch_irc::ClientConfig;
+/// use twitch_irc::transport::ServerEndpoint;
+///
+/// let mut config = ClientConfig::default();
+/// config.server_endpoint = ServerEndpoint {
+///     host: Some(Cow::Borrowed("127.0.0.1")),
+///     port: Some(6667),
+///     ..ServerEndpoint::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerEndpoint {
+    /// Hostname or IP address to open the TCP connection to.
+    ///
+    /// Defaults to `irc.chat.twitch.tv`.
+    pub host: Option<Cow<'static, str>>,
+    /// Port to open the TCP connection to.
+    ///
+    /// Defaults to `6697` for TLS-secured connections and `6667` for plain-text connections.
+    pub port: Option<u16>,
+    /// The name the server's TLS certificate is verified against. This is also sent to the server
+    /// via SNI.
+    ///
+    /// Defaults to the `host` (or its default value if `host` is not set).
+    pub tls_server_name: Option<Cow<'static, str>>,
+    /// Full URI to connect to when using a WebSocket transport, e.g. `ws://127.0.0.1:8080`.
+    ///
+    /// Defaults to `wss://irc-ws.chat.twitch.tv` for TLS-secured connections and
+    /// `ws://irc-ws.chat.twitch.tv` for plain-text connections.
+    pub uri: Option<Cow<'static, str>>,
+}
+
 /// Abstracts over different ways of connecting to Twitch Chat, which are currently
 /// plain IRC (TCP), and the Twitch-specific WebSocket extension.
 #[async_trait]
@@ -30,9 +79,9 @@ pub trait Transport: Sized + Send + Sync + Debug + 'static {
     /// Type of outgoing messages sink.
     type Outgoing: Sink<IRCMessage, Error = Self::OutgoingError> + Unpin + Send + Sync;
 
-    /// Try to create and connect a new `Transport` of this type. Returns `Ok(Self)` after
-    /// the connection was established successfully.
-    async fn new() -> Result<Self, Self::ConnectError>;
+    /// Try to create and connect a new `Transport` of this type to the given `endpoint`.
+    /// Returns `Ok(Self)` after the connection was established successfully.
+    async fn new(endpoint: &ServerEndpoint) -> Result<Self, Self::ConnectError>;
     /// Split this transport into its incoming and outgoing halves (streams).
     fn split(self) -> (Self::Incoming, Self::Outgoing);
 }
@@ -2,7 +2,7 @@
 
 use crate::message::IRCMessage;
 use crate::message::{AsRawIRC, IRCParseError};
-use crate::transport::Transport;
+use crate::transport::{ServerEndpoint, Transport};
 use async_trait::async_trait;
 use bytes::Bytes;
 use either::Either;
@@ -48,9 +48,28 @@ pub trait MakeConnection: 'static {
     /// What kind of socket this trait implementation creates.
     type Socket: AsyncRead + AsyncWrite + Send + Sync;
 
-    /// Connect to Twitch servers and return the created socket. Depending on the implementation,
+    /// Connect to the server specified by `endpoint` (or the Twitch servers, for any values not
+    /// specified) and return the created socket. Depending on the implementation,
     /// the returned socket is either plain-text or wrapped using a TLS implementation.
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError>;
+    async fn new_socket(
+        endpoint: &ServerEndpoint,
+    ) -> Result<Self::Socket, TCPTransportConnectError>;
+}
+
+fn server_host(endpoint: &ServerEndpoint) -> &str {
+    endpoint.host.as_deref().unwrap_or(TWITCH_SERVER_HOSTNAME)
+}
+
+#[cfg(any(
+    feature = "transport-tcp-native-tls",
+    feature = "transport-tcp-rustls-native-roots",
+    feature = "transport-tcp-rustls-webpki-roots"
+))]
+fn tls_server_name(endpoint: &ServerEndpoint) -> &str {
+    endpoint
+        .tls_server_name
+        .as_deref()
+        .unwrap_or_else(|| server_host(endpoint))
 }
 
 #[cfg(any(
@@ -79,16 +98,18 @@ pub struct TLS;
 impl MakeConnection for TLS {
     type Socket = tokio_native_tls::TlsStream<TcpStream>;
 
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
+    async fn new_socket(
+        endpoint: &ServerEndpoint,
+    ) -> Result<Self::Socket, TCPTransportConnectError> {
         use tokio_native_tls::native_tls;
 
-        let tcp_socket =
-            TcpStream::connect((TWITCH_SERVER_HOSTNAME, TWITCH_SERVER_PORT_TLS)).await?;
+        let port = endpoint.port.unwrap_or(TWITCH_SERVER_PORT_TLS);
+        let tcp_socket = TcpStream::connect((server_host(endpoint), port)).await?;
 
         let cx = native_tls::TlsConnector::new()?;
         let cx = tokio_native_tls::TlsConnector::from(cx);
 
-        Ok(cx.connect(TWITCH_SERVER_HOSTNAME, tcp_socket).await?)
+        Ok(cx.connect(tls_server_name(endpoint), tcp_socket).await?)
     }
 }
 
@@ -100,7 +121,9 @@ impl MakeConnection for TLS {
 impl MakeConnection for TLS {
     type Socket = tokio_rustls::client::TlsStream<TcpStream>;
 
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
+    async fn new_socket(
+        endpoint: &ServerEndpoint,
+    ) -> Result<Self::Socket, TCPTransportConnectError> {
         use std::sync::Arc;
         use tokio_rustls::{TlsConnector, rustls::ClientConfig, rustls::pki_types::ServerName};
 
@@ -124,9 +147,11 @@ impl MakeConnection for TLS {
         };
 
         let connector = TlsConnector::from(Arc::new(config));
-        let domain = ServerName::try_from(TWITCH_SERVER_HOSTNAME).unwrap();
+        let domain = ServerName::try_from(tls_server_name(endpoint).to_owned())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
 
-        let stream = TcpStream::connect((TWITCH_SERVER_HOSTNAME, TWITCH_SERVER_PORT_TLS)).await?;
+        let port = endpoint.port.unwrap_or(TWITCH_SERVER_PORT_TLS);
+        let stream = TcpStream::connect((server_host(endpoint), port)).await?;
         Ok(connector.connect(domain, stream).await?)
     }
 }
@@ -138,8 +163,11 @@ pub struct NoTLS;
 impl MakeConnection for NoTLS {
     type Socket = TcpStream;
 
-    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
-        Ok(TcpStream::connect((TWITCH_SERVER_HOSTNAME, TWITCH_SERVER_PORT_NO_TLS)).await?)
+    async fn new_socket(
+        endpoint: &ServerEndpoint,
+    ) -> Result<Self::Socket, TCPTransportConnectError> {
+        let port = endpoint.port.unwrap_or(TWITCH_SERVER_PORT_NO_TLS);
+        Ok(TcpStream::connect((server_host(endpoint), port)).await?)
     }
 }
 
@@ -172,8 +200,8 @@ impl<C: MakeConnection> Transport for TCPTransport<C> {
     >;
     type Outgoing = Box<dyn Sink<IRCMessage, Error = Self::OutgoingError> + Unpin + Send + Sync>;
 
-    async fn new() -> Result<TCPTransport<C>, TCPTransportConnectError> {
-        let socket = C::new_socket().await?;
+    async fn new(endpoint: &ServerEndpoint) -> Result<TCPTransport<C>, TCPTransportConnectError> {
+        let socket = C::new_socket(endpoint).await?;
         let (read_half, write_half) = tokio::io::split(socket);
 
         // TODO if tokio re-adds stream support revert to:
@@ -2,7 +2,7 @@
 
 use crate::message::IRCMessage;
 use crate::message::{AsRawIRC, IRCParseError};
-use crate::transport::Transport;
+use crate::transport::{ServerEndpoint, Transport};
 use async_trait::async_trait;
 use async_tungstenite::tokio::connect_async;
 use async_tungstenite::tungstenite::Error as WSError;
@@ -36,7 +36,8 @@ compile_error!(
 /// Parameterizes [`WSTransport`] with either the `ws:` or `wss:` URI to connect
 /// either using plain-text or secured by TLS.
 pub trait ConnectionUri: 'static {
-    /// Get what server URI to connect to, according to this implementation.
+    /// Get what server URI to connect to by default, according to this implementation.
+    /// This URI is used if no [`uri`](ServerEndpoint::uri) is set on the [`ServerEndpoint`].
     fn get_server_uri() -> &'static str;
 }
 
@@ -95,8 +96,9 @@ impl<C: ConnectionUri> Transport for WSTransport<C> {
     >;
     type Outgoing = Box<dyn Sink<IRCMessage, Error = Self::OutgoingError> + Unpin + Send + Sync>;
 
-    async fn new() -> Result<WSTransport<C>, Self::ConnectError> {
-        let (ws_stream, _response) = connect_async(C::get_server_uri()).await?;
+    async fn new(endpoint: &ServerEndpoint) -> Result<WSTransport<C>, Self::ConnectError> {
+        let uri = endpoint.uri.as_deref().unwrap_or(C::get_server_uri());
+        let (ws_stream, _response) = connect_async(uri).await?;
 
         let (write_half, read_half) = ws_stream.split();
 